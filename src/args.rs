@@ -8,13 +8,17 @@ const APP_VERSION: &str = "0.1.0";
 
 /// 获取应用启动参数
 /// ```
-/// # jsnap [-d <data_dir>] [-r] <file>
+/// # jsnap [-d <data_dir>] [-r] [--recover] [--heap <heap>] [--validate] [--print-records] <file>
 /// let args = args::get_args();
 /// ```
 pub fn get_args() -> Args {
     let file_arg_name = "file";
     let data_arg_name = "data";
     let force_arg_name = "force";
+    let recover_arg_name = "recover";
+    let heap_arg_name = "heap";
+    let validate_arg_name = "validate";
+    let print_arg_name = "print-records";
     let data_arg = Arg::new(data_arg_name)
         .short('d')
         .long("data")
@@ -24,7 +28,23 @@ pub fn get_args() -> Args {
         .short('r')
         .long("force")
         .action(ArgAction::SetTrue)
-        .help("强制重新分析文件");
+        .help("丢弃已保存的索引，重新分析文件");
+    let recover_arg = Arg::new(recover_arg_name)
+        .long("recover")
+        .action(ArgAction::SetTrue)
+        .help("文件不完整时解析到最后一条完整记录为止");
+    let heap_arg = Arg::new(heap_arg_name)
+        .long("heap")
+        .action(ArgAction::Set)
+        .help("只分析 Android 快照中的指定堆: default、app、image、zygote");
+    let validate_arg = Arg::new(validate_arg_name)
+        .long("validate")
+        .action(ArgAction::SetTrue)
+        .help("校验文件结构并输出报告，不进入分析");
+    let print_arg = Arg::new(print_arg_name)
+        .long("print-records")
+        .action(ArgAction::SetTrue)
+        .help("单遍读取文件并逐条打印记录，用于调试");
     let file_arg = Arg::new(file_arg_name)
         .required(true)
         .help("快照文件")
@@ -38,6 +58,10 @@ pub fn get_args() -> Args {
         .arg_required_else_help(true)
         .arg(data_arg)
         .arg(force_arg)
+        .arg(recover_arg)
+        .arg(heap_arg)
+        .arg(validate_arg)
+        .arg(print_arg)
         .arg(file_arg)
         .get_matches();
 
@@ -55,8 +79,20 @@ pub fn get_args() -> Args {
     // 重新分析
     let force = matches.get_flag(force_arg_name);
 
+    // 截断恢复
+    let recover = matches.get_flag(recover_arg_name);
+
+    // 堆
+    let heap = matches.get_one::<String>(heap_arg_name).map(|heap| heap.to_string());
+
+    // 校验
+    let validate = matches.get_flag(validate_arg_name);
+
+    // 打印记录
+    let print_records = matches.get_flag(print_arg_name);
+
     // 返回
-    Args::new(file, data_dir, force)
+    Args::new(file, data_dir, force, recover, heap, validate, print_records)
 }
 
 /// 启动命令参数
@@ -64,11 +100,16 @@ pub struct Args {
     file: String,
     data_dir: String,
     force: bool,
+    recover: bool,
+    heap: Option<String>,
+    validate: bool,
+    print_records: bool,
 }
 
 impl Args {
-    fn new(file: String, data_dir: String, force: bool) -> Args {
-        Args { file, data_dir, force }
+    fn new(file: String, data_dir: String, force: bool, recover: bool, heap: Option<String>, validate: bool,
+           print_records: bool) -> Args {
+        Args { file, data_dir, force, recover, heap, validate, print_records }
     }
     pub fn get_file(&self) -> &String {
         &self.file
@@ -79,4 +120,16 @@ impl Args {
     pub fn is_force(&self) -> &bool {
         &self.force
     }
+    pub fn is_recover(&self) -> &bool {
+        &self.recover
+    }
+    pub fn get_heap(&self) -> Option<&String> {
+        self.heap.as_ref()
+    }
+    pub fn is_validate(&self) -> &bool {
+        &self.validate
+    }
+    pub fn is_print_records(&self) -> &bool {
+        &self.print_records
+    }
 }
\ No newline at end of file
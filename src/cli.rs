@@ -1,6 +1,5 @@
 use std::collections::HashSet;
 use rustyline::{Config, Context, Editor};
-use rustyline::error::ReadlineError;
 use rustyline::hint::{Hint, Hinter};
 use rustyline::{Completer, Helper, Validator, Highlighter};
 use rustyline::sqlite_history::SQLiteHistory;
@@ -82,6 +81,16 @@ impl JSnapCli {
         let mut hints = HashSet::new();
         hints.insert(CommandHint::new("help", "help"));
         hints.insert(CommandHint::new("exit", "exit"));
+        hints.insert(CommandHint::new("threads", "threads"));
+        hints.insert(CommandHint::new("top [n]", "top"));
+        hints.insert(CommandHint::new("timeline", "timeline"));
+        hints.insert(CommandHint::new("dumps", "dumps"));
+        hints.insert(CommandHint::new("validate", "validate"));
+        hints.insert(CommandHint::new("use <n>", "use"));
+        hints.insert(CommandHint::new("diff <from> <to> [n]", "diff"));
+        hints.insert(CommandHint::new("live-classes", "live-classes"));
+        hints.insert(CommandHint::new("write <file>", "write"));
+        hints.insert(CommandHint::new("refs <id>", "refs"));
         let hinter = JSnapHinter {
             hints
         };
@@ -0,0 +1,173 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom};
+use crate::io::channel::Result;
+use crate::io::source::Source;
+
+/// Default read-ahead size of `BufferedSource`
+pub const DEFAULT_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+/// # Buffered file backend with a large read-ahead
+///
+/// The file position is tracked here, so `position()` needs no system call and
+/// seeks that stay inside the buffer keep it.
+pub struct BufferedSource {
+    file: File,
+    /// file size
+    size: u64,
+    buf: Vec<u8>,
+    /// file offset of `buf[0]`
+    start: u64,
+    /// read position in `buf`
+    pos: usize,
+    /// number of valid bytes in `buf`
+    filled: usize,
+}
+
+impl BufferedSource {
+    pub fn new(file: File, capacity: usize) -> Result<Self> {
+        let size = file.metadata()?.len();
+        Ok(Self {
+            file,
+            size,
+            buf: vec![0; capacity.max(1)],
+            start: 0,
+            pos: 0,
+            filled: 0,
+        })
+    }
+
+    /// Unread bytes in the buffer
+    fn available(&self) -> &[u8] {
+        &self.buf[self.pos..self.filled]
+    }
+
+    /// Read ahead from the file once the buffer is used up
+    fn fill(&mut self) -> Result<()> {
+        if self.pos < self.filled {
+            return Ok(());
+        }
+        self.start += self.filled as u64;
+        self.pos = 0;
+        self.filled = 0;
+        while self.filled < self.buf.len() {
+            match self.file.read(&mut self.buf[self.filled..]) {
+                Ok(0) => break,
+                Ok(count) => self.filled += count,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for BufferedSource {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        // 缓冲区已空且读取量不小于缓冲区时直接读文件
+        if self.pos == self.filled && out.len() >= self.buf.len() {
+            self.start += self.filled as u64;
+            self.pos = 0;
+            self.filled = 0;
+            let count = self.file.read(out)?;
+            self.start += count as u64;
+            return Ok(count);
+        }
+        self.fill()?;
+        let count = out.len().min(self.filled - self.pos);
+        out[..count].copy_from_slice(&self.buf[self.pos..self.pos + count]);
+        self.pos += count;
+        Ok(count)
+    }
+}
+
+impl Source for BufferedSource {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.start + self.pos as u64)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        let end = self.start + self.filled as u64;
+        if position >= self.start && position <= end {
+            // 仍在缓冲区内
+            self.pos = (position - self.start) as usize;
+        } else {
+            self.file.seek(SeekFrom::Start(position))?;
+            self.start = position;
+            self.pos = 0;
+            self.filled = 0;
+        }
+        Ok(position)
+    }
+
+    fn skip(&mut self, len: i64) -> Result<()> {
+        let current = self.start + self.pos as u64;
+        let position = current.checked_add_signed(len)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        self.seek(position)?;
+        Ok(())
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<Cow<'_, [u8]>> {
+        if len > self.available().len() && len <= self.buf.len() {
+            // 把剩余内容移到缓冲区开头，再补满
+            self.buf.copy_within(self.pos..self.filled, 0);
+            self.start += self.pos as u64;
+            self.filled -= self.pos;
+            self.pos = 0;
+            while self.filled < len {
+                match self.file.read(&mut self.buf[self.filled..]) {
+                    Ok(0) => return Err(Error::from(ErrorKind::UnexpectedEof)),
+                    Ok(count) => self.filled += count,
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        if len <= self.available().len() {
+            let pos = self.pos;
+            self.pos += len;
+            return Ok(Cow::Borrowed(&self.buf[pos..pos + len]));
+        }
+        self.check_available(self.start + self.pos as u64, len)?;
+        let mut out = vec![0; len];
+        self.read_exact(&mut out)?;
+        Ok(Cow::Owned(out))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use byteordered::byteorder::{BigEndian, ReadBytesExt};
+    use super::*;
+
+    #[test]
+    fn test_skip_inside_buffer() {
+        let path = std::env::temp_dir().join(format!("jsnap-buffered-{}.bin", std::process::id()));
+        let data: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        fs::write(&path, &data).unwrap();
+        let mut source = BufferedSource::new(File::open(&path).unwrap(), 64).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(source.read_u8().unwrap(), 0);
+        source.skip(10).unwrap();
+        assert_eq!(source.position().unwrap(), 11);
+        assert_eq!(source.read_u8().unwrap(), 11);
+        // 向后移动，仍在缓冲区内
+        source.skip(-12).unwrap();
+        assert_eq!(source.read_u16::<BigEndian>().unwrap(), 0x0001);
+        // 跨越缓冲区边界
+        source.seek(62).unwrap();
+        assert_eq!(source.read_u32::<BigEndian>().unwrap(), 0x3e3f4041);
+        assert_eq!(&*source.read_slice(3).unwrap(), &[66, 67, 68]);
+        source.skip(500).unwrap();
+        assert_eq!(source.position().unwrap(), 569);
+        assert_eq!(source.read_u8().unwrap(), data[569]);
+        assert!(source.skip(-1000).is_err());
+    }
+}
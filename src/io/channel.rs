@@ -1,11 +1,14 @@
-/*!
-
- */
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::{BufReader, Cursor, Error, ErrorKind, Read, Seek, SeekFrom};
-use std::path::PathBuf;
+use std::io::{Error, Read};
+use std::path::Path;
 use std::result;
 use byteordered::byteorder::{BigEndian, ReadBytesExt};
+use crate::io::buffered::{BufferedSource, DEFAULT_BUFFER_SIZE};
+use crate::io::file::FileSource;
+use crate::io::gzip::{self, is_gzip, GzipSource};
+use crate::io::mmap::MmapSource;
+use crate::io::source::Source;
 
 pub type Result<T> = result::Result<T, Error>;
 /// The byte type in the heap dump file
@@ -20,88 +23,190 @@ pub type Long = u64;
 pub type Float = f32;
 /// The double type in the heap dump file
 pub type Double = f64;
-/// The char type in the heap dump file
-pub type Char = char;
+/// The char type in the heap dump file, a UTF-16 code unit
+pub type Char = u16;
 /// The boolean type in the heap dump file
 pub type Boolean = bool;
 
+/// # How a channel reads the snapshot file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// Plain unbuffered file reads
+    File,
+    /// Buffered file reads with the given read-ahead size in bytes
+    Buffered(usize),
+    /// Memory-mapped file, falls back to `Buffered` when the file cannot be mapped
+    #[default]
+    Mmap,
+}
+
 /// # Channel used to read snapshot files
 pub struct Channel {
-    /// a snapshot file
-    file: File,
+    /// the backend reading the snapshot file
+    source: Box<dyn Source>,
 }
 
 impl Channel {
-    /// Open a file to build a channel
+    /// Open a file to build a channel with the default backend
     /// # Examples
-    /// ```rust
-    /// use io::channel::{Channel, Result};
+    /// ```rust,no_run
+    /// use std::path::Path;
+    /// use jsnap::io::channel::Channel;
     ///
-    /// let file_path = ...
-    /// let channel = Channel::open(&file_path);
+    /// let mut channel = Channel::open(Path::new("heap.hprof"))?;
+    /// let byte = channel.read_byte()?;
+    /// # Ok::<(), std::io::Error>(())
     /// ```
-    pub fn open(file_path: &PathBuf) -> Result<Self> {
-        let file = File::open(file_path.clone())?;
-        Ok(Self { file })
+    pub fn open(file_path: &Path) -> Result<Self> {
+        Self::open_with(file_path, Backend::default())
     }
 
-    fn read(&mut self, len: usize) -> Result<Vec<u8>> {
-        let mut buf: Vec<u8> = vec![Default::default(); len];
-        let count: usize = self.file.read(&mut buf)?;
-        if count == 0 || buf.is_empty() {
-            return Err(Error::from(ErrorKind::UnexpectedEof));
+    /// Open a file to build a channel with the given backend
+    ///
+    /// Gzip-compressed files are detected by their magic and decompressed transparently,
+    /// the backend does not apply to them.
+    pub fn open_with(file_path: &Path, backend: Backend) -> Result<Self> {
+        let mut file = File::open(file_path)?;
+        if is_gzip(&mut file)? {
+            return Ok(Self { source: Box::new(GzipSource::new(file_path, file)?) });
         }
-        Ok(buf)
+        let source: Box<dyn Source> = match backend {
+            Backend::File => Box::new(FileSource::new(file)?),
+            Backend::Buffered(capacity) => Box::new(BufferedSource::new(file, capacity)?),
+            Backend::Mmap => match MmapSource::new(&file) {
+                Ok(source) => Box::new(source),
+                Err(_) => Box::new(BufferedSource::new(file, DEFAULT_BUFFER_SIZE)?),
+            },
+        };
+        Ok(Self { source })
+    }
+
+    /// Prepare random access to a gzip-compressed file with the seek index saved at `index_path`
+    ///
+    /// Channels opened on the file afterwards reuse the index instead of decompressing the
+    /// whole file to build it. Returns `true` when the index was rebuilt and should be saved
+    /// with `save_seek_index`, files that are not compressed need nothing.
+    pub fn load_seek_index(file_path: &Path, index_path: &Path) -> Result<bool> {
+        gzip::load_index(file_path, index_path)
     }
 
-    pub fn skip(&mut self, len: i64) {
-        let _ = self.file.seek(SeekFrom::Current(len));
+    /// Save the seek index `load_seek_index` rebuilt
+    pub fn save_seek_index(file_path: &Path, index_path: &Path) -> Result<()> {
+        gzip::save_index(file_path, index_path)
+    }
+
+    pub fn skip(&mut self, len: i64) -> Result<()> {
+        self.source.skip(len)
     }
 
     pub fn read_byte(&mut self) -> Result<Byte> {
-        self.file.read_u8()
+        self.source.read_u8()
     }
 
     pub fn read_bool(&mut self) -> Result<Boolean> {
-        let val = self.file.read_u8()?;
+        let val = self.source.read_u8()?;
         Ok(val != 0)
     }
 
     pub fn read_short(&mut self) -> Result<Short> {
-        self.file.read_u16::<BigEndian>()
+        self.source.read_u16::<BigEndian>()
     }
 
     pub fn read_int(&mut self) -> Result<Int> {
-        self.file.read_u32::<BigEndian>()
+        self.source.read_u32::<BigEndian>()
     }
 
     pub fn read_long(&mut self) -> Result<Long> {
-        self.file.read_u64::<BigEndian>()
+        self.source.read_u64::<BigEndian>()
     }
 
     pub fn read_float(&mut self) -> Result<Float> {
-        self.file.read_f32::<BigEndian>()
+        self.source.read_f32::<BigEndian>()
     }
 
     pub fn read_double(&mut self) -> Result<Double> {
-        self.file.read_f64::<BigEndian>()
+        self.source.read_f64::<BigEndian>()
+    }
+
+    /// Read `len` bytes, fails with `UnexpectedEof` without allocating when the file is shorter
+    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<Byte>> {
+        let position = self.source.position()?;
+        self.source.check_available(position, len)?;
+        let mut buf: Vec<u8> = vec![0; len];
+        self.source.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Read `len` bytes without copying when the backend supports it
+    pub fn read_slice(&mut self, len: usize) -> Result<Cow<'_, [Byte]>> {
+        self.source.read_slice(len)
     }
 
+    /// Read `len` bytes at `offset`, the current position is unchanged
+    pub fn slice_at(&mut self, offset: u64, len: usize) -> Result<Cow<'_, [Byte]>> {
+        self.source.slice_at(offset, len)
+    }
+
+    /// Read a Java char (2 bytes, UTF-16 big-endian)
     pub fn read_char(&mut self) -> Result<Char> {
-        let str = self.read_str(1)?;
-        let byte = str.chars().next().unwrap();
-        Ok(byte)
+        self.source.read_u16::<BigEndian>()
+    }
+
+    /// Read a null-terminated ASCII string of at most `max_len` bytes
+    pub fn read_cstr(&mut self, max_len: usize) -> Result<String> {
+        let mut buf: Vec<u8> = Vec::new();
+        while buf.len() < max_len {
+            let byte = self.source.read_u8()?;
+            if byte == 0 {
+                break;
+            }
+            buf.push(byte);
+        }
+        Ok(String::from_utf8_lossy(&buf).to_string())
     }
 
     pub fn read_str(&mut self, len: usize) -> Result<String> {
-        let buf: Vec<u8> = self.read(len)?;
+        let buf = self.source.read_slice(len)?;
         let result = String::from_utf8_lossy(&buf);
         Ok(result.to_string())
     }
 
+    /// The size of the file
+    pub fn size(&self) -> Result<u64> {
+        Ok(self.source.size())
+    }
+
+    /// Move to an absolute position in the file
+    pub fn seek(&mut self, position: u64) -> Result<u64> {
+        self.source.seek(position)
+    }
+
     /// The current position where the file is being read
     pub fn position(&mut self) -> Result<u64> {
-        self.file.seek(SeekFrom::Current(0))
+        self.source.position()
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::ErrorKind;
+    use super::*;
+
+    #[test]
+    fn test_length_beyond_file() {
+        let path = std::env::temp_dir().join(format!("jsnap-channel-{}.bin", std::process::id()));
+        fs::write(&path, [1, 2, 3, 4]).unwrap();
+        for backend in [Backend::File, Backend::Buffered(2), Backend::Mmap] {
+            let mut channel = Channel::open_with(&path, backend).unwrap();
+            channel.read_byte().unwrap();
+            // 损坏文件中的长度不应导致按该长度分配内存
+            let err = channel.read_bytes(usize::MAX / 2).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof, "{:?}", backend);
+            assert_eq!(channel.read_bytes(3).unwrap(), [2, 3, 4], "{:?}", backend);
+            assert_eq!(channel.slice_at(1, usize::MAX).unwrap_err().kind(), ErrorKind::UnexpectedEof);
+        }
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use crate::io::channel::Result;
+use crate::io::source::Source;
+
+/// # Unbuffered file backend, every read is a system call
+pub struct FileSource {
+    /// a snapshot file
+    file: File,
+    /// file size
+    size: u64,
+}
+
+impl FileSource {
+    pub fn new(file: File) -> Result<Self> {
+        let size = file.metadata()?.len();
+        Ok(Self { file, size })
+    }
+}
+
+impl Read for FileSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Source for FileSource {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn position(&mut self) -> Result<u64> {
+        self.file.stream_position()
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        self.file.seek(SeekFrom::Start(position))
+    }
+
+    fn skip(&mut self, len: i64) -> Result<()> {
+        self.file.seek(SeekFrom::Current(len))?;
+        Ok(())
+    }
+}
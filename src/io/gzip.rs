@@ -0,0 +1,672 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::UNIX_EPOCH;
+use miniz_oxide::inflate::core::{decompress, inflate_flags, DecompressorOxide, TINFL_LZ_DICT_SIZE};
+use miniz_oxide::inflate::TINFLStatus;
+use crate::io::channel::Result;
+use crate::io::source::Source;
+
+/// The two magic bytes every gzip member starts with
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// The only compression method gzip defines, deflate
+const GZIP_DEFLATE: u8 = 8;
+/// Member header flags
+const FLAG_HCRC: u8 = 0x02;
+const FLAG_EXTRA: u8 = 0x04;
+const FLAG_NAME: u8 = 0x08;
+const FLAG_COMMENT: u8 = 0x10;
+/// CRC32 and size following the deflate data of a member
+const GZIP_TRAILER_LEN: u64 = 8;
+/// Read buffer size for the compressed and the decompressed side
+const GZIP_BUFFER_SIZE: usize = 64 * 1024;
+/// Decompressed distance between two seek points inside a member
+///
+/// Every point keeps the 32 KiB window and the decompressor state, about 43 KiB,
+/// a random access decompresses at most this many bytes before reaching its target.
+const SEEK_POINT_SPACING: u64 = 32 * 1024 * 1024;
+/// Seek indexes kept for channels opened later on the same file
+const MAX_CACHED_INDEXES: usize = 4;
+/// Comment the JDK writes in the header of the first member of a `-gz` heap dump
+const JDK_BLOCK_COMMENT: &[u8] = b"HPROF BLOCKSIZE=";
+/// Longest first member comment accepted while looking for `JDK_BLOCK_COMMENT`
+const MAX_COMMENT_LEN: usize = 128;
+/// Bytes checked at a member start found by scanning, the header and the start of its comment
+const MEMBER_PROBE_LEN: usize = 10 + JDK_BLOCK_COMMENT.len();
+
+/// Check whether the file is gzip-compressed, the position is restored afterwards
+pub fn is_gzip(file: &mut File) -> Result<bool> {
+    let mut magic = [0; 2];
+    let result = file.read_exact(&mut magic);
+    file.seek(SeekFrom::Start(0))?;
+    match result {
+        Ok(()) => Ok(magic == GZIP_MAGIC),
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// # State of a deflate stream between two reads
+///
+/// The decompressor uses the window as a ring buffer of its last 32 KiB of output,
+/// together they are all that is needed to continue from the same input offset.
+#[derive(Clone)]
+struct Stream {
+    state: Box<DecompressorOxide>,
+    window: Box<[u8]>,
+    /// where the next output is written in `window`
+    window_pos: usize,
+}
+
+impl Stream {
+    fn new() -> Self {
+        Stream { state: Box::default(), window: vec![0; TINFL_LZ_DICT_SIZE].into_boxed_slice(), window_pos: 0 }
+    }
+}
+
+/// # A position decompression can restart from
+///
+/// Points at member starts need no stream state, `jcmd GC.heap_dump -gz=N` compresses
+/// every chunk as its own member so those files get them for free. Inside a member,
+/// such as a plain `gzip` file with a single member, the stream state is kept.
+struct SeekPoint {
+    /// offset of the next input byte in the compressed file
+    compressed: u64,
+    /// offset of the next output byte in the decompressed stream
+    position: u64,
+    /// `None` at the start of a member
+    stream: Option<Stream>,
+}
+
+/// # Seek points of a gzip file, used for random access
+struct SeekIndex {
+    points: Vec<SeekPoint>,
+    /// decompressed size, up to where a truncated file could be decompressed
+    size: u64,
+}
+
+impl SeekIndex {
+    /// Find the seek points of a file, from the member headers when it is a JDK `-gz` dump
+    fn build(file: &File, spacing: u64) -> Result<Self> {
+        match Self::scan_members(file)? {
+            Some(index) => Ok(index),
+            None => Self::inflate(file, spacing),
+        }
+    }
+
+    /// Find the member starts of a JDK `-gz` heap dump without decompressing it
+    ///
+    /// The JDK compresses every block of the dump as its own member, the first one names the
+    /// block size in its comment. A member starts right after the trailer of the previous one,
+    /// whose last 4 bytes are that member's decompressed size, and its header has a zero
+    /// modification time, so the members are found by these bytes alone. Only the last member
+    /// is decompressed, to learn where a possibly truncated file ends.
+    /// `None` when the file was not written by the JDK.
+    fn scan_members(file: &File) -> Result<Option<Self>> {
+        let mut input = BufReader::with_capacity(GZIP_BUFFER_SIZE, file.try_clone()?);
+        input.seek(SeekFrom::Start(0))?;
+        let Some(block_size) = read_block_size(&mut input)? else { return Ok(None) };
+
+        let start = SeekPoint { compressed: 0, position: 0, stream: None };
+        let mut points = vec![start];
+        let mut position = 0;
+        let mut buf = Vec::with_capacity(GZIP_BUFFER_SIZE + MEMBER_PROBE_LEN);
+        // `buf[0]` 在文件中的偏移，跳过第一个 member 的文件头
+        let mut buf_start = input.stream_position()?;
+        loop {
+            let len = buf.len();
+            buf.resize(len + GZIP_BUFFER_SIZE, 0);
+            let count = loop {
+                match input.read(&mut buf[len..]) {
+                    Ok(count) => break count,
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(err) => return Err(err),
+                }
+            };
+            buf.truncate(len + count);
+            // 前 4 个字节为上一个 member 的解压大小
+            let end = buf.len().saturating_sub(MEMBER_PROBE_LEN);
+            let mut at = 4;
+            while at <= end {
+                if let Some(size) = member_start(&buf[at - 4..at + MEMBER_PROBE_LEN], block_size) {
+                    position += size;
+                    points.push(SeekPoint { compressed: buf_start + at as u64, position, stream: None });
+                    at += MEMBER_PROBE_LEN;
+                } else {
+                    at += 1;
+                }
+            }
+            if count == 0 {
+                break;
+            }
+            let keep = at.min(buf.len()).saturating_sub(4);
+            buf.drain(..keep);
+            buf_start += keep as u64;
+        }
+
+        let mut inflater = Inflater::resume(file, points.last().unwrap())?;
+        let mut buf = vec![0; GZIP_BUFFER_SIZE];
+        loop {
+            match inflater.read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Some(Self { points, size: inflater.position }))
+    }
+
+    /// Decompress the whole file once, keeping a seek point at every member start
+    /// and every `spacing` decompressed bytes
+    fn inflate(file: &File, spacing: u64) -> Result<Self> {
+        let start = SeekPoint { compressed: 0, position: 0, stream: None };
+        let mut inflater = Inflater::resume(file, &start)?;
+        let mut points = vec![start];
+        let mut buf = vec![0; GZIP_BUFFER_SIZE];
+        loop {
+            // 输出已全部取走时才能保存状态
+            let last = points.last().unwrap().position;
+            let due = inflater.at_member_start() || inflater.position - last >= spacing;
+            if inflater.available == 0 && inflater.position > last && due {
+                points.push(inflater.seek_point());
+            }
+            match inflater.read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                // 文件被截断，保留已解压的部分
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(Self { points, size: inflater.position })
+    }
+
+    /// The last seek point at or before `position`
+    fn point_before(&self, position: u64) -> &SeekPoint {
+        let index = self.points.partition_point(|point| point.position <= position).saturating_sub(1);
+        &self.points[index]
+    }
+
+    /// Load the index of a file, the most recently used ones are shared by every channel on them
+    fn load(file_path: &Path, file: &File) -> Result<Arc<Self>> {
+        let key = CacheKey::of(file_path, file)?;
+        if let Some(index) = cached(&key) {
+            return Ok(index);
+        }
+        let index = Arc::new(Self::build(file, SEEK_POINT_SPACING)?);
+        cache(key, index.clone());
+        Ok(index)
+    }
+
+    /// Only seek points at member starts can be saved, the decompressor state has no file format
+    fn is_savable(&self) -> bool {
+        self.points.iter().all(|point| point.stream.is_none())
+    }
+
+    /// Parse an index written by `to_text`, `None` when it belongs to another file
+    fn parse(text: &str, key: &CacheKey) -> Option<Self> {
+        let mut fields = HashMap::new();
+        let mut points = Vec::new();
+        for (name, value) in text.lines().filter_map(|line| line.split_once('=')) {
+            if name == "point" {
+                let (compressed, position) = value.split_once(',')?;
+                points.push(SeekPoint { compressed: compressed.parse().ok()?, position: position.parse().ok()?, stream: None });
+            } else {
+                fields.insert(name, value);
+            }
+        }
+        let matches = fields.get("size")?.parse::<u64>().ok()? == key.size
+            && fields.get("mtime")?.parse::<u128>().ok()? == key.mtime;
+        if !matches || points.first().is_none_or(|point| point.position != 0) {
+            return None;
+        }
+        Some(Self { points, size: fields.get("length")?.parse().ok()? })
+    }
+
+    fn to_text(&self, key: &CacheKey) -> String {
+        let mut text = format!("size={}\nmtime={}\nlength={}\n", key.size, key.mtime, self.size);
+        for point in &self.points {
+            text.push_str(&format!("point={},{}\n", point.compressed, point.position));
+        }
+        text
+    }
+}
+
+/// # Identity of a gzip file whose seek index is cached or saved
+#[derive(Clone, PartialEq, Eq)]
+struct CacheKey {
+    path: PathBuf,
+    size: u64,
+    /// modification time in nanoseconds since the UNIX epoch
+    mtime: u128,
+}
+
+impl CacheKey {
+    fn of(file_path: &Path, file: &File) -> Result<Self> {
+        let metadata = file.metadata()?;
+        let mtime = metadata.modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_nanos());
+        Ok(CacheKey { path: file_path.to_path_buf(), size: metadata.len(), mtime })
+    }
+}
+
+/// 最近使用的在末尾
+type Cache = Vec<(CacheKey, Arc<SeekIndex>)>;
+
+fn indexes() -> &'static Mutex<Cache> {
+    static INDEXES: OnceLock<Mutex<Cache>> = OnceLock::new();
+    INDEXES.get_or_init(Default::default)
+}
+
+fn cached(key: &CacheKey) -> Option<Arc<SeekIndex>> {
+    let mut cached = indexes().lock().unwrap();
+    let found = cached.iter().position(|(cached, _)| cached == key)?;
+    let entry = cached.remove(found);
+    let index = entry.1.clone();
+    cached.push(entry);
+    Some(index)
+}
+
+fn cache(key: CacheKey, index: Arc<SeekIndex>) {
+    let mut cached = indexes().lock().unwrap();
+    cached.retain(|(cached, _)| *cached != key);
+    if cached.len() >= MAX_CACHED_INDEXES {
+        cached.remove(0);
+    }
+    cached.push((key, index));
+}
+
+/// Prepare random access to a gzip file through its seek index saved at `index_path`
+///
+/// A saved index that is missing or belongs to another version of the file is rebuilt,
+/// channels opened on the file afterwards share it. Returns `true` when it should be
+/// saved again with `save_index`. Files that are not gzip-compressed are left alone.
+pub fn load_index(file_path: &Path, index_path: &Path) -> Result<bool> {
+    let mut file = File::open(file_path)?;
+    if !is_gzip(&mut file)? {
+        return Ok(false);
+    }
+    let key = CacheKey::of(file_path, &file)?;
+    let saved = fs::read_to_string(index_path).ok().and_then(|text| SeekIndex::parse(&text, &key));
+    if let Some(index) = saved {
+        cache(key, Arc::new(index));
+        return Ok(false);
+    }
+    let index = SeekIndex::load(file_path, &file)?;
+    Ok(index.is_savable())
+}
+
+/// Save the seek index `load_index` prepared for a gzip file
+pub fn save_index(file_path: &Path, index_path: &Path) -> Result<()> {
+    let file = File::open(file_path)?;
+    let key = CacheKey::of(file_path, &file)?;
+    match cached(&key) {
+        Some(index) if index.is_savable() => fs::write(index_path, index.to_text(&key)),
+        _ => Ok(()),
+    }
+}
+
+/// Read the block size from the first member header of a JDK `-gz` heap dump
+fn read_block_size(input: &mut impl BufRead) -> Result<Option<u64>> {
+    let mut header = [0; 10];
+    match input.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    if header[..3] != [GZIP_MAGIC[0], GZIP_MAGIC[1], GZIP_DEFLATE] || header[3] != FLAG_COMMENT {
+        return Ok(None);
+    }
+    let mut comment = Vec::new();
+    input.take(MAX_COMMENT_LEN as u64).read_until(0, &mut comment)?;
+    let Some(size) = comment.strip_suffix(&[0]).and_then(|comment| comment.strip_prefix(JDK_BLOCK_COMMENT)) else {
+        return Ok(None);
+    };
+    Ok(std::str::from_utf8(size).ok().and_then(|size| size.parse().ok()).filter(|size| *size > 0))
+}
+
+/// The decompressed size of the previous member when `probe` holds its size followed by
+/// the header of a member the JDK wrote
+fn member_start(probe: &[u8], block_size: u64) -> Option<u64> {
+    let (size, header) = probe.split_at(4);
+    if header[..3] != [GZIP_MAGIC[0], GZIP_MAGIC[1], GZIP_DEFLATE] || header[4..8] != [0; 4] {
+        return None;
+    }
+    // 并行生成的堆转储由多个文件拼接而成，每个文件的第一个 member 都带有块大小注释
+    let is_header = match header[3] {
+        0 => true,
+        FLAG_COMMENT => header[10..] == *JDK_BLOCK_COMMENT,
+        _ => false,
+    };
+    let size = u32::from_le_bytes(size.try_into().unwrap()) as u64;
+    (is_header && size > 0 && size <= block_size).then_some(size)
+}
+
+/// # A decompressor over the members of a gzip file
+struct Inflater {
+    input: BufReader<File>,
+    /// offset of the next input byte in the compressed file
+    compressed: u64,
+    /// decompressed position of the next byte `read` yields
+    position: u64,
+    /// `None` between members
+    stream: Option<Stream>,
+    /// the member of `stream` has ended, only its remaining output is left
+    member_end: bool,
+    /// output in the window not yet yielded, starting at `window_pos`
+    available: usize,
+}
+
+impl Inflater {
+    /// Start decompressing at a seek point
+    fn resume(file: &File, point: &SeekPoint) -> Result<Self> {
+        let mut input = BufReader::with_capacity(GZIP_BUFFER_SIZE, file.try_clone()?);
+        input.seek(SeekFrom::Start(point.compressed))?;
+        let stream = point.stream.clone();
+        Ok(Self { input, compressed: point.compressed, position: point.position, stream, member_end: false, available: 0 })
+    }
+
+    /// The next input is a member header or the end of the file
+    fn at_member_start(&self) -> bool {
+        self.stream.is_none() || self.member_end
+    }
+
+    /// The current state as a seek point, all output must have been yielded
+    fn seek_point(&self) -> SeekPoint {
+        let stream = if self.member_end { None } else { self.stream.clone() };
+        SeekPoint { compressed: self.compressed, position: self.position, stream }
+    }
+
+    /// Decompress more output into the window, `false` at the end of the file
+    fn fill(&mut self) -> Result<bool> {
+        loop {
+            if self.member_end {
+                self.stream = None;
+                self.member_end = false;
+            }
+            if self.stream.is_none() {
+                if !self.read_member_header()? {
+                    return Ok(false);
+                }
+                self.stream = Some(Stream::new());
+            }
+            let stream = self.stream.as_mut().unwrap();
+            let input = self.input.fill_buf()?;
+            let flags = if input.is_empty() { 0 } else { inflate_flags::TINFL_FLAG_HAS_MORE_INPUT };
+            let (status, consumed, produced) = decompress(&mut stream.state, input, &mut stream.window, stream.window_pos, flags);
+            let exhausted = input.is_empty();
+            self.input.consume(consumed);
+            self.compressed += consumed as u64;
+            self.available = produced;
+            match status {
+                TINFLStatus::Done => {
+                    // 下一个 member 从新的窗口开始，本次的输出取走之后再切换
+                    self.skip_input(GZIP_TRAILER_LEN)?;
+                    self.member_end = true;
+                }
+                TINFLStatus::NeedsMoreInput | TINFLStatus::HasMoreOutput if !exhausted || produced > 0 => {}
+                // 先交出已解压的内容，下次调用再报告错误
+                _ if produced > 0 => {}
+                TINFLStatus::NeedsMoreInput | TINFLStatus::HasMoreOutput | TINFLStatus::FailedCannotMakeProgress => {
+                    return Err(Error::from(ErrorKind::UnexpectedEof));
+                }
+                _ => return Err(Error::new(ErrorKind::InvalidData, format!("invalid deflate data: {:?}", status))),
+            }
+            if produced > 0 {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Parse the header of the next member, `false` at the end of the file
+    fn read_member_header(&mut self) -> Result<bool> {
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+        let mut header = [0; 10];
+        self.read_input(&mut header)?;
+        if header[..2] != GZIP_MAGIC || header[2] != GZIP_DEFLATE {
+            return Err(Error::new(ErrorKind::InvalidData, format!("invalid gzip member at {}", self.compressed - 10)));
+        }
+        let flags = header[3];
+        if flags & FLAG_EXTRA != 0 {
+            let mut len = [0; 2];
+            self.read_input(&mut len)?;
+            self.skip_input(u16::from_le_bytes(len) as u64)?;
+        }
+        for flag in [FLAG_NAME, FLAG_COMMENT] {
+            if flags & flag != 0 {
+                let mut byte = [1];
+                while byte[0] != 0 {
+                    self.read_input(&mut byte)?;
+                }
+            }
+        }
+        if flags & FLAG_HCRC != 0 {
+            self.skip_input(2)?;
+        }
+        Ok(true)
+    }
+
+    fn read_input(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.input.read_exact(buf)?;
+        self.compressed += buf.len() as u64;
+        Ok(())
+    }
+
+    fn skip_input(&mut self, len: u64) -> Result<()> {
+        let skipped = io::copy(&mut (&mut self.input).take(len), &mut io::sink())?;
+        self.compressed += skipped;
+        if skipped < len {
+            return Err(Error::from(ErrorKind::UnexpectedEof));
+        }
+        Ok(())
+    }
+}
+
+impl Read for Inflater {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.available == 0 && !self.fill()? {
+            return Ok(0);
+        }
+        let stream = self.stream.as_mut().unwrap();
+        let count = self.available.min(buf.len());
+        buf[..count].copy_from_slice(&stream.window[stream.window_pos..stream.window_pos + count]);
+        stream.window_pos = (stream.window_pos + count) & (TINFL_LZ_DICT_SIZE - 1);
+        self.available -= count;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+/// # Gzip-compressed snapshot file, decompressed transparently
+///
+/// Seeking forward decompresses up to the target, seeking backward or far ahead restarts
+/// from the nearest seek point before the target, so both JDK chunked dumps and plain
+/// single-member files are random accessible.
+pub struct GzipSource {
+    file: File,
+    index: Arc<SeekIndex>,
+    inflater: Option<Inflater>,
+    /// decompressed read position, may be past the end
+    position: u64,
+}
+
+impl GzipSource {
+    pub fn new(file_path: &Path, file: File) -> Result<Self> {
+        let index = SeekIndex::load(file_path, &file)?;
+        Ok(Self { file, index, inflater: None, position: 0 })
+    }
+
+    /// Move the decompressor to the read position
+    fn sync(&mut self) -> Result<&mut Inflater> {
+        let target = self.position;
+        let point = self.index.point_before(target);
+        let restart = match &self.inflater {
+            Some(inflater) => inflater.position > target || inflater.position < point.position,
+            None => true,
+        };
+        if restart {
+            self.inflater = Some(Inflater::resume(&self.file, point)?);
+        }
+        let inflater = self.inflater.as_mut().unwrap();
+        if inflater.position < target {
+            io::copy(&mut inflater.take(target - inflater.position), &mut io::sink())?;
+        }
+        Ok(inflater)
+    }
+}
+
+impl Read for GzipSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.index.size.saturating_sub(self.position);
+        if remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let len = buf.len().min(remaining.min(usize::MAX as u64) as usize);
+        let inflater = self.sync()?;
+        let count = inflater.read(&mut buf[..len])?;
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Source for GzipSource {
+    fn size(&self) -> u64 {
+        self.index.size
+    }
+
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.position)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        self.position = position;
+        Ok(position)
+    }
+
+    fn skip(&mut self, len: i64) -> Result<()> {
+        self.position = self.position.checked_add_signed(len)
+            .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use flate2::{Compression, GzBuilder};
+    use flate2::write::GzEncoder;
+    use crate::io::channel::Channel;
+    use super::*;
+
+    #[test]
+    fn test_chunked_random_access() {
+        let path = std::env::temp_dir().join(format!("jsnap-gzip-{}.gz", std::process::id()));
+        let data: Vec<u8> = (0..3000u32).map(|i| (i * 7 % 251) as u8).collect();
+        // 与 JDK 一样，每 1000 字节压缩为一个独立的 member
+        let mut compressed = Vec::new();
+        for chunk in data.chunks(1000) {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(chunk).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+        fs::write(&path, &compressed).unwrap();
+        let mut file = File::open(&path).unwrap();
+        assert!(is_gzip(&mut file).unwrap());
+        let mut source = GzipSource::new(&path, file).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(source.size(), 3000);
+        // 每个 member 开头及文件末尾各一个恢复点
+        let points: Vec<u64> = source.index.points.iter().map(|point| point.position).collect();
+        assert_eq!(points, [0, 1000, 2000, 3000]);
+        assert!(source.index.points.iter().all(|point| point.stream.is_none()));
+        assert_eq!(&*source.slice_at(2500, 4).unwrap(), &data[2500..2504]);
+        assert_eq!(&*source.slice_at(10, 4).unwrap(), &data[10..14]);
+        source.seek(995).unwrap();
+        assert_eq!(&*source.read_slice(10).unwrap(), &data[995..1005]);
+        source.skip(-1005).unwrap();
+        let mut all = Vec::new();
+        source.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+    }
+
+    #[test]
+    fn test_jdk_members_from_headers() {
+        let dir = std::env::temp_dir().join(format!("jsnap-gzip-jdk-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("heap.hprof.gz");
+        let index_path = dir.join("seek-index");
+        let data: Vec<u8> = (0..3500u32).map(|i| (i * 13 % 241) as u8).collect();
+        // 与 JDK 的并行堆转储一样由两个文件拼接而成，各自的第一个 member 带有块大小注释
+        let mut compressed = Vec::new();
+        let mut starts = Vec::new();
+        for (index, chunk) in data.chunks(1000).enumerate() {
+            starts.push(compressed.len());
+            let mut encoder = match index {
+                0 | 2 => GzBuilder::new().comment("HPROF BLOCKSIZE=1000").write(Vec::new(), Compression::default()),
+                _ => GzEncoder::new(Vec::new(), Compression::default()),
+            };
+            encoder.write_all(chunk).unwrap();
+            compressed.extend(encoder.finish().unwrap());
+        }
+        // 第二个 member 的数据无法解压，找到各 member 时不应解压它
+        compressed[starts[1] + 10] = 0xff;
+        fs::write(&path, &compressed).unwrap();
+
+        let first = Channel::load_seek_index(&path, &index_path).unwrap();
+        Channel::save_seek_index(&path, &index_path).unwrap();
+        let saved = fs::read_to_string(&index_path).unwrap();
+        let reloaded = Channel::load_seek_index(&path, &index_path).unwrap();
+        let file = File::open(&path).unwrap();
+        let mut source = GzipSource::new(&path, file).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(first && !reloaded);
+        assert_eq!(source.size(), 3500);
+        let points: Vec<(u64, u64)> = source.index.points.iter().map(|point| (point.compressed, point.position)).collect();
+        let expected: Vec<(u64, u64)> = starts.iter().zip([0, 1000, 2000, 3000]).map(|(start, position)| (*start as u64, position)).collect();
+        assert_eq!(points, expected);
+        assert_eq!(saved.matches("point=").count(), 4);
+        assert_eq!(&*source.slice_at(3400, 4).unwrap(), &data[3400..3404]);
+        assert_eq!(&*source.slice_at(500, 4).unwrap(), &data[500..504]);
+        assert!(source.slice_at(1500, 4).is_err());
+    }
+
+    #[test]
+    fn test_single_member_seek_points() {
+        let path = std::env::temp_dir().join(format!("jsnap-gzip-single-{}.gz", std::process::id()));
+        // 有重复内容才会产生回溯引用，恢复时依赖保存的窗口
+        let data: Vec<u8> = (0..400_000u32).map(|i| ((i / 3) ^ (i % 1021)) as u8).collect();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        fs::write(&path, encoder.finish().unwrap()).unwrap();
+        let file = File::open(&path).unwrap();
+        let index = SeekIndex::build(&file, 50_000).unwrap();
+        let mut source = GzipSource { file, index: Arc::new(index), inflater: None, position: 0 };
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(source.size(), data.len() as u64);
+        assert!(source.index.points.len() >= 8);
+        assert!(source.index.points[1..].iter().all(|point| point.stream.is_some() || point.position == source.size()));
+        // 向后及大跨度向前的访问从 member 中间的恢复点继续解压
+        for offset in [390_000u64, 120_000, 120_003, 250_000, 7, 399_990] {
+            let start = offset as usize;
+            assert_eq!(&*source.slice_at(offset, 10).unwrap(), &data[start..start + 10]);
+            assert_eq!(source.index.point_before(offset).stream.is_some(), offset >= 50_000);
+        }
+        source.seek(0).unwrap();
+        let mut all = Vec::new();
+        source.read_to_end(&mut all).unwrap();
+        assert_eq!(all, data);
+    }
+}
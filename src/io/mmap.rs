@@ -0,0 +1,126 @@
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read};
+use memmap2::Mmap;
+use crate::io::channel::Result;
+use crate::io::source::Source;
+
+/// # Memory-mapped backend
+///
+/// Reads are plain memory copies, strings and arrays can be borrowed without copying.
+pub struct MmapSource {
+    map: Mmap,
+    /// read position, may be past the end of the map
+    position: u64,
+}
+
+impl MmapSource {
+    pub fn new(file: &File) -> Result<Self> {
+        // Safety: 快照文件以只读方式打开，分析期间不应被其他进程修改
+        let map = unsafe { Mmap::map(file)? };
+        Ok(Self { map, position: 0 })
+    }
+
+    /// The mapped bytes in `[offset, offset + len)`
+    fn range(&self, offset: u64, len: usize) -> Result<&[u8]> {
+        let end = offset.checked_add(len as u64).filter(|end| *end <= self.map.len() as u64);
+        match end {
+            Some(end) => Ok(&self.map[offset as usize..end as usize]),
+            None => Err(Error::from(ErrorKind::UnexpectedEof)),
+        }
+    }
+}
+
+impl Read for MmapSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let start = self.position.min(self.map.len() as u64) as usize;
+        let count = buf.len().min(self.map.len() - start);
+        buf[..count].copy_from_slice(&self.map[start..start + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Source for MmapSource {
+    fn size(&self) -> u64 {
+        self.map.len() as u64
+    }
+
+    fn position(&mut self) -> Result<u64> {
+        Ok(self.position)
+    }
+
+    fn seek(&mut self, position: u64) -> Result<u64> {
+        self.position = position;
+        Ok(position)
+    }
+
+    fn skip(&mut self, len: i64) -> Result<()> {
+        self.position = self.position.checked_add_signed(len)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid seek to a negative position"))?;
+        Ok(())
+    }
+
+    fn read_slice(&mut self, len: usize) -> Result<Cow<'_, [u8]>> {
+        let position = self.position;
+        self.range(position, len)?;
+        self.position += len as u64;
+        Ok(Cow::Borrowed(self.range(position, len)?))
+    }
+
+    fn slice_at(&mut self, offset: u64, len: usize) -> Result<Cow<'_, [u8]>> {
+        Ok(Cow::Borrowed(self.range(offset, len)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use crate::io::channel::Backend;
+    use crate::parser::dump::{read_heap_record, ArrayData, GcRoot, OBJECT_TYPE};
+    use crate::parser::hprof::{read_header, HPROF_HEAP_DUMP_SEGMENT};
+    use crate::parser::reader::Reader;
+    use crate::parser::writer::HprofBuilder;
+
+    /// 逐条读取记录，堆转储分段解析为子记录
+    fn parse(path: &Path, backend: Backend) -> Vec<String> {
+        let mut reader = Reader::open_with(path, backend).unwrap();
+        let mut records = vec![format!("{:?}", read_header(&mut reader).unwrap())];
+        while let Some((tag, _, len)) = reader.get_header().unwrap() {
+            if tag != HPROF_HEAP_DUMP_SEGMENT {
+                records.push(format!("{} {:?}", tag, reader.read_slice(len as usize).unwrap()));
+                continue;
+            }
+            let end = reader.position().unwrap() + len as u64;
+            while reader.position().unwrap() < end {
+                records.push(format!("{:?}", read_heap_record(&mut reader).unwrap()));
+            }
+        }
+        records
+    }
+
+    #[test]
+    fn test_same_records_as_file_source() {
+        let data = HprofBuilder::new(8).unwrap()
+            .utf8(1, "Node")
+            .utf8(2, "next")
+            .load_class(1, 0x7f00_0000_0100, 1)
+            .root(GcRoot::StickyClass { id: 0x7f00_0000_0100 })
+            .class_dump(0x7f00_0000_0100, 0, &[(2, OBJECT_TYPE)])
+            .instance(0x7f00_0000_0200, 0x7f00_0000_0100, 0x7f00_0000_0300u64.to_be_bytes().to_vec())
+            .object_array(0x7f00_0000_0300, 0x7f00_0000_0100, vec![0x7f00_0000_0200, 0])
+            .primitive_array(0x7f00_0000_0400, ArrayData::Char("héllo".encode_utf16().collect()))
+            .end_dump()
+            .build().unwrap();
+        let path = std::env::temp_dir().join(format!("jsnap-mmap-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let mapped = parse(&path, Backend::Mmap);
+        let plain = parse(&path, Backend::File);
+        let _ = fs::remove_file(&path);
+
+        // 文件头、2 条 UTF8、LOAD CLASS、5 条子记录及 HEAP DUMP END
+        assert_eq!(mapped.len(), 10);
+        assert_eq!(mapped, plain);
+    }
+}
@@ -1 +1,6 @@
 pub mod channel;
+pub mod source;
+mod buffered;
+mod file;
+mod gzip;
+mod mmap;
@@ -0,0 +1,51 @@
+use std::borrow::Cow;
+use std::io::{Error, ErrorKind, Read};
+use crate::io::channel::Result;
+
+/// # A backend that `Channel` reads the snapshot file from
+///
+/// Positions may move past the end of the file, reads there fail with `UnexpectedEof`.
+pub trait Source: Read + Send {
+    /// The size of the file
+    fn size(&self) -> u64;
+
+    /// The current position where the file is being read
+    fn position(&mut self) -> Result<u64>;
+
+    /// Move to an absolute position in the file
+    fn seek(&mut self, position: u64) -> Result<u64>;
+
+    /// Move relative to the current position
+    fn skip(&mut self, len: i64) -> Result<()>;
+
+    /// Fail with `UnexpectedEof` when the file ends before `offset + len`
+    ///
+    /// Lengths come from the file, check them before allocating a buffer of that size.
+    fn check_available(&self, offset: u64, len: usize) -> Result<()> {
+        match offset.checked_add(len as u64) {
+            Some(end) if end <= self.size() => Ok(()),
+            _ => Err(Error::from(ErrorKind::UnexpectedEof)),
+        }
+    }
+
+    /// Read `len` bytes, borrowed from the backend when it supports zero-copy
+    fn read_slice(&mut self, len: usize) -> Result<Cow<'_, [u8]>> {
+        let position = self.position()?;
+        self.check_available(position, len)?;
+        let mut buf = vec![0; len];
+        self.read_exact(&mut buf)?;
+        Ok(Cow::Owned(buf))
+    }
+
+    /// Read `len` bytes at `offset` without moving the current position
+    fn slice_at(&mut self, offset: u64, len: usize) -> Result<Cow<'_, [u8]>> {
+        self.check_available(offset, len)?;
+        let position = self.position()?;
+        self.seek(offset)?;
+        let mut buf = vec![0; len];
+        let result = self.read_exact(&mut buf);
+        self.seek(position)?;
+        result?;
+        Ok(Cow::Owned(buf))
+    }
+}
@@ -0,0 +1,64 @@
+//! # JSnap
+//!
+//! Java 堆转储快照（HPROF）解析库，`jsnap` 命令行工具基于此实现
+//!
+//! ```rust,no_run
+//! use std::path::Path;
+//!
+//! let path = Path::new("heap.hprof");
+//! let hprof = jsnap::read(path, false)?;
+//! for thread in hprof.threads() {
+//!     println!("{}", thread);
+//! }
+//! println!("{} 个实例", hprof.dump()?.objects().len());
+//! # Ok::<(), jsnap::ParseError>(())
+//! ```
+
+pub mod io;
+pub mod parser;
+pub mod store;
+
+pub use crate::parser::error::{ParseError, Result};
+pub use crate::parser::hprof::{read, Hprof};
+pub use crate::parser::reader::{Reader, Section};
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::parser::hprof::read_header;
+    use crate::parser::reader::Utf8;
+    use crate::parser::writer::HprofBuilder;
+    use super::*;
+
+    #[test]
+    fn test_public_api() {
+        let data = HprofBuilder::new(4).unwrap()
+            .utf8(1, "Foo")
+            .load_class(1, 100, 1)
+            .class_dump(100, 0, &[])
+            .instance(200, 100, Vec::new())
+            .end_dump()
+            .build().unwrap();
+        // 文件名不是合法的 UTF-8 时同样可以解析
+        #[cfg(unix)]
+        let name = <std::ffi::OsStr as std::os::unix::ffi::OsStrExt>::from_bytes(b"heap-\xff.hprof").to_os_string();
+        #[cfg(not(unix))]
+        let name = std::ffi::OsString::from("heap.hprof");
+        let path = std::env::temp_dir().join(format!("jsnap-api-{}", std::process::id())).join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &data).unwrap();
+
+        let hprof = read(&path, false);
+        let mut reader = Reader::new(&path).unwrap();
+        read_header(&mut reader).unwrap();
+        let (_, _, length) = reader.get_header().unwrap().unwrap();
+        let utf8 = reader.read::<Utf8>(length);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+
+        let hprof: Hprof = hprof.unwrap();
+        assert_eq!(hprof.file_path(), &path);
+        assert_eq!(hprof.get_class_name_by_id(100), "Foo");
+        assert_eq!(hprof.dump().unwrap().objects().len(), 1);
+        assert_eq!(utf8.unwrap().name(), "Foo");
+    }
+}
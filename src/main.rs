@@ -1,90 +1,125 @@
 mod args;
-mod parser;
-mod io;
-mod store;
 mod cli;
+mod progress;
 
-use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::fs;
 use std::fs::File;
-use std::io::BufReader;
-use crate::args::Args;
-use crate::parser::hprof;
-
-use std::collections::HashSet;
+use std::io::BufWriter;
 use std::thread;
 use std::time::Duration;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use crate::args::Args;
+use jsnap::io::channel::Int;
+use jsnap::parser::dump::Heap;
+use jsnap::parser::error::ParseError;
+use jsnap::parser::hprof;
+use jsnap::parser::hprof::Hprof;
+use jsnap::parser::validate;
+use jsnap::parser::visitor::{self, DebugVisitor};
+use jsnap::store::ids::CompactId;
+use jsnap::store::objects::ObjectIndex;
+
 use rustyline::error::ReadlineError;
 
-use crate::cli::{JSnapCli, Result};
+use crate::cli::JSnapCli;
+use crate::progress::ConsoleProgress;
+
+/// `top` 命令默认显示的条数
+const DEFAULT_TOP_LIMIT: usize = 10;
+/// 校验报告最多列出的问题数
+const MAX_REPORTED_ISSUES: usize = 100;
+/// `refs` 命令最多列出的引用数
+const MAX_LISTED_REFS: usize = 20;
 
 fn main() {
     // 启动参数
     let args = args::get_args();
+    let force = *args.is_force();
+    let recover = *args.is_recover();
+    let validate = *args.is_validate();
+    let print_records = *args.is_print_records();
+    let heap = match args.get_heap().map(|heap| heap.parse::<Heap>()) {
+        None => None,
+        Some(Ok(heap)) => Some(heap),
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            exit(exitcode::USAGE)
+        }
+    };
 
     // 初始化数据目录
     let (file_path, work_path) = init_dir(args);
 
-    // 解析
-    let hprof = hprof::read(&file_path, &work_path);
-    let _ = match hprof {
-        Ok(hprof) => hprof,
-        Err(_) => {
-            eprintln!("解析失败");
+    // 只校验文件结构
+    if validate {
+        let valid = print_validation(&file_path);
+        exit(if valid { exitcode::OK } else { exitcode::DATAERR })
+    }
+
+    // 只逐条打印记录
+    if print_records {
+        if let Err(err) = visitor::stream(&file_path, &mut DebugVisitor::default()) {
+            eprintln!("解析失败: {}", err);
             exit(exitcode::DATAERR)
         }
-    };
-
-    // 进度
-    let progress = MultiProgress::new();
-    let sty = ProgressStyle::with_template(
-        "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
-    )
-        .unwrap()
-        .progress_chars("##-");
-
-    let pb = progress.add(ProgressBar::new(128));
-    pb.set_style(sty.clone());
-
-    let pb2 = progress.insert_after(&pb, ProgressBar::new(200));
-    pb2.set_style(sty.clone());
+        exit(exitcode::OK)
+    }
 
-    let m_clone = progress.clone();
-    let h1 = thread::spawn(move || {
-        for i in 0..128 {
-            thread::sleep(Duration::from_millis(10));
-            pb.set_message(format!("item #{}", i + 1));
-            pb.inc(1);
+    // 丢弃已保存的索引
+    if force {
+        if let Err(err) = hprof::clear_index(&work_path) {
+            eprintln!("无法删除索引: {}", err);
+            exit(exitcode::CANTCREAT)
         }
-        m_clone.println("pb1 is done!").unwrap();
-        pb.finish_with_message("done");
-    });
+    }
 
-    let m_clone = progress.clone();
-    let h2 = thread::spawn(move || {
-        for i in 0..200 {
-            thread::sleep(Duration::from_millis(10));
-            pb2.set_message(format!("item #{}", i + 1));
-            pb2.inc(1);
+    // 优先加载索引，没有可用的索引时解析并保存
+    let loaded = match hprof::load(&file_path, &work_path) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            eprintln!("索引无法使用，重新解析: {}", err);
+            // 已损坏的索引不能作为检查点
+            if let Err(err) = hprof::clear_index(&work_path) {
+                eprintln!("无法清除索引: {}", err);
+                exit(exitcode::CANTCREAT)
+            }
+            None
         }
-        m_clone.println("pb2 is done!").unwrap();
-        pb2.finish_with_message("done");
-    });
-
-    let _ = h1.join();
-    let _ = h2.join();
-
-    let _ = progress.clear();
-
+    };
+    let mut hprof = match loaded {
+        Some(hprof) => {
+            println!("已从索引加载");
+            hprof
+        }
+        None => parse(&file_path, &work_path, recover),
+    };
+    if let Some(truncation) = hprof.truncation() {
+        println!("文件不完整: 解析停止于 {}，已读取 {} 条记录，记录 0x{:02x} 缺失 {} 字节，丢弃 {} 字节",
+                 truncation.offset(), truncation.records(), truncation.tag(),
+                 truncation.missing_bytes(), truncation.discarded_bytes());
+    }
+    for index in 0..hprof.snapshots().len() {
+        let path = hprof::snapshot_path(&work_path, index);
+        if let Err(err) = fs::create_dir_all(&path) {
+            eprintln!("无法初始化快照目录 {}: {}", path.display(), err);
+            exit(exitcode::CANTCREAT)
+        }
+    }
+    if hprof.snapshots().len() > 1 {
+        println!("文件包含 {} 次堆转储，使用 use <序号> 切换，当前为 0", hprof.snapshots().len());
+    }
+    if let Some(heap) = heap {
+        hprof.retain_heap(heap);
+        let counts = hprof.snapshot().object_counts();
+        println!("只分析 {} 堆: {} 个类，{} 个实例", heap, counts.classes(), counts.instances());
+    }
 
     let mut cli = match JSnapCli::new() {
         Ok(cli) => cli,
         Err(err) => {
-            println!("{}", err.to_string());
-            return exit(exitcode::OSERR);
+            println!("{}", err);
+            exit(exitcode::OSERR);
         }
     };
 
@@ -95,7 +130,57 @@ fn main() {
                 if "exit".eq_ignore_ascii_case(line.as_str()) {
                     break;
                 }
-                println!("Line: {:?}", line);
+                let mut words = line.split_whitespace();
+                match words.next().unwrap_or_default() {
+                    // 线程栈
+                    "threads" => print_threads(&hprof),
+                    // 分配点与热点调用栈
+                    "top" => match words.next().map(|limit| limit.parse::<usize>()) {
+                        None => print_top(&hprof, DEFAULT_TOP_LIMIT),
+                        Some(Ok(limit)) => print_top(&hprof, limit),
+                        Some(Err(_)) => println!("用法: top [数量]"),
+                    },
+                    // 快照列表与切换
+                    "dumps" => print_dumps(&hprof),
+                    "use" => match words.next().map(|index| index.parse::<usize>()) {
+                        Some(Ok(index)) if hprof.select_dump(index) => println!("当前快照: {}", index),
+                        _ => println!("用法: use <序号>，序号范围 0..{}", hprof.snapshots().len()),
+                    },
+                    // 快照比较
+                    "diff" => {
+                        let args: Vec<usize> = words.filter_map(|word| word.parse().ok()).collect();
+                        match args[..] {
+                            [from, to] => print_diff(&hprof, from, to, DEFAULT_TOP_LIMIT),
+                            [from, to, limit] => print_diff(&hprof, from, to, limit),
+                            _ => println!("用法: diff <序号> <序号> [数量]"),
+                        }
+                    }
+                    // 文件结构校验
+                    "validate" => {
+                        print_validation(hprof.file_path());
+                    }
+                    // 类加载与线程事件
+                    "timeline" => print_timeline(&hprof),
+                    // 快照时刻存活的类
+                    "live-classes" => {
+                        let classes = hprof.live_classes();
+                        for name in &classes {
+                            println!("{}", name);
+                        }
+                        println!("共 {} 个类", classes.len());
+                    }
+                    // 对象的引用与被引用
+                    "refs" => match words.next().and_then(parse_id) {
+                        Some(id) => print_refs(&hprof, &work_path, id, heap),
+                        None => println!("用法: refs <对象ID>，十进制或 0x 开头的十六进制"),
+                    },
+                    // 写出为新的快照文件，例如 --heap 过滤后的内容
+                    "write" => match words.next() {
+                        Some(path) => write_hprof(&hprof, Path::new(path)),
+                        None => println!("用法: write <文件>"),
+                    },
+                    _ => println!("Line: {:?}", line),
+                }
             }
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
@@ -119,6 +204,197 @@ fn main() {
     exit(exitcode::OK)
 }
 
+/// 解析快照文件并保存索引，解析失败时退出
+fn parse(file_path: &Path, work_path: &Path, recover: bool) -> Hprof {
+    let progress = ConsoleProgress::new();
+    let hprof = hprof::read_resumable(file_path, work_path, recover, &progress);
+    progress.clear();
+    let hprof = match hprof {
+        Ok(hprof) => hprof,
+        Err(err) => {
+            eprintln!("解析失败: {}", err);
+            if let ParseError::Truncated { .. } = err {
+                eprintln!("可使用 --recover 解析截断前的内容");
+            }
+            exit(exitcode::DATAERR)
+        }
+    };
+    let saved = hprof.save_with_progress(work_path, &progress);
+    progress.clear();
+    if let Err(err) = saved {
+        eprintln!("无法保存索引: {}", err);
+    }
+    for (index, snapshot) in hprof.snapshots().iter().enumerate() {
+        // 刚解析的快照都在内存中
+        let Ok(dump) = snapshot.dump() else {
+            continue;
+        };
+        let unresolved = dump.unresolved_instances(*hprof.id_size() as Int);
+        if !unresolved.is_empty() {
+            eprintln!("堆转储 {}: {} 个实例的类定义缺失或字段布局不一致，其引用未计入，例如 0x{:x}",
+                      index, unresolved.len(), dump.id(unresolved[0]));
+        }
+    }
+    hprof
+}
+
+/// 打印快照时刻的线程栈
+fn print_threads(hprof: &Hprof) {
+    for thread in hprof.threads() {
+        println!("{}\n", thread);
+    }
+}
+
+/// 校验文件结构并打印报告，返回文件是否可信
+fn print_validation(file_path: &Path) -> bool {
+    let report = match validate::validate(file_path) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("校验失败: {}", err);
+            return false;
+        }
+    };
+    for issue in report.issues().iter().take(MAX_REPORTED_ISSUES) {
+        println!("{}", issue);
+    }
+    if report.issues().len() > MAX_REPORTED_ISSUES {
+        println!("... 另有 {} 个问题未列出", report.issues().len() - MAX_REPORTED_ISSUES);
+    }
+    println!("已检查 {} 条记录，{} 条子记录", report.records(), report.sub_records());
+    if report.is_valid() {
+        println!("未发现问题");
+    }
+    for (name, count) in report.summary() {
+        println!("{}: {}", name, count);
+    }
+    report.is_valid()
+}
+
+/// 打印全部快照
+fn print_dumps(hprof: &Hprof) {
+    for (index, snapshot) in hprof.snapshots().iter().enumerate() {
+        let marker = if index == *hprof.selected() { "*" } else { " " };
+        let counts = snapshot.object_counts();
+        println!("{} {:>3}: {} 个类，{} 个实例，{} 个对象数组，{} 个基本类型数组", marker, index,
+                 counts.classes(), counts.instances(), counts.object_arrays(), counts.primitive_arrays());
+    }
+}
+
+/// 解析十进制或 0x 开头的十六进制对象 ID
+fn parse_id(text: &str) -> Option<u64> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// 打印对象引用的对象及引用它的对象
+///
+/// 对象索引包含全部堆，指定 `--heap` 时只列出该堆中保留的对象
+fn print_refs(hprof: &Hprof, work_path: &Path, id: u64, heap: Option<Heap>) {
+    let objects = match ObjectIndex::open(&hprof::snapshot_path(work_path, *hprof.selected())) {
+        Ok(objects) => objects,
+        Err(err) => {
+            println!("无法打开对象索引: {}", err);
+            return;
+        }
+    };
+    let in_heap = |index: CompactId| heap.is_none_or(|heap| objects.heap(index) == heap);
+    let describe = |index: CompactId| {
+        let id = objects.id(index);
+        let class = match objects.class_id(index) {
+            0 if hprof.class_ids().contains_key(&id) => format!("class {}", hprof.get_class_name_by_id(id)),
+            0 => "基本类型数组".to_string(),
+            class_id => hprof.get_class_name_by_id(class_id),
+        };
+        format!("0x{:x} {} ({} 字节)", id, class, objects.shallow_size(index))
+    };
+    let Some(index) = objects.index_of(id).filter(|index| in_heap(*index)) else {
+        match heap {
+            Some(heap) => println!("当前快照的 {} 堆中没有对象 0x{:x}", heap, id),
+            None => println!("当前快照中没有对象 0x{:x}", id),
+        }
+        return;
+    };
+    println!("{}", describe(index));
+    for (title, graph) in [("引用", objects.outbound()), ("被引用", objects.inbound())] {
+        let neighbors: Vec<CompactId> = graph.neighbors(index).filter(|neighbor| in_heap(*neighbor)).collect();
+        println!("{} {} 个对象:", title, neighbors.len());
+        for neighbor in neighbors.iter().take(MAX_LISTED_REFS) {
+            println!("\t{}", describe(*neighbor));
+        }
+        if neighbors.len() > MAX_LISTED_REFS {
+            println!("\t... 另有 {} 个未列出", neighbors.len() - MAX_LISTED_REFS);
+        }
+    }
+}
+
+/// 将快照写为 HPROF 文件
+fn write_hprof(hprof: &Hprof, path: &Path) {
+    let file = match File::create(path) {
+        Ok(file) => file,
+        Err(err) => {
+            println!("无法创建 {}: {}", path.display(), err);
+            return;
+        }
+    };
+    match hprof.write(BufWriter::new(file)) {
+        Ok(()) => println!("已写入 {}", path.display()),
+        Err(err) => println!("写入失败: {}", err),
+    }
+}
+
+/// 打印两个快照间变化最大的类
+fn print_diff(hprof: &Hprof, from: usize, to: usize, limit: usize) {
+    let count = hprof.snapshots().len();
+    if from >= count || to >= count {
+        println!("快照序号范围 0..{}", count);
+        return;
+    }
+    println!("{:>12} {:>10}  类", "字节变化", "数量变化");
+    for delta in hprof.compare_dumps(from, to).iter().take(limit) {
+        println!("{:>+12} {:>+10}  {}", delta.bytes_delta(), delta.count_delta(), delta.class_name());
+    }
+}
+
+/// 按记录顺序打印类加载/卸载与线程启动/结束事件
+fn print_timeline(hprof: &Hprof) {
+    for event in hprof.timeline().events() {
+        println!("{:>12.3}ms  {}", *event.time() as f64 / 1000.0, hprof.describe_event(event.kind()));
+    }
+}
+
+/// 打印存活字节最多的分配点与采样最多的调用栈
+fn print_top(hprof: &Hprof, limit: usize) {
+    let sites = hprof.top_alloc_sites(limit);
+    if sites.is_empty() {
+        println!("没有分配点记录");
+    } else {
+        println!("{:>12} {:>10} {:>12} {:>10} {:>8}  类", "存活字节", "存活实例", "分配字节", "分配实例", "调用栈");
+        for site in sites {
+            println!("{:>12} {:>10} {:>12} {:>10} {:>8}  {}", site.live_bytes(), site.live_inst(),
+                     site.bytes_allocated(), site.inst_allocated(), site.trace_serial_num(),
+                     hprof.get_alloc_site_class(site));
+        }
+    }
+    println!();
+
+    let traces = hprof.hot_traces(limit);
+    if traces.is_empty() {
+        println!("没有 CPU 采样记录");
+        return;
+    }
+    let total = hprof.total_cpu_samples().max(1);
+    for (serial_num, count) in traces {
+        println!("调用栈 {}: {} 次采样 ({:.2}%)", serial_num, count, count as f64 * 100.0 / total as f64);
+        if let Some(trace) = hprof.get_trace(serial_num) {
+            for frame in trace.frames() {
+                println!("\tat {}", frame);
+            }
+        }
+    }
+}
+
 /// 初始化工作目录
 fn init_dir(args: Args) -> (PathBuf, PathBuf) {
     // 快照文件
@@ -150,21 +426,21 @@ fn init_dir(args: Args) -> (PathBuf, PathBuf) {
     work_path.push(file_name);
 
     if work_path.exists() && work_path.is_dir() {
-        println!("工作目录: {}", get_path_real_name(&mut work_path));
+        println!("工作目录: {}", get_path_real_name(&work_path));
         return (file_path.to_path_buf(), work_path);
     }
 
     let result = fs::create_dir_all(work_path.clone());
     if result.is_err() {
-        eprintln!("无法初始化工作目录: {}", get_path_real_name(&mut work_path));
+        eprintln!("无法初始化工作目录: {}", get_path_real_name(&work_path));
         exit(exitcode::CANTCREAT)
     }
-    println!("工作目录: {}", work_path.canonicalize().unwrap().display().to_string());
+    println!("工作目录: {}", work_path.canonicalize().unwrap().display());
 
     (file_path.to_path_buf(), work_path)
 }
 
-fn get_path_real_name(path: &mut PathBuf) -> String {
+fn get_path_real_name(path: &Path) -> String {
     path.canonicalize().unwrap().display().to_string()
 }
 
@@ -1,233 +1,1019 @@
-use std::fmt::format;
-use std::io::{Error, ErrorKind};
-use std::process::id;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io;
+use std::io::Cursor;
+use std::result;
+use std::str::FromStr;
+use std::sync::Arc;
+use byteordered::byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 use derive_getters::Getters;
-use crate::parser::reader::{Reader, Section};
-use crate::io::channel::{Byte, Int, Long, Result, Short};
-
-const HPROF_GC_ROOT_UNKNOWN: u8 = 0xff;
-const HPROF_GC_ROOT_JNI_GLOBAL: u8 = 0x01;
-const HPROF_GC_ROOT_JNI_LOCAL: u8 = 0x02;
-const HPROF_GC_ROOT_JAVA_FRAME: u8 = 0x03;
-const HPROF_GC_ROOT_NATIVE_STACK: u8 = 0x04;
-const HPROF_GC_ROOT_STICKY_CLASS: u8 = 0x05;
-const HPROF_GC_ROOT_THREAD_BLOCK: u8 = 0x06;
-const HPROF_GC_ROOT_MONITOR_USED: u8 = 0x07;
-const HPROF_GC_ROOT_THREAD_OBJ: u8 = 0x08;
-
-const HPROF_GC_CLASS_DUMP: u8 = 0x20;
-const HPROF_GC_INSTANCE_DUMP: u8 = 0x21;
-const HPROF_GC_OBJ_ARRAY_DUMP: u8 = 0x22;
-const HPROF_GC_PRIM_ARRAY_DUMP: u8 = 0x23;
-
-pub struct Dump {}
-
-pub fn get_heap_dump(reader: &mut Reader, len: Int) -> Result<Dump> {
-    for i in 0..len {
-        let tag = reader.read_byte();
-        match tag {
-            HPROF_GC_ROOT_UNKNOWN => {
-                // unknown root
-                let obj_id = reader.get_id();
-            }
-            HPROF_GC_ROOT_THREAD_OBJ => {
-                // thread object
-                let thread_obj_id = reader.get_id();    // thread object ID  (may be 0 for a thread newly attached through JNI)
-                let thread_seq = reader.read_int(); // thread sequence number
-                let stack_seq = reader.read_int();  // stack trace sequence number
-                println!("gc-root, thread {}", thread_obj_id)
-            }
-            HPROF_GC_ROOT_JNI_GLOBAL => {
-                // JNI global ref root
-                let obj_id = reader.get_id();
-                let global_ref_id = reader.get_id();    // JNI global ref ID
+use crate::parser::reader::{capacity, Reader, Section};
+use crate::io::channel::{Byte, Char, Double, Float, Int, Long, Short};
+use crate::parser::error::{ParseError, Result};
+use crate::parser::visitor::{visit_heap_record, Visitor};
+use crate::store::ids::{CompactId, IdMap, NO_ID};
+
+pub(crate) const HPROF_GC_ROOT_UNKNOWN: u8 = 0xff;
+pub(crate) const HPROF_GC_ROOT_JNI_GLOBAL: u8 = 0x01;
+pub(crate) const HPROF_GC_ROOT_JNI_LOCAL: u8 = 0x02;
+pub(crate) const HPROF_GC_ROOT_JAVA_FRAME: u8 = 0x03;
+pub(crate) const HPROF_GC_ROOT_NATIVE_STACK: u8 = 0x04;
+pub(crate) const HPROF_GC_ROOT_STICKY_CLASS: u8 = 0x05;
+pub(crate) const HPROF_GC_ROOT_THREAD_BLOCK: u8 = 0x06;
+pub(crate) const HPROF_GC_ROOT_MONITOR_USED: u8 = 0x07;
+pub(crate) const HPROF_GC_ROOT_THREAD_OBJ: u8 = 0x08;
+
+// Android (JAVA PROFILE 1.0.3)
+pub(crate) const HPROF_HEAP_DUMP_INFO: u8 = 0xfe;
+pub(crate) const HPROF_GC_ROOT_INTERNED_STRING: u8 = 0x89;
+pub(crate) const HPROF_GC_ROOT_FINALIZING: u8 = 0x8a;
+pub(crate) const HPROF_GC_ROOT_DEBUGGER: u8 = 0x8b;
+pub(crate) const HPROF_GC_ROOT_REFERENCE_CLEANUP: u8 = 0x8c;
+pub(crate) const HPROF_GC_ROOT_VM_INTERNAL: u8 = 0x8d;
+pub(crate) const HPROF_GC_ROOT_JNI_MONITOR: u8 = 0x8e;
+pub(crate) const HPROF_GC_UNREACHABLE: u8 = 0x90;
+pub(crate) const HPROF_GC_PRIM_ARRAY_NODATA_DUMP: u8 = 0xc3;
+
+pub(crate) const HPROF_GC_CLASS_DUMP: u8 = 0x20;
+pub(crate) const HPROF_GC_INSTANCE_DUMP: u8 = 0x21;
+pub(crate) const HPROF_GC_OBJ_ARRAY_DUMP: u8 = 0x22;
+pub(crate) const HPROF_GC_PRIM_ARRAY_DUMP: u8 = 0x23;
+
+/// 基本类型数组内容超过该字节数时不再读入内存，仅记录其在文件中的偏移
+const MAX_INLINE_ARRAY_BYTES: Long = 64 * 1024;
+
+/// # 堆转储内容
+/// 一个或多个 HEAP DUMP (SEGMENT) 记录解析后的对象集合
+///
+/// 实例及数组以 `ids` 中的紧凑下标表示其自身、其类及数组元素，同一次堆转储的各分段共用一个映射表；
+/// 类对象数量少，保留 HPROF ID，字段布局沿父类 ID 查找
+#[derive(Clone, Debug, Default, Getters)]
+pub struct Dump {
+    ids: Arc<IdMap>,
+    roots: Vec<GcRoot>,
+    classes: Vec<ClassObject>,
+    objects: Vec<NormalObject<CompactId>>,
+    object_arrays: Vec<ObjectArray<CompactId>>,
+    primitive_arrays: Vec<PrimitiveArray<CompactId>>,
+    /// 类对象ID -> classes 下标
+    #[getter(skip)]
+    class_index: HashMap<Long, usize>,
+    /// 最近一条 HEAP DUMP INFO 指定的堆，之后的对象都属于该堆
+    #[getter(skip)]
+    heap: Option<Heap>,
+}
+
+impl Dump {
+    /// 以 `ids` 为映射表的空堆转储
+    pub fn new(ids: Arc<IdMap>) -> Self {
+        Dump { ids, ..Default::default() }
+    }
+
+    /// 紧凑下标对应的 HPROF ID
+    pub fn id(&self, index: CompactId) -> Long {
+        self.ids.id(index)
+    }
+
+    /// HPROF ID 对应的紧凑下标
+    pub fn index_of(&self, id: Long) -> Option<CompactId> {
+        self.ids.compact(id)
+    }
+
+    /// 追加一条子记录
+    pub fn push(&mut self, record: HeapRecord) {
+        let ids = &self.ids;
+        match record {
+            HeapRecord::HeapInfo { heap, .. } => self.heap = Some(heap),
+            HeapRecord::Root(root) => self.roots.push(root),
+            HeapRecord::Class(mut class) => {
+                class.heap = self.heap;
+                self.class_index.insert(class.id, self.classes.len());
+                self.classes.push(class);
             }
-            HPROF_GC_ROOT_JNI_LOCAL => {
-                // JNI local ref
-                let obj_id = reader.get_id();
-                let thread_seq = reader.read_int(); // thread serial number
-                let depth = reader.read_int();  // frame # in stack trace (-1 for empty)
+            HeapRecord::Instance(obj) => {
+                let mut obj = obj.map_ids(|id| ids.intern(id));
+                obj.heap = self.heap;
+                self.objects.push(obj);
             }
-            HPROF_GC_ROOT_JAVA_FRAME => {
-                // Java stack frame
-                let obj_id = reader.get_id();
-                let thread_seq = reader.read_int(); // thread serial number
-                let depth = reader.read_int(); // frame # in stack trace (-1 for empty)
+            HeapRecord::ObjectArray(array) => {
+                let mut array = array.map_ids(|id| ids.intern(id));
+                array.heap = self.heap;
+                self.object_arrays.push(array);
             }
-            HPROF_GC_ROOT_NATIVE_STACK => {
-                // Native stack
-                let obj_id = reader.get_id();
-                let thread_seq = reader.read_int(); // thread serial number
+            HeapRecord::PrimitiveArray(array) => {
+                let mut array = array.map_ids(|id| ids.intern(id));
+                array.heap = self.heap;
+                self.primitive_arrays.push(array);
             }
-            HPROF_GC_ROOT_STICKY_CLASS => {
-                // System class
-                let obj_id = reader.get_id();
+        }
+    }
+
+    /// 最近一条 HEAP DUMP INFO 指定的堆，之后分段开头的对象属于该堆
+    pub(crate) fn heap_tag(&self) -> Option<Heap> {
+        self.heap
+    }
+
+    /// 合并另一个分段的解析结果
+    ///
+    /// 分段开头、第一条 HEAP DUMP INFO 之前的对象属于上一分段最后指定的堆。
+    /// 两者的映射表不同时，`other` 的紧凑下标换为本堆转储的；本堆转储还没有对象时直接使用 `other` 的映射表
+    pub fn merge(&mut self, mut other: Dump) {
+        if !Arc::ptr_eq(&self.ids, &other.ids) {
+            if self.objects.is_empty() && self.object_arrays.is_empty() && self.primitive_arrays.is_empty() {
+                self.ids = other.ids.clone();
+            } else {
+                other = other.with_ids(&self.ids);
             }
-            HPROF_GC_ROOT_THREAD_BLOCK => {
-                // Reference from thread block
-                let obj_id = reader.get_id();
-                let thread_seq = reader.read_int(); // thread serial number
-                println!("gc-root, thread {}", obj_id);
+        }
+        inherit_heap(&mut other.classes, self.heap);
+        inherit_heap(&mut other.objects, self.heap);
+        inherit_heap(&mut other.object_arrays, self.heap);
+        inherit_heap(&mut other.primitive_arrays, self.heap);
+        self.heap = other.heap.or(self.heap);
+
+        self.roots.extend(other.roots);
+        for class in other.classes {
+            self.class_index.insert(class.id, self.classes.len());
+            self.classes.push(class);
+        }
+        self.objects.extend(other.objects);
+        self.object_arrays.extend(other.object_arrays);
+        self.primitive_arrays.extend(other.primitive_arrays);
+    }
+
+    /// 换用映射表 `ids`，各对象的紧凑下标经 HPROF ID 重新分配
+    fn with_ids(self, ids: &Arc<IdMap>) -> Dump {
+        let remap = |index| ids.intern(self.ids.id(index));
+        Dump {
+            ids: ids.clone(),
+            objects: self.objects.into_iter().map(|obj| obj.map_ids(remap)).collect(),
+            object_arrays: self.object_arrays.into_iter().map(|array| array.map_ids(remap)).collect(),
+            primitive_arrays: self.primitive_arrays.into_iter().map(|array| array.map_ids(remap)).collect(),
+            ..self
+        }
+    }
+
+    /// 按类统计对象数量与浅大小
+    pub fn histogram(&self, id_size: Int) -> HashMap<HistogramKey, HistogramEntry> {
+        let mut histogram: HashMap<HistogramKey, HistogramEntry> = HashMap::new();
+        for obj in &self.objects {
+            histogram.entry(HistogramKey::Class(obj.class_id)).or_default().add(obj.payload.len() as u64);
+        }
+        for array in &self.object_arrays {
+            histogram.entry(HistogramKey::Class(array.class_id)).or_default().add(array.len as u64 * id_size as u64);
+        }
+        for array in &self.primitive_arrays {
+            histogram.entry(HistogramKey::PrimitiveArray(array.element_type)).or_default().add(array.byte_size());
+        }
+        histogram
+    }
+
+    /// 是否没有任何子记录
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty() && self.classes.is_empty() && self.objects.is_empty()
+            && self.object_arrays.is_empty() && self.primitive_arrays.is_empty()
+    }
+
+    /// 类对象、实例及数组的个数，不含 GC root
+    pub fn object_count(&self) -> usize {
+        self.classes.len() + self.objects.len() + self.object_arrays.len() + self.primitive_arrays.len()
+    }
+
+    /// 只保留指定堆中的类与对象，GC root 全部保留
+    pub fn filter_heap(&self, heap: Heap) -> Dump {
+        let mut dump = Dump {
+            ids: self.ids.clone(),
+            roots: self.roots.clone(),
+            heap: self.heap,
+            ..Default::default()
+        };
+        for class in self.classes.iter().filter(|class| class.heap() == heap) {
+            dump.class_index.insert(class.id, dump.classes.len());
+            dump.classes.push(class.clone());
+        }
+        dump.objects = self.objects.iter().filter(|obj| obj.heap() == heap).cloned().collect();
+        dump.object_arrays = self.object_arrays.iter().filter(|array| array.heap() == heap).cloned().collect();
+        dump.primitive_arrays = self.primitive_arrays.iter().filter(|array| array.heap() == heap).cloned().collect();
+        dump
+    }
+
+    /// 将 `offsets` 中的数组恢复为从源文件偏移处按需读取，用于加载索引
+    pub(crate) fn restore_lazy(&mut self, offsets: &HashMap<CompactId, u64>) {
+        for array in self.primitive_arrays.iter_mut() {
+            if let Some(offset) = offsets.get(&array.id) {
+                array.elements = ArrayData::Lazy { offset: *offset };
             }
-            HPROF_GC_ROOT_MONITOR_USED => {
-                // Busy monitor
-                let obj_id = reader.get_id();
+        }
+    }
+
+    /// 根据类对象ID查找类
+    pub fn get_class(&self, id: Long) -> Option<&ClassObject> {
+        self.class_index.get(&id).map(|index| &self.classes[*index])
+    }
+
+    /// 实例字段布局
+    ///
+    /// 与实例数据的排列顺序一致：先是类自身声明的字段，其后依次为父类、父类的父类……
+    pub fn get_field_layout(&self, class_id: Long) -> Result<Vec<&FieldInfo>> {
+        let mut layout = Vec::new();
+        let mut visited = HashSet::new();
+        let mut class_id = class_id;
+        while class_id != 0 {
+            if !visited.insert(class_id) {
+                return Err(ParseError::malformed(format!("类继承关系存在循环: {}", class_id)));
             }
-            HPROF_GC_CLASS_DUMP => {
-                // dump of a class object
-                let class_dump = reader.read::<ClassObject>(len);
-                println!("class {}", class_dump.id);
+            let class = self.get_class(class_id).ok_or_else(|| {
+                ParseError::malformed(format!("未找到类: {}", class_id))
+            })?;
+            layout.extend(class.fields.iter());
+            class_id = class.super_id;
+        }
+        Ok(layout)
+    }
+
+    /// 按类字段布局解析实例字段值
+    pub fn get_instance_fields(&self, obj: &NormalObject<CompactId>, id_size: Int) -> Result<Vec<Field>> {
+        let layout = self.get_field_layout(self.id(obj.class_id))?;
+        let mut cursor = Cursor::new(obj.payload.as_slice());
+        let mut fields = Vec::with_capacity(layout.len());
+        for info in layout {
+            let value = decode_value(&mut cursor, info.field_type, id_size).map_err(|err| {
+                ParseError::malformed(format!("实例 {} 字段解析失败: {}", self.id(obj.id), err))
+            })?;
+            fields.push(Field { name_id: info.name_id, value });
+        }
+        if cursor.position() != obj.payload.len() as u64 {
+            return Err(ParseError::malformed(format!("实例 {} 字段长度不匹配: 布局 {} 字节，实际 {} 字节",
+                                                     self.id(obj.id), cursor.position(), obj.payload.len())));
+        }
+        Ok(fields)
+    }
+
+    /// 无法按类字段布局解析的实例：类或父类缺失、继承关系成环、或实例数据长度与布局不一致
+    ///
+    /// 这些实例的引用不在 `references` 中
+    pub fn unresolved_instances(&self, id_size: Int) -> Vec<CompactId> {
+        // 每个类的实例数据长度，布局无法解析时为 None
+        let mut sizes: HashMap<CompactId, Option<u64>> = HashMap::new();
+        self.objects.iter()
+            .filter(|obj| {
+                let size = *sizes.entry(obj.class_id).or_insert_with(|| {
+                    let layout = self.get_field_layout(self.id(obj.class_id)).ok()?;
+                    layout.iter()
+                        .map(|info| Some(get_type_size(info.field_type, id_size) as u64).filter(|size| *size > 0))
+                        .sum()
+                });
+                size != Some(obj.payload.len() as u64)
+            })
+            .map(|obj| obj.id)
+            .collect()
+    }
+
+    /// 全部非空引用 (from, to, field name ID)：类的静态字段、实例字段及对象数组元素，数组元素的字段名 ID 为 0
+    ///
+    /// 对象以紧凑下标表示，指向堆转储之外的对象时为其在映射表中追加的下标。
+    /// 无法解析字段的实例，其引用被跳过，见 `unresolved_instances`
+    pub fn references(&self, id_size: Int) -> impl Iterator<Item = (CompactId, CompactId, Long)> + '_ {
+        let ids = &self.ids;
+        let statics = self.classes.iter().flat_map(move |class| {
+            class.static_fields.iter().filter_map(move |field| match field.value {
+                Value::Id(to_id) if to_id != 0 => Some((ids.intern(class.id), ids.intern(to_id), field.name_id)),
+                _ => None,
+            })
+        });
+        let fields = self.objects.iter().flat_map(move |obj| {
+            let fields = self.get_instance_fields(obj, id_size).unwrap_or_default();
+            fields.into_iter().filter_map(move |field| match field.value {
+                Value::Id(to_id) if to_id != 0 => Some((obj.id, ids.intern(to_id), field.name_id)),
+                _ => None,
+            })
+        });
+        let elements = self.object_arrays.iter().flat_map(|array| {
+            array.elements.iter()
+                .filter(|to| **to != NO_ID)
+                .map(|to| (array.id, *to, 0))
+        });
+        statics.chain(fields).chain(elements)
+    }
+}
+
+/// 子记录逐条追加
+impl Visitor for Dump {
+    fn on_heap_info(&mut self, heap: Heap, name_id: Long) {
+        self.push(HeapRecord::HeapInfo { heap, name_id });
+    }
+
+    fn on_gc_root(&mut self, root: GcRoot) {
+        self.push(HeapRecord::Root(root));
+    }
+
+    fn on_class_dump(&mut self, class: ClassObject) {
+        self.push(HeapRecord::Class(class));
+    }
+
+    fn on_instance(&mut self, obj: NormalObject) {
+        self.push(HeapRecord::Instance(obj));
+    }
+
+    fn on_object_array(&mut self, array: ObjectArray) {
+        self.push(HeapRecord::ObjectArray(array));
+    }
+
+    fn on_primitive_array(&mut self, array: PrimitiveArray) {
+        self.push(HeapRecord::PrimitiveArray(array));
+    }
+}
+
+/// # 直方图中的一类对象
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HistogramKey {
+    /// 实例或对象数组，按类对象的紧凑下标区分
+    Class(CompactId),
+    /// 基本类型数组，按元素类型区分
+    PrimitiveArray(Byte),
+}
+
+/// # 一类对象的数量与浅大小
+///
+/// 浅大小只计实例数据与数组元素，不含对象头
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Getters)]
+pub struct HistogramEntry {
+    count: u64,
+    bytes: u64,
+}
+
+impl HistogramEntry {
+    pub fn new(count: u64, bytes: u64) -> Self {
+        Self { count, bytes }
+    }
+
+    fn add(&mut self, bytes: u64) {
+        self.count += 1;
+        self.bytes += bytes;
+    }
+}
+
+/// # a heap dump sub-record
+#[derive(Clone, Debug)]
+pub enum HeapRecord {
+    /// Android: the heap the following objects belong to
+    HeapInfo { heap: Heap, name_id: Long },
+    Root(GcRoot),
+    Class(ClassObject),
+    Instance(NormalObject),
+    ObjectArray(ObjectArray),
+    PrimitiveArray(PrimitiveArray),
+}
+
+/// 解析一个 HEAP DUMP 或 HEAP DUMP SEGMENT 记录体，子记录依次交给 `visitor`
+///
+/// 按字节位置遍历子记录，直到用完记录长度 `len`
+pub fn get_heap_dump<V: Visitor + ?Sized>(reader: &mut Reader, len: Int, visitor: &mut V) -> Result<()> {
+    let mut position = reader.position()?;
+    let end = position + len as u64;
+    while position < end {
+        let record = read_heap_record(reader)?;
+        visit_heap_record(visitor, record);
+        position = reader.position()?;
+        reader.mark_complete(position);
+    }
+    Ok(())
+}
+
+/// 随机读取文件 `offset` 处的一条堆转储子记录，例如按偏移索引直接定位对象
+pub fn read_heap_record_at(reader: &mut Reader, offset: u64) -> Result<HeapRecord> {
+    reader.seek(offset)?;
+    read_heap_record(reader)
+}
+
+/// 读取一条堆转储子记录（含 tag），只取出其对象 ID，GC root 及 HEAP DUMP INFO 为 `None`
+///
+/// 实例及数组只读取记录头，其余内容直接跳过，用于解析分段前收集对象 ID
+pub fn read_object_id(reader: &mut Reader) -> Result<Option<Long>> {
+    let start = reader.position()?;
+    let tag = reader.read_byte()?;
+    reader.set_tag(tag);
+    let id_size = reader.id_size() as Long;
+    match tag {
+        HPROF_GC_INSTANCE_DUMP => {
+            // object ID, stack trace serial number, class object ID, number of bytes that follow
+            let id = reader.get_id()?;
+            reader.skip(4 + id_size)?;
+            let len = reader.read_int()?;
+            reader.skip(len as Long)?;
+            Ok(Some(id))
+        }
+        HPROF_GC_OBJ_ARRAY_DUMP => {
+            // array object ID, stack trace serial number, number of elements, array class object ID, elements
+            let id = reader.get_id()?;
+            reader.skip(4)?;
+            let num = reader.read_int()? as Long;
+            reader.skip(id_size * (1 + num))?;
+            Ok(Some(id))
+        }
+        HPROF_GC_PRIM_ARRAY_DUMP | HPROF_GC_PRIM_ARRAY_NODATA_DUMP => {
+            let (id, _, num, element_type) = PrimitiveArray::read_header(reader)?;
+            if tag == HPROF_GC_PRIM_ARRAY_DUMP {
+                reader.skip(num as Long * get_type_size(element_type, 0) as Long)?;
             }
-            HPROF_GC_INSTANCE_DUMP => {
-                // dump of a class object
-                let normal_obj = reader.read::<NormalObject>(len);
-                println!("obj {}", normal_obj.id);
+            Ok(Some(id))
+        }
+        _ => {
+            reader.seek(start)?;
+            match read_heap_record(reader)? {
+                HeapRecord::Class(class) => Ok(Some(class.id)),
+                _ => Ok(None),
             }
-            HPROF_GC_OBJ_ARRAY_DUMP => {
-                let obj_array = reader.read::<ObjectArray>(len);
+        }
+    }
+}
+
+/// 读取一条堆转储子记录（含 tag）
+pub fn read_heap_record(reader: &mut Reader) -> Result<HeapRecord> {
+    let tag = reader.read_byte()?;
+    reader.set_tag(tag);
+    let record = match tag {
+        HPROF_GC_ROOT_UNKNOWN => {
+            // unknown root
+            let id = reader.get_id()?;
+            HeapRecord::Root(GcRoot::Unknown { id })
+        }
+        HPROF_GC_ROOT_THREAD_OBJ => {
+            // thread object
+            let id = reader.get_id()?;    // thread object ID  (may be 0 for a thread newly attached through JNI)
+            let thread_seq = reader.read_int()?; // thread sequence number
+            let stack_seq = reader.read_int()?;  // stack trace sequence number
+            HeapRecord::Root(GcRoot::ThreadObj { id, thread_seq, stack_seq })
+        }
+        HPROF_GC_ROOT_JNI_GLOBAL => {
+            // JNI global ref root
+            let id = reader.get_id()?;
+            let global_ref_id = reader.get_id()?;    // JNI global ref ID
+            HeapRecord::Root(GcRoot::JniGlobal { id, global_ref_id })
+        }
+        HPROF_GC_ROOT_JNI_LOCAL => {
+            // JNI local ref
+            let id = reader.get_id()?;
+            let thread_seq = reader.read_int()?; // thread serial number
+            let depth = reader.read_int()?;  // frame # in stack trace (-1 for empty)
+            HeapRecord::Root(GcRoot::JniLocal { id, thread_seq, depth })
+        }
+        HPROF_GC_ROOT_JAVA_FRAME => {
+            // Java stack frame
+            let id = reader.get_id()?;
+            let thread_seq = reader.read_int()?; // thread serial number
+            let depth = reader.read_int()?; // frame # in stack trace (-1 for empty)
+            HeapRecord::Root(GcRoot::JavaFrame { id, thread_seq, depth })
+        }
+        HPROF_GC_ROOT_NATIVE_STACK => {
+            // Native stack
+            let id = reader.get_id()?;
+            let thread_seq = reader.read_int()?; // thread serial number
+            HeapRecord::Root(GcRoot::NativeStack { id, thread_seq })
+        }
+        HPROF_GC_ROOT_STICKY_CLASS => {
+            // System class
+            let id = reader.get_id()?;
+            HeapRecord::Root(GcRoot::StickyClass { id })
+        }
+        HPROF_GC_ROOT_THREAD_BLOCK => {
+            // Reference from thread block
+            let id = reader.get_id()?;
+            let thread_seq = reader.read_int()?; // thread serial number
+            HeapRecord::Root(GcRoot::ThreadBlock { id, thread_seq })
+        }
+        HPROF_GC_ROOT_MONITOR_USED => {
+            // Busy monitor
+            let id = reader.get_id()?;
+            HeapRecord::Root(GcRoot::MonitorUsed { id })
+        }
+        HPROF_HEAP_DUMP_INFO => {
+            // Android: heap type, heap name
+            let heap = Heap::from(reader.read_int()?);
+            let name_id = reader.get_id()?;
+            HeapRecord::HeapInfo { heap, name_id }
+        }
+        HPROF_GC_ROOT_INTERNED_STRING => {
+            // Android: interned string
+            let id = reader.get_id()?;
+            HeapRecord::Root(GcRoot::InternedString { id })
+        }
+        HPROF_GC_ROOT_FINALIZING => {
+            // Android: object pending finalization
+            let id = reader.get_id()?;
+            HeapRecord::Root(GcRoot::Finalizing { id })
+        }
+        HPROF_GC_ROOT_DEBUGGER => {
+            // Android: object held by the debugger
+            let id = reader.get_id()?;
+            HeapRecord::Root(GcRoot::Debugger { id })
+        }
+        HPROF_GC_ROOT_REFERENCE_CLEANUP => {
+            // Android: reference being cleared
+            let id = reader.get_id()?;
+            HeapRecord::Root(GcRoot::ReferenceCleanup { id })
+        }
+        HPROF_GC_ROOT_VM_INTERNAL => {
+            // Android: held by the runtime
+            let id = reader.get_id()?;
+            HeapRecord::Root(GcRoot::VmInternal { id })
+        }
+        HPROF_GC_ROOT_JNI_MONITOR => {
+            // Android: monitor held by JNI code
+            let id = reader.get_id()?;
+            let thread_seq = reader.read_int()?; // thread serial number
+            let depth = reader.read_int()?; // frame # in stack trace
+            HeapRecord::Root(GcRoot::JniMonitor { id, thread_seq, depth })
+        }
+        HPROF_GC_UNREACHABLE => {
+            // Android: unreachable object
+            let id = reader.get_id()?;
+            HeapRecord::Root(GcRoot::Unreachable { id })
+        }
+        HPROF_GC_PRIM_ARRAY_NODATA_DUMP => {
+            // Android: primitive array without its elements
+            HeapRecord::PrimitiveArray(PrimitiveArray::read_nodata(reader)?)
+        }
+        HPROF_GC_CLASS_DUMP => {
+            // dump of a class object
+            let class_dump = reader.read::<ClassObject>(0)?;
+            HeapRecord::Class(class_dump)
+        }
+        HPROF_GC_INSTANCE_DUMP => {
+            // dump of a normal object
+            let normal_obj = reader.read::<NormalObject>(0)?;
+            HeapRecord::Instance(normal_obj)
+        }
+        HPROF_GC_OBJ_ARRAY_DUMP => {
+            // dump of an object array
+            HeapRecord::ObjectArray(reader.read::<ObjectArray>(0)?)
+        }
+        HPROF_GC_PRIM_ARRAY_DUMP => {
+            // dump of a primitive array
+            HeapRecord::PrimitiveArray(reader.read::<PrimitiveArray>(0)?)
+        }
+        _ => {
+            // 子记录没有长度字段，无法跳过未知类型
+            let offset = reader.position()? - 1;
+            return Err(ParseError::UnknownTag { offset, tag });
+        }
+    };
+    Ok(record)
+}
+
+/// # GC root
+#[derive(Clone, Debug)]
+pub enum GcRoot {
+    /// unknown root
+    Unknown { id: Long },
+    /// JNI global ref root
+    JniGlobal { id: Long, global_ref_id: Long },
+    /// JNI local ref
+    JniLocal { id: Long, thread_seq: Int, depth: Int },
+    /// Java stack frame
+    JavaFrame { id: Long, thread_seq: Int, depth: Int },
+    /// Native stack
+    NativeStack { id: Long, thread_seq: Int },
+    /// System class
+    StickyClass { id: Long },
+    /// Reference from thread block
+    ThreadBlock { id: Long, thread_seq: Int },
+    /// Busy monitor
+    MonitorUsed { id: Long },
+    /// thread object
+    ThreadObj { id: Long, thread_seq: Int, stack_seq: Int },
+    /// Android: interned string
+    InternedString { id: Long },
+    /// Android: object pending finalization
+    Finalizing { id: Long },
+    /// Android: object held by the debugger
+    Debugger { id: Long },
+    /// Android: reference being cleared
+    ReferenceCleanup { id: Long },
+    /// Android: held by the runtime
+    VmInternal { id: Long },
+    /// Android: monitor held by JNI code
+    JniMonitor { id: Long, thread_seq: Int, depth: Int },
+    /// Android: unreachable object
+    Unreachable { id: Long },
+}
+
+/// # Android heap an object is allocated in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Heap {
+    /// HotSpot dumps, or before the first HEAP DUMP INFO
+    #[default]
+    Default,
+    /// application heap
+    App,
+    /// boot image
+    Image,
+    /// shared with the zygote
+    Zygote,
+    /// other heap type
+    Other(Int),
+}
+
+impl From<Int> for Heap {
+    fn from(id: Int) -> Self {
+        match id {
+            0 => Heap::Default,
+            0x41 => Heap::App,      // 'A'
+            0x49 => Heap::Image,    // 'I'
+            0x5a => Heap::Zygote,   // 'Z'
+            id => Heap::Other(id),
+        }
+    }
+}
+
+impl FromStr for Heap {
+    type Err = String;
+
+    fn from_str(name: &str) -> result::Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "default" => Ok(Heap::Default),
+            "app" => Ok(Heap::App),
+            "image" => Ok(Heap::Image),
+            "zygote" => Ok(Heap::Zygote),
+            _ => Err(format!("未知的堆: {}，可选 default、app、image、zygote", name)),
+        }
+    }
+}
+
+impl From<Heap> for Int {
+    fn from(heap: Heap) -> Self {
+        match heap {
+            Heap::Default => 0,
+            Heap::App => 0x41,
+            Heap::Image => 0x49,
+            Heap::Zygote => 0x5a,
+            Heap::Other(id) => id,
+        }
+    }
+}
+
+impl fmt::Display for Heap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Heap::Default => write!(f, "default"),
+            Heap::App => write!(f, "app"),
+            Heap::Image => write!(f, "image"),
+            Heap::Zygote => write!(f, "zygote"),
+            Heap::Other(id) => write!(f, "heap-{:#x}", id),
+        }
+    }
+}
+
+/// 带有堆标记的对象
+trait OnHeap {
+    fn heap_mut(&mut self) -> &mut Option<Heap>;
+}
+
+/// 为开头尚未标记堆的对象补上 `heap`
+fn inherit_heap<T: OnHeap>(items: &mut [T], heap: Option<Heap>) {
+    for item in items {
+        let tag = item.heap_mut();
+        if tag.is_some() {
+            break;
+        }
+        *tag = heap;
+    }
+}
+
+macro_rules! impl_on_heap {
+    ($($ty:ident $(<$param:ident>)?),*) => {$(
+        impl$(<$param>)? $ty$(<$param>)? {
+            /// 所在的堆，仅 Android 快照区分
+            pub fn heap(&self) -> Heap {
+                self.heap.unwrap_or_default()
             }
-            HPROF_GC_PRIM_ARRAY_DUMP => {
-                // Todo let prim_array = reader.read::<PrimitiveArray>(len);
-                reader.skip(len - 1);
+
+            /// HEAP DUMP INFO 指定的堆，分段开头尚未指定时为 `None`
+            pub(crate) fn heap_tag(&self) -> Option<Heap> {
+                self.heap
             }
-            _ => {
-                reader.skip(len - 1);
+        }
+
+        impl$(<$param>)? OnHeap for $ty$(<$param>)? {
+            fn heap_mut(&mut self) -> &mut Option<Heap> {
+                &mut self.heap
             }
         }
+    )*};
+}
+
+impl_on_heap!(ClassObject, NormalObject<I>, ObjectArray<I>, PrimitiveArray<I>);
+
+impl GcRoot {
+    /// 根对象ID
+    pub fn id(&self) -> Long {
+        match self {
+            GcRoot::Unknown { id }
+            | GcRoot::JniGlobal { id, .. }
+            | GcRoot::JniLocal { id, .. }
+            | GcRoot::JavaFrame { id, .. }
+            | GcRoot::NativeStack { id, .. }
+            | GcRoot::StickyClass { id }
+            | GcRoot::ThreadBlock { id, .. }
+            | GcRoot::MonitorUsed { id }
+            | GcRoot::ThreadObj { id, .. }
+            | GcRoot::InternedString { id }
+            | GcRoot::Finalizing { id }
+            | GcRoot::Debugger { id }
+            | GcRoot::ReferenceCleanup { id }
+            | GcRoot::VmInternal { id }
+            | GcRoot::JniMonitor { id, .. }
+            | GcRoot::Unreachable { id } => *id,
+        }
+    }
+
+    /// 子记录类型
+    pub fn tag(&self) -> Byte {
+        match self {
+            GcRoot::Unknown { .. } => HPROF_GC_ROOT_UNKNOWN,
+            GcRoot::JniGlobal { .. } => HPROF_GC_ROOT_JNI_GLOBAL,
+            GcRoot::JniLocal { .. } => HPROF_GC_ROOT_JNI_LOCAL,
+            GcRoot::JavaFrame { .. } => HPROF_GC_ROOT_JAVA_FRAME,
+            GcRoot::NativeStack { .. } => HPROF_GC_ROOT_NATIVE_STACK,
+            GcRoot::StickyClass { .. } => HPROF_GC_ROOT_STICKY_CLASS,
+            GcRoot::ThreadBlock { .. } => HPROF_GC_ROOT_THREAD_BLOCK,
+            GcRoot::MonitorUsed { .. } => HPROF_GC_ROOT_MONITOR_USED,
+            GcRoot::ThreadObj { .. } => HPROF_GC_ROOT_THREAD_OBJ,
+            GcRoot::InternedString { .. } => HPROF_GC_ROOT_INTERNED_STRING,
+            GcRoot::Finalizing { .. } => HPROF_GC_ROOT_FINALIZING,
+            GcRoot::Debugger { .. } => HPROF_GC_ROOT_DEBUGGER,
+            GcRoot::ReferenceCleanup { .. } => HPROF_GC_ROOT_REFERENCE_CLEANUP,
+            GcRoot::VmInternal { .. } => HPROF_GC_ROOT_VM_INTERNAL,
+            GcRoot::JniMonitor { .. } => HPROF_GC_ROOT_JNI_MONITOR,
+            GcRoot::Unreachable { .. } => HPROF_GC_UNREACHABLE,
+        }
+    }
+
+    /// 所在线程的 serial number，与线程无关的根为 `None`
+    pub fn thread_seq(&self) -> Option<Int> {
+        match self {
+            GcRoot::JniLocal { thread_seq, .. }
+            | GcRoot::JavaFrame { thread_seq, .. }
+            | GcRoot::NativeStack { thread_seq, .. }
+            | GcRoot::ThreadBlock { thread_seq, .. }
+            | GcRoot::ThreadObj { thread_seq, .. }
+            | GcRoot::JniMonitor { thread_seq, .. } => Some(*thread_seq),
+            _ => None,
+        }
+    }
+}
+
+/// # a field value
+#[derive(Clone, Debug, Getters)]
+pub struct Field {
+    /// field name ID
+    name_id: Long,
+    value: Value,
+}
+
+impl Field {
+    pub fn new(name_id: Long, value: Value) -> Self {
+        Field { name_id, value }
+    }
+}
+
+/// # an instance field declared by a class
+#[derive(Clone, Debug, Getters)]
+pub struct FieldInfo {
+    /// field name ID
+    name_id: Long,
+    /// field type
+    field_type: Byte,
+}
+
+impl FieldInfo {
+    pub fn new(name_id: Long, field_type: Byte) -> Self {
+        FieldInfo { name_id, field_type }
     }
-    Ok(Dump {})
 }
 
 /// # dump of a class object
 #[derive(Clone, Debug, Getters)]
-struct ClassObject {
+pub struct ClassObject {
+    /// class object ID
     id: Long,
+    /// stack trace serial number
+    stack_trace_ser: Int,
+    /// super class object ID
     super_id: Long,
+    /// class loader object ID
     class_loader_id: Long,
-
+    /// signers object ID
     signers_id: Long,
+    /// protection domain object ID
+    protection_domain_id: Long,
+    /// instance size (in bytes)
+    instance_size: Int,
+    /// static fields
+    static_fields: Vec<Field>,
+    /// instance fields (not inc. super)
+    fields: Vec<FieldInfo>,
+    #[getter(skip)]
+    heap: Option<Heap>,
+}
+
+impl ClassObject {
+    /// 没有类加载器、签名及保护域的类
+    pub fn new(id: Long, super_id: Long, instance_size: Int, static_fields: Vec<Field>, fields: Vec<FieldInfo>) -> Self {
+        ClassObject {
+            id,
+            stack_trace_ser: 0,
+            super_id,
+            class_loader_id: 0,
+            signers_id: 0,
+            protection_domain_id: 0,
+            instance_size,
+            static_fields,
+            fields,
+            heap: None,
+        }
+    }
 }
 
 impl Section for ClassObject {
-    fn read(reader: &mut Reader, _: Int) -> Self {
-        let id = reader.get_id();
-        let stack_ser = reader.read_int();
-        let super_id = reader.get_id();
-        let class_loader_id = reader.get_id();
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
+        let id = reader.get_id()?;
+        let stack_trace_ser = reader.read_int()?;
+        let super_id = reader.get_id()?;
+        let class_loader_id = reader.get_id()?;
 
         // read signers, protection domain, reserved ids (2)
-        let signers_id = reader.get_id();
-        let protection_domain_id = reader.get_id();
-        let reserved1 = reader.get_id();
-        let reserved12 = reader.get_id();
+        let signers_id = reader.get_id()?;
+        let protection_domain_id = reader.get_id()?;
+        let _reserved1 = reader.get_id()?;
+        let _reserved2 = reader.get_id()?;
 
         // instance size (in bytes)
-        let inst_size = reader.read_int();
+        let instance_size = reader.read_int()?;
 
         // size of constant pool
-        let const_pool_size = reader.read_short();
+        let const_pool_size = reader.read_short()?;
         for _ in 0..const_pool_size {
-            let index = reader.read_short();    // constant pool index
-            let name = format!("<constant pool[{}]>", index);
-            let ty = reader.read_byte();    // type
-            let val = get_value(reader, ty);
+            let _index = reader.read_short()?;    // constant pool index
+            let ty = reader.read_byte()?;    // type
+            let _ = get_value(reader, ty)?;
         }
 
         // number of static fields
-        let num_static_fields = reader.read_short();
+        let num_static_fields = reader.read_short()?;
+        let mut static_fields = Vec::with_capacity(num_static_fields as usize);
         for _ in 0..num_static_fields {
-            let name_id = reader.get_id();  // static field name
-            let ty = reader.read_byte();    // type
-            let val = get_value(reader, ty);
+            let name_id = reader.get_id()?;  // static field name
+            let ty = reader.read_byte()?;    // type
+            let value = get_value(reader, ty)?;
+            static_fields.push(Field { name_id, value });
         }
 
         // number of inst. fields (not inc. super)
-        let num_inst_fields = reader.read_short();
+        let num_inst_fields = reader.read_short()?;
+        let mut fields = Vec::with_capacity(num_inst_fields as usize);
         for _ in 0..num_inst_fields {
-            let name_id = reader.get_id();  // instance field name
-            let ty = reader.read_byte();    // type
+            let name_id = reader.get_id()?;  // instance field name
+            let field_type = reader.read_byte()?;    // type
+            fields.push(FieldInfo { name_id, field_type });
         }
 
-        ClassObject {
+        Ok(ClassObject {
             id,
+            stack_trace_ser,
             super_id,
             class_loader_id,
-
             signers_id,
-        }
+            protection_domain_id,
+            instance_size,
+            static_fields,
+            fields,
+            heap: None,
+        })
     }
 }
 
 /// # dump of a normal object
+///
+/// `I` 为对象的表示，解析时为 HPROF ID，`Dump` 中为紧凑下标
 #[derive(Clone, Debug, Getters)]
-struct NormalObject {
+pub struct NormalObject<I = Long> {
     /// object ID
-    id: Long,
+    id: I,
     /// stack trace serial number
     stack_trace_ser: Int,
     /// class object ID
-    class_id: Long,
+    class_id: I,
+    /// instance field values (class, followed by super, super's super ...)
+    payload: Vec<Byte>,
+    #[getter(skip)]
+    heap: Option<Heap>,
+}
+
+impl<I> NormalObject<I> {
+    pub fn new(id: I, class_id: I, payload: Vec<Byte>) -> Self {
+        NormalObject { id, stack_trace_ser: 0, class_id, payload, heap: None }
+    }
+
+    /// 换用另一种对象表示
+    pub fn map_ids<J>(self, f: impl Fn(I) -> J) -> NormalObject<J> {
+        NormalObject {
+            id: f(self.id),
+            stack_trace_ser: self.stack_trace_ser,
+            class_id: f(self.class_id),
+            payload: self.payload,
+            heap: self.heap,
+        }
+    }
 }
 
 impl Section for NormalObject {
-    fn read(reader: &mut Reader, _: Int) -> Self {
-        let id = reader.get_id();
-        let stack_trace_id = reader.read_int();
-        let class_id = reader.get_id();
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
+        let id = reader.get_id()?;
+        let stack_trace_id = reader.read_int()?;
+        let class_id = reader.get_id()?;
         // number of bytes that follow
-        let payload = reader.read_int();
+        let len = reader.read_int()?;
         // instance field values (class, followed by super, super's super ...)
-        // Todo 暂时跳过
-        reader.skip(payload);
-        NormalObject {
+        // 字段布局依赖类定义，待全部类解析完成后再按需解码
+        let payload = reader.read_bytes(len as usize)?;
+        Ok(NormalObject {
             id,
             stack_trace_ser: stack_trace_id,
             class_id,
-        }
+            payload,
+            heap: None,
+        })
     }
 }
 
 /// # dump of an object array
+///
+/// `I` 为对象的表示，解析时为 HPROF ID，`Dump` 中为紧凑下标
 #[derive(Clone, Debug, Getters)]
-struct ObjectArray {
+pub struct ObjectArray<I = Long> {
     /// object ID
-    id: Long,
+    id: I,
     /// stack trace serial number
     stack_trace_ser: Int,
     /// number of elements
     len: Int,
     /// class object ID
-    class_id: Long,
+    class_id: I,
     /// elements
-    elements: Vec<Long>,
+    elements: Vec<I>,
+    #[getter(skip)]
+    heap: Option<Heap>,
+}
+
+impl<I> ObjectArray<I> {
+    pub fn new(id: I, class_id: I, elements: Vec<I>) -> Self {
+        ObjectArray { id, stack_trace_ser: 0, len: elements.len() as Int, class_id, elements, heap: None }
+    }
+
+    /// 换用另一种对象表示
+    pub fn map_ids<J>(self, f: impl Fn(I) -> J) -> ObjectArray<J> {
+        ObjectArray {
+            id: f(self.id),
+            stack_trace_ser: self.stack_trace_ser,
+            len: self.len,
+            class_id: f(self.class_id),
+            elements: self.elements.into_iter().map(f).collect(),
+            heap: self.heap,
+        }
+    }
 }
 
 impl Section for ObjectArray {
-    fn read(reader: &mut Reader, len: Int) -> Self {
-        let id = reader.get_id();
-        let stack_trace_id = reader.read_int();
-        let num = reader.read_int();
-        let class_id = reader.get_id();
-        let mut elements: Vec<Long> = Vec::new();
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
+        let id = reader.get_id()?;
+        let stack_trace_id = reader.read_int()?;
+        let num = reader.read_int()?;
+        let class_id = reader.get_id()?;
+        let remaining = reader.remaining()?;
+        let mut elements: Vec<Long> = Vec::with_capacity(capacity(num, reader.id_size() as usize, remaining));
         for _ in 0..num {
-            let element_id = reader.get_id();
+            let element_id = reader.get_id()?;
             elements.push(element_id);
         }
-        ObjectArray {
+        Ok(ObjectArray {
             id,
             stack_trace_ser: stack_trace_id,
             class_id,
-            len,
+            len: num,
             elements,
-        }
+            heap: None,
+        })
     }
 }
 
 /// # dump of a primitive array
+///
+/// `I` 为对象的表示，解析时为 HPROF ID，`Dump` 中为紧凑下标
 #[derive(Clone, Debug, Getters)]
-struct PrimitiveArray {
+pub struct PrimitiveArray<I = Long> {
     /// array object ID
-    id: Long,
+    id: I,
     /// stack trace serial number
     stack_trace_ser: Int,
     /// number of elements
@@ -235,41 +1021,236 @@ struct PrimitiveArray {
     /// element type
     element_type: Byte,
     /// elements
-    elements: Vec<Long>,
+    elements: ArrayData,
+    #[getter(skip)]
+    heap: Option<Heap>,
 }
 
-impl Section for PrimitiveArray {
-    fn read(reader: &mut Reader, len: Int) -> Self {
-        let id = reader.get_id();
-        let stack_trace_id = reader.read_int();
-        let num = reader.read_int();
-        let element_type = reader.read_byte();
-        let mut elements: Vec<Long> = Vec::new();
+impl<I: fmt::Display> PrimitiveArray<I> {
+    /// 元素必须已在内存中，`Lazy` 或 `NoData` 没有元素类型及个数，返回错误
+    pub fn new(id: I, elements: ArrayData) -> Result<Self> {
+        let Some((element_type, len)) = elements.element_type().zip(elements.count()) else {
+            return Err(ParseError::malformed(format!("数组 {} 的元素不在内存中，无法确定元素类型及个数", id)));
+        };
+        Ok(PrimitiveArray { id, stack_trace_ser: 0, len: len as Int, element_type, elements, heap: None })
+    }
+}
 
+impl<I: Clone> PrimitiveArray<I> {
+    /// 换用另一种对象表示
+    pub fn map_ids<J>(self, f: impl Fn(I) -> J) -> PrimitiveArray<J> {
         PrimitiveArray {
+            id: f(self.id),
+            stack_trace_ser: self.stack_trace_ser,
+            len: self.len,
+            element_type: self.element_type,
+            elements: self.elements,
+            heap: self.heap,
+        }
+    }
+
+    /// 元素内容占用的字节数
+    pub fn byte_size(&self) -> Long {
+        self.len as Long * get_type_size(self.element_type, 0) as Long
+    }
+
+    /// 读取数组元素，大数组从文件偏移处重新加载
+    pub fn load(&self, reader: &mut Reader) -> Result<ArrayData> {
+        match self.elements {
+            ArrayData::Lazy { offset } => {
+                let buf = reader.slice_at(offset, self.byte_size() as usize)?;
+                decode_array(self.element_type, &buf)
+            }
+            _ => Ok(self.elements.clone()),
+        }
+    }
+
+    /// 只保留数组头，元素标记为不在快照中
+    pub(crate) fn without_elements(&self) -> Self {
+        PrimitiveArray { elements: ArrayData::NoData, ..self.clone() }
+    }
+}
+
+impl PrimitiveArray {
+
+    /// 读取数组头：array object ID, stack trace serial number, number of elements, element type
+    fn read_header(reader: &mut Reader) -> Result<(Long, Int, Int, Byte)> {
+        let id = reader.get_id()?;
+        let stack_trace_id = reader.read_int()?;
+        let num = reader.read_int()?;
+        let element_type = reader.read_byte()?;
+        if get_type_size(element_type, 0) == 0 {
+            let offset = reader.position()? - 1;
+            return Err(ParseError::InvalidType { offset, tag: reader.tag(), value_type: element_type });
+        }
+        Ok((id, stack_trace_id, num, element_type))
+    }
+
+    /// Android PRIMITIVE ARRAY NODATA：只有数组头，没有元素内容
+    fn read_nodata(reader: &mut Reader) -> Result<Self> {
+        let (id, stack_trace_id, num, element_type) = Self::read_header(reader)?;
+        Ok(PrimitiveArray {
+            id,
+            stack_trace_ser: stack_trace_id,
+            len: num,
+            element_type,
+            elements: ArrayData::NoData,
+            heap: None,
+        })
+    }
+}
+
+impl Section for PrimitiveArray {
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
+        let (id, stack_trace_id, num, element_type) = Self::read_header(reader)?;
+        let size = num as Long * get_type_size(element_type, 0) as Long;
+        let elements = if size > MAX_INLINE_ARRAY_BYTES {
+            let offset = reader.position()?;
+            reader.skip(size)?;
+            ArrayData::Lazy { offset }
+        } else {
+            let buf = reader.read_slice(size as usize)?;
+            decode_array(element_type, &buf)?
+        };
+
+        Ok(PrimitiveArray {
             id,
             stack_trace_ser: stack_trace_id,
-            len,
+            len: num,
             element_type,
             elements,
+            heap: None,
+        })
+    }
+}
+
+/// # elements of a primitive array
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArrayData {
+    Bool(Vec<bool>),
+    /// UTF-16 code units
+    Char(Vec<Char>),
+    Float(Vec<Float>),
+    Double(Vec<Double>),
+    Byte(Vec<Byte>),
+    Short(Vec<Short>),
+    Int(Vec<Int>),
+    Long(Vec<Long>),
+    /// 元素未读入内存，记录元素内容在文件中的偏移
+    Lazy { offset: u64 },
+    /// 快照中没有元素内容（Android PRIMITIVE ARRAY NODATA）
+    NoData,
+}
+
+impl ArrayData {
+    /// 元素类型，元素不在内存中时为 `None`
+    pub fn element_type(&self) -> Option<Byte> {
+        match self {
+            ArrayData::Bool(_) => Some(BOOLEAN_TYPE),
+            ArrayData::Char(_) => Some(CHAR_TYPE),
+            ArrayData::Float(_) => Some(FLOAT_TYPE),
+            ArrayData::Double(_) => Some(DOUBLE_TYPE),
+            ArrayData::Byte(_) => Some(BYTE_TYPE),
+            ArrayData::Short(_) => Some(SHORT_TYPE),
+            ArrayData::Int(_) => Some(INT_TYPE),
+            ArrayData::Long(_) => Some(LONG_TYPE),
+            ArrayData::Lazy { .. } | ArrayData::NoData => None,
+        }
+    }
+
+    /// 元素个数，元素不在内存中时为 `None`
+    pub fn count(&self) -> Option<usize> {
+        match self {
+            ArrayData::Bool(v) => Some(v.len()),
+            ArrayData::Char(v) => Some(v.len()),
+            ArrayData::Float(v) => Some(v.len()),
+            ArrayData::Double(v) => Some(v.len()),
+            ArrayData::Byte(v) => Some(v.len()),
+            ArrayData::Short(v) => Some(v.len()),
+            ArrayData::Int(v) => Some(v.len()),
+            ArrayData::Long(v) => Some(v.len()),
+            ArrayData::Lazy { .. } | ArrayData::NoData => None,
+        }
+    }
+
+    /// `char[]` 按 UTF-16 解码为字符串，非法的代理项替换为 U+FFFD
+    pub fn to_utf16_string(&self) -> Option<String> {
+        match self {
+            ArrayData::Char(units) => Some(decode_utf16(units)),
+            _ => None,
+        }
+    }
+}
+
+/// UTF-16 编码单元解码为字符串
+pub fn decode_utf16(units: &[Char]) -> String {
+    char::decode_utf16(units.iter().copied())
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// 按元素类型解码数组内容
+fn decode_array(t: Byte, buf: &[u8]) -> Result<ArrayData> {
+    let data = match t {
+        BOOLEAN_TYPE => ArrayData::Bool(buf.iter().map(|b| *b != 0).collect()),
+        BYTE_TYPE => ArrayData::Byte(buf.to_vec()),
+        CHAR_TYPE => ArrayData::Char(buf.chunks_exact(2).map(BigEndian::read_u16).collect()),
+        SHORT_TYPE => ArrayData::Short(buf.chunks_exact(2).map(BigEndian::read_u16).collect()),
+        INT_TYPE => ArrayData::Int(buf.chunks_exact(4).map(BigEndian::read_u32).collect()),
+        FLOAT_TYPE => ArrayData::Float(buf.chunks_exact(4).map(BigEndian::read_f32).collect()),
+        LONG_TYPE => ArrayData::Long(buf.chunks_exact(8).map(BigEndian::read_u64).collect()),
+        DOUBLE_TYPE => ArrayData::Double(buf.chunks_exact(8).map(BigEndian::read_f64).collect()),
+        _ => {
+            return Err(ParseError::malformed(format!("无效的数组元素类型: {}", t)));
         }
+    };
+    Ok(data)
+}
+
+pub const OBJECT_TYPE: u8 = 2;
+pub const BOOLEAN_TYPE: u8 = 4;
+pub const CHAR_TYPE: u8 = 5;
+pub const FLOAT_TYPE: u8 = 6;
+pub const DOUBLE_TYPE: u8 = 7;
+pub const BYTE_TYPE: u8 = 8;
+pub const SHORT_TYPE: u8 = 9;
+pub const INT_TYPE: u8 = 10;
+pub const LONG_TYPE: u8 = 11;
+
+/// 类型名称，与 Java 源码中的写法一致
+pub fn get_type_name(t: Byte) -> &'static str {
+    match t {
+        OBJECT_TYPE => "Object",
+        BOOLEAN_TYPE => "boolean",
+        CHAR_TYPE => "char",
+        FLOAT_TYPE => "float",
+        DOUBLE_TYPE => "double",
+        BYTE_TYPE => "byte",
+        SHORT_TYPE => "short",
+        INT_TYPE => "int",
+        LONG_TYPE => "long",
+        _ => "unknown",
     }
 }
 
-const OBJECT_TYPE: u8 = 2;
-const BOOLEAN_TYPE: u8 = 4;
-const CHAR_TYPE: u8 = 5;
-const FLOAT_TYPE: u8 = 6;
-const DOUBLE_TYPE: u8 = 7;
-const BYTE_TYPE: u8 = 8;
-const SHORT_TYPE: u8 = 9;
-const INT_TYPE: u8 = 10;
-const LONG_TYPE: u8 = 11;
+/// 基本类型占用的字节数
+pub fn get_type_size(t: Byte, id_size: Int) -> Int {
+    match t {
+        OBJECT_TYPE => id_size,
+        BOOLEAN_TYPE | BYTE_TYPE => 1,
+        CHAR_TYPE | SHORT_TYPE => 2,
+        FLOAT_TYPE | INT_TYPE => 4,
+        DOUBLE_TYPE | LONG_TYPE => 8,
+        _ => 0,
+    }
+}
 
-enum Value {
+#[derive(Clone, Debug)]
+pub enum Value {
     Id(Long),
     Bool(bool),
-    Char(char),
+    /// UTF-16 code unit
+    Char(Char),
     Float(u32),
     Double(u64),
     Byte(Byte),
@@ -278,48 +1259,348 @@ enum Value {
     Long(Long),
 }
 
+impl Value {
+    /// 值的类型
+    pub fn value_type(&self) -> Byte {
+        match self {
+            Value::Id(_) => OBJECT_TYPE,
+            Value::Bool(_) => BOOLEAN_TYPE,
+            Value::Char(_) => CHAR_TYPE,
+            Value::Float(_) => FLOAT_TYPE,
+            Value::Double(_) => DOUBLE_TYPE,
+            Value::Byte(_) => BYTE_TYPE,
+            Value::Short(_) => SHORT_TYPE,
+            Value::Int(_) => INT_TYPE,
+            Value::Long(_) => LONG_TYPE,
+        }
+    }
+}
+
 fn get_value(reader: &mut Reader, t: Byte) -> Result<Value> {
-    return match t {
+    match t {
         OBJECT_TYPE => {
-            let id = reader.get_id();
+            let id = reader.get_id()?;
             Ok(Value::Id(id))
         }
         BOOLEAN_TYPE => {
-            let byte = reader.read_byte();
+            let byte = reader.read_byte()?;
             Ok(Value::Bool(byte != 0))
         }
         CHAR_TYPE => {
-            let char = reader.read_char();
+            let char = reader.read_char()?;
             Ok(Value::Char(char))
         }
         FLOAT_TYPE => {
-            let float = reader.read_int();
+            let float = reader.read_int()?;
             Ok(Value::Float(float))
         }
         DOUBLE_TYPE => {
-            let double = reader.read_long();
+            let double = reader.read_long()?;
             Ok(Value::Double(double))
         }
         BYTE_TYPE => {
-            let byte = reader.read_byte();
+            let byte = reader.read_byte()?;
             Ok(Value::Byte(byte))
         }
         SHORT_TYPE => {
-            let short = reader.read_short();
+            let short = reader.read_short()?;
             Ok(Value::Short(short))
         }
         INT_TYPE => {
-            let int = reader.read_int();
+            let int = reader.read_int()?;
             Ok(Value::Int(int))
         }
         LONG_TYPE => {
-            let long = reader.read_long();
+            let long = reader.read_long()?;
             Ok(Value::Long(long))
         }
         _ => {
-            Err(Error::new(ErrorKind::InvalidData,
-                           format!("无效的类型: {}，position {}", t, reader.position().unwrap())))
+            let offset = reader.position()?;
+            Err(ParseError::InvalidType { offset, tag: reader.tag(), value_type: t })
         }
-    };
+    }
+}
+
+/// 从实例数据中解码一个字段值
+fn decode_value(cursor: &mut Cursor<&[u8]>, t: Byte, id_size: Int) -> io::Result<Value> {
+    match t {
+        OBJECT_TYPE => {
+            let id = if id_size == 4 {
+                cursor.read_u32::<BigEndian>()? as Long
+            } else {
+                cursor.read_u64::<BigEndian>()?
+            };
+            Ok(Value::Id(id))
+        }
+        BOOLEAN_TYPE => Ok(Value::Bool(cursor.read_u8()? != 0)),
+        CHAR_TYPE => {
+            Ok(Value::Char(cursor.read_u16::<BigEndian>()?))
+        }
+        FLOAT_TYPE => Ok(Value::Float(cursor.read_u32::<BigEndian>()?)),
+        DOUBLE_TYPE => Ok(Value::Double(cursor.read_u64::<BigEndian>()?)),
+        BYTE_TYPE => Ok(Value::Byte(cursor.read_u8()?)),
+        SHORT_TYPE => Ok(Value::Short(cursor.read_u16::<BigEndian>()?)),
+        INT_TYPE => Ok(Value::Int(cursor.read_u32::<BigEndian>()?)),
+        LONG_TYPE => Ok(Value::Long(cursor.read_u64::<BigEndian>()?)),
+        _ => {
+            Err(io::Error::new(io::ErrorKind::InvalidData,
+                           format!("无效的类型: {}，offset {}", t, cursor.position())))
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::parser::writer::{Emit, Encoder};
+    use super::*;
+
+    /// 写入临时文件并读取 id 大小（4 字节）
+    fn open_reader(name: &str, body: &[u8]) -> (Reader, PathBuf) {
+        let path = std::env::temp_dir().join(format!("jsnap-{}-{}.bin", name, std::process::id()));
+        let mut data = 4u32.to_be_bytes().to_vec();
+        data.extend_from_slice(body);
+        fs::write(&path, data).unwrap();
+        let mut reader = Reader::new(&path).unwrap();
+        reader.get_id_size().unwrap();
+        (reader, path)
+    }
+
+    #[test]
+    fn test_static_char_field() {
+        let mut body = vec![HPROF_GC_CLASS_DUMP];
+        for id in [100u32, 1, 0, 0, 0, 0, 0, 0, 16] {
+            // id, stack trace, super, loader, signers, protection domain, reserved x2, instance size
+            body.extend_from_slice(&id.to_be_bytes());
+        }
+        body.extend_from_slice(&0u16.to_be_bytes()); // constant pool
+        body.extend_from_slice(&2u16.to_be_bytes()); // static fields
+        body.extend_from_slice(&7u32.to_be_bytes());
+        body.push(CHAR_TYPE);
+        body.extend_from_slice(&0x4e2du16.to_be_bytes()); // '中'
+        body.extend_from_slice(&8u32.to_be_bytes());
+        body.push(INT_TYPE);
+        body.extend_from_slice(&42u32.to_be_bytes());
+        body.extend_from_slice(&0u16.to_be_bytes()); // instance fields
+
+        let (mut reader, path) = open_reader("static-char", &body);
+        let record = read_heap_record(&mut reader).unwrap();
+        let _ = fs::remove_file(path);
+
+        let HeapRecord::Class(class) = record else { panic!("not a class dump") };
+        let fields = class.static_fields();
+        assert_eq!(fields.len(), 2);
+        assert!(matches!(fields[0].value(), Value::Char(0x4e2d)));
+        assert!(matches!(fields[1].value(), Value::Int(42)));
+    }
+
+    #[test]
+    fn test_instance_fields_through_super_chain() {
+        let mut dump = Dump::new(Arc::new(IdMap::new(vec![10, 20, 300, 301, 302]).unwrap()));
+        // Child(count: int) extends Base(next: Object)
+        dump.push(HeapRecord::Class(ClassObject::new(10, 0, 4, Vec::new(), vec![FieldInfo::new(1, OBJECT_TYPE)])));
+        dump.push(HeapRecord::Class(ClassObject::new(20, 10, 8, Vec::new(), vec![FieldInfo::new(2, INT_TYPE)])));
+        let mut payload = 7u32.to_be_bytes().to_vec();
+        payload.extend_from_slice(&300u32.to_be_bytes());
+        dump.push(HeapRecord::Instance(NormalObject::new(300, 20, payload)));
+        dump.push(HeapRecord::Instance(NormalObject::new(301, 20, 7u32.to_be_bytes().to_vec())));
+        dump.push(HeapRecord::Instance(NormalObject::new(302, 30, 300u32.to_be_bytes().to_vec())));
+        let [obj, short, orphan] = dump.objects().as_slice() else { panic!("expected 3 instances") };
+
+        // 子类自身的字段在前，其后为父类字段
+        let fields = dump.get_instance_fields(obj, 4).unwrap();
+        let names: Vec<Long> = fields.iter().map(|field| *field.name_id()).collect();
+        assert_eq!(names, [2, 1]);
+        assert!(matches!(fields[0].value(), Value::Int(7)));
+        assert!(matches!(fields[1].value(), Value::Id(300)));
+
+        // 实例数据比布局短，或父类缺失，都报告为格式错误
+        assert!(matches!(dump.get_instance_fields(short, 4), Err(ParseError::Malformed { .. })));
+        assert!(matches!(dump.get_instance_fields(orphan, 4), Err(ParseError::Malformed { .. })));
+        // 缺失的类追加在映射表之后
+        assert_eq!(*orphan.class_id(), 5);
+
+        // 无法解析的实例不产生引用，单独列出
+        let references: Vec<(Long, Long, Long)> = dump.references(4)
+            .map(|(from, to, name_id)| (dump.id(from), dump.id(to), name_id))
+            .collect();
+        assert_eq!(references, [(300, 300, 1)]);
+        let unresolved: Vec<Long> = dump.unresolved_instances(4).into_iter().map(|index| dump.id(index)).collect();
+        assert_eq!(unresolved, [301, 302]);
+    }
+
+    #[test]
+    fn test_typed_primitive_arrays() {
+        let mut body = Vec::new();
+        let mut push_array = |id: u32, element_type: Byte, num: u32, data: &[u8]| {
+            body.push(HPROF_GC_PRIM_ARRAY_DUMP);
+            for value in [id, 1, num] {
+                body.extend_from_slice(&value.to_be_bytes());
+            }
+            body.push(element_type);
+            body.extend_from_slice(data);
+        };
+        push_array(200, BOOLEAN_TYPE, 3, &[1, 0, 1]);
+        push_array(201, LONG_TYPE, 1, &(-2i64).to_be_bytes());
+        push_array(202, DOUBLE_TYPE, 1, &1.5f64.to_be_bytes());
+        // 超过内联阈值的数组只记录偏移，按需从文件加载
+        let large: Vec<u8> = (0..MAX_INLINE_ARRAY_BYTES as u32 / 4 + 1).flat_map(|i| i.to_be_bytes()).collect();
+        push_array(203, INT_TYPE, large.len() as u32 / 4, &large);
+
+        let (mut reader, path) = open_reader("typed-arrays", &body);
+        let arrays: Vec<PrimitiveArray> = (0..4).map(|_| match read_heap_record(&mut reader).unwrap() {
+            HeapRecord::PrimitiveArray(array) => array,
+            other => panic!("not a primitive array: {:?}", other),
+        }).collect();
+        let loaded = arrays[3].load(&mut reader).unwrap();
+        let _ = fs::remove_file(path);
+
+        assert_eq!(*arrays[0].elements(), ArrayData::Bool(vec![true, false, true]));
+        assert_eq!(*arrays[1].elements(), ArrayData::Long(vec![-2i64 as Long]));
+        assert_eq!(*arrays[2].elements(), ArrayData::Double(vec![1.5]));
+        assert!(matches!(arrays[3].elements(), ArrayData::Lazy { .. }));
+        assert_eq!(arrays[3].byte_size(), large.len() as Long);
+        let ArrayData::Int(values) = loaded else { panic!("not an int array") };
+        assert_eq!((values.len(), values[1], values[values.len() - 1]), (large.len() / 4, 1, values.len() as Int - 1));
+    }
+
+    #[test]
+    fn test_char_array_surrogate_pair() {
+        let mut body = vec![HPROF_GC_PRIM_ARRAY_DUMP];
+        body.extend_from_slice(&200u32.to_be_bytes());
+        body.extend_from_slice(&1u32.to_be_bytes());
+        body.extend_from_slice(&4u32.to_be_bytes());
+        body.push(CHAR_TYPE);
+        for unit in [0x0061u16, 0xd83d, 0xde00, 0xd83d] {
+            body.extend_from_slice(&unit.to_be_bytes());
+        }
+
+        let (mut reader, path) = open_reader("surrogate", &body);
+        let record = read_heap_record(&mut reader).unwrap();
+        let _ = fs::remove_file(path);
+
+        let HeapRecord::PrimitiveArray(array) = record else { panic!("not a primitive array") };
+        assert_eq!(array.byte_size(), 8);
+        // 成对的代理项合并为一个字符，孤立的代理项替换为 U+FFFD
+        assert_eq!(array.elements().to_utf16_string().unwrap(), "a\u{1F600}\u{FFFD}");
+    }
+
+    #[test]
+    fn test_android_heap_info() {
+        // 第一个分段：切换到 app 堆后写入一个 NODATA 数组
+        let mut body = vec![HPROF_HEAP_DUMP_INFO];
+        body.extend_from_slice(&0x41u32.to_be_bytes());
+        body.extend_from_slice(&9u32.to_be_bytes());
+        body.push(HPROF_GC_PRIM_ARRAY_NODATA_DUMP);
+        for value in [400u32, 1, 16] {
+            body.extend_from_slice(&value.to_be_bytes());
+        }
+        body.push(INT_TYPE);
+        body.push(HPROF_GC_ROOT_INTERNED_STRING);
+        body.extend_from_slice(&400u32.to_be_bytes());
+        let first_len = body.len() as Int;
+        // 第二个分段：开头的实例仍属于 app 堆，之后切换到 zygote 堆
+        body.push(HPROF_GC_INSTANCE_DUMP);
+        for value in [500u32, 1, 100, 0] {
+            body.extend_from_slice(&value.to_be_bytes());
+        }
+        body.push(HPROF_HEAP_DUMP_INFO);
+        body.extend_from_slice(&0x5au32.to_be_bytes());
+        body.extend_from_slice(&10u32.to_be_bytes());
+        body.push(HPROF_GC_INSTANCE_DUMP);
+        for value in [600u32, 1, 100, 0] {
+            body.extend_from_slice(&value.to_be_bytes());
+        }
+        let second_len = body.len() as Int - first_len;
+
+        let (mut reader, path) = open_reader("android", &body);
+        let mut dump = Dump::default();
+        get_heap_dump(&mut reader, first_len, &mut dump).unwrap();
+        let mut second = Dump::default();
+        get_heap_dump(&mut reader, second_len, &mut second).unwrap();
+        let _ = fs::remove_file(path);
+        dump.merge(second);
+
+        assert!(matches!(dump.roots()[0], GcRoot::InternedString { id: 400 }));
+        assert!(matches!(dump.primitive_arrays()[0].elements(), ArrayData::NoData));
+        assert_eq!(dump.primitive_arrays()[0].heap(), Heap::App);
+        let heaps: Vec<Heap> = dump.objects().iter().map(|obj| obj.heap()).collect();
+        assert_eq!(heaps, [Heap::App, Heap::Zygote]);
+        let zygote = dump.filter_heap(Heap::Zygote);
+        assert_eq!(zygote.objects().len(), 1);
+        assert!(zygote.primitive_arrays().is_empty());
+    }
+
+    #[test]
+    fn test_object_array_count_beyond_file() {
+        let mut body = vec![HPROF_GC_ROOT_STICKY_CLASS];
+        body.extend_from_slice(&100u32.to_be_bytes());
+        body.push(HPROF_GC_OBJ_ARRAY_DUMP);
+        for value in [300u32, 1, 2, 100, 201, 0] {
+            body.extend_from_slice(&value.to_be_bytes());
+        }
+        // 损坏的元素个数远超文件大小，不应按其预分配
+        body.push(HPROF_GC_OBJ_ARRAY_DUMP);
+        for value in [301u32, 1, u32::MAX, 100, 201] {
+            body.extend_from_slice(&value.to_be_bytes());
+        }
+
+        let (mut reader, path) = open_reader("obj-array", &body);
+        let root = read_heap_record(&mut reader).unwrap();
+        let array = read_heap_record(&mut reader).unwrap();
+        let corrupt = read_heap_record(&mut reader);
+        let _ = fs::remove_file(path);
+
+        assert!(matches!(root, HeapRecord::Root(GcRoot::StickyClass { id: 100 })));
+        let HeapRecord::ObjectArray(array) = array else { panic!("not an object array") };
+        assert_eq!((*array.class_id(), array.elements().as_slice()), (100, [201, 0].as_slice()));
+        assert!(matches!(corrupt, Err(ParseError::Truncated { .. })));
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let mut body = vec![HPROF_GC_INSTANCE_DUMP];
+        body.extend_from_slice(&300u32.to_be_bytes());
+        body.extend_from_slice(&1u32.to_be_bytes());
+        body.extend_from_slice(&100u32.to_be_bytes());
+        body.extend_from_slice(&8u32.to_be_bytes());
+        body.extend_from_slice(&[0; 3]); // 实例数据只有 3 个字节
+
+        let (mut reader, path) = open_reader("truncated", &body);
+        let result = read_heap_record(&mut reader);
+        let _ = fs::remove_file(path);
+
+        match result {
+            Err(ParseError::Truncated { tag, .. }) => assert_eq!(tag, Some(HPROF_GC_INSTANCE_DUMP)),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_read_object_ids() {
+        let records = [
+            HeapRecord::HeapInfo { heap: Heap::App, name_id: 0 },
+            HeapRecord::Root(GcRoot::StickyClass { id: 100 }),
+            HeapRecord::Class(ClassObject::new(100, 0, 4, Vec::new(), vec![FieldInfo::new(1, INT_TYPE)])),
+            HeapRecord::Instance(NormalObject::new(200, 100, vec![0, 0, 0, 7])),
+            HeapRecord::ObjectArray(ObjectArray::new(300, 100, vec![200, 0])),
+            HeapRecord::PrimitiveArray(PrimitiveArray::new(400, ArrayData::Int(vec![1, 2, 3])).unwrap()),
+            HeapRecord::PrimitiveArray(PrimitiveArray::new(401, ArrayData::Byte(vec![1])).unwrap().without_elements()),
+        ];
+        let mut encoder = Encoder::new(4, true);
+        for record in &records {
+            record.emit(&mut encoder).unwrap();
+        }
+        let (mut reader, path) = open_reader("object-ids", encoder.as_bytes());
+        let mut ids = Vec::new();
+        for _ in &records {
+            ids.push(read_object_id(&mut reader).unwrap());
+        }
+        let end = reader.position().unwrap();
+        let _ = fs::remove_file(path);
+
+        assert_eq!(ids, [None, None, Some(100), Some(200), Some(300), Some(400), Some(401)]);
+        assert_eq!(end, 4 + encoder.as_bytes().len() as u64);
+    }
+}
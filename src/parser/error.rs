@@ -0,0 +1,122 @@
+use std::{error, fmt, io, result};
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+use crate::io::channel::{Byte, Int};
+
+pub type Result<T> = result::Result<T, ParseError>;
+
+/// # 快照文件解析错误
+/// `offset` 为发现错误时的文件字节偏移，`tag` 为正在读取的记录类型
+#[derive(Debug)]
+pub enum ParseError {
+    /// 文件在记录中途结束
+    Truncated { offset: u64, tag: Option<Byte> },
+    /// 无法识别的记录或子记录类型
+    UnknownTag { offset: u64, tag: Byte },
+    /// 文件头中的 id 大小既不是 4 也不是 8
+    BadIdSize { offset: u64, id_size: Int },
+    /// 无效的基本类型
+    InvalidType { offset: u64, tag: Option<Byte>, value_type: Byte },
+    /// 不支持的文件版本
+    UnsupportedVersion { version: String },
+    /// 记录内容与文件结构不一致
+    Malformed { message: String },
+    /// 其他读取错误
+    Io { offset: u64, tag: Option<Byte>, source: io::Error },
+    /// 工作目录中的数据库操作失败
+    Database { source: rusqlite::Error },
+    /// 工作目录中的文件读写失败，或校验索引时读取快照文件信息失败，与快照文件内容无关
+    Store { path: PathBuf, source: io::Error },
+}
+
+impl ParseError {
+    /// 将底层读取错误转为解析错误，读到文件末尾视为截断
+    pub fn from_io(source: io::Error, offset: u64, tag: Option<Byte>) -> Self {
+        if source.kind() == io::ErrorKind::UnexpectedEof {
+            ParseError::Truncated { offset, tag }
+        } else {
+            ParseError::Io { offset, tag, source }
+        }
+    }
+
+    /// 读写 `path` 失败
+    pub fn store(source: io::Error, path: &Path) -> Self {
+        ParseError::Store { path: path.to_path_buf(), source }
+    }
+
+    pub fn malformed(message: String) -> Self {
+        ParseError::Malformed { message }
+    }
+
+    /// 发现错误时的文件偏移
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            ParseError::Truncated { offset, .. }
+            | ParseError::UnknownTag { offset, .. }
+            | ParseError::BadIdSize { offset, .. }
+            | ParseError::InvalidType { offset, .. }
+            | ParseError::Io { offset, .. } => Some(*offset),
+            ParseError::UnsupportedVersion { .. }
+            | ParseError::Malformed { .. }
+            | ParseError::Database { .. }
+            | ParseError::Store { .. } => None,
+        }
+    }
+}
+
+/// 记录类型描述
+struct TagName(Option<Byte>);
+
+impl fmt::Display for TagName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(tag) => write!(f, "，记录类型 0x{:02x}", tag),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Truncated { offset, tag } => {
+                write!(f, "文件不完整: 在 {} 处被截断{}", offset, TagName(*tag))
+            }
+            ParseError::UnknownTag { offset, tag } => {
+                write!(f, "无效的记录类型: 0x{:02x}，position {}", tag, offset)
+            }
+            ParseError::BadIdSize { offset, id_size } => {
+                write!(f, "无效的 id 大小: {}，position {}", id_size, offset)
+            }
+            ParseError::InvalidType { offset, tag, value_type } => {
+                write!(f, "无效的类型: {}，position {}{}", value_type, offset, TagName(*tag))
+            }
+            ParseError::UnsupportedVersion { version } => {
+                write!(f, "不支持的版本: {}", version)
+            }
+            ParseError::Malformed { message } => write!(f, "{}", message),
+            ParseError::Io { offset, tag, source } => {
+                write!(f, "读取失败: {}，position {}{}", source, offset, TagName(*tag))
+            }
+            ParseError::Database { source } => write!(f, "数据库操作失败: {}", source),
+            ParseError::Store { path, source } => write!(f, "文件读写失败: {}: {}", path.display(), source),
+        }
+    }
+}
+
+impl error::Error for ParseError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParseError::Io { source, .. } => Some(source),
+            ParseError::Database { source } => Some(source),
+            ParseError::Store { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ParseError {
+    fn from(source: rusqlite::Error) -> Self {
+        ParseError::Database { source }
+    }
+}
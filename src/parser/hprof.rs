@@ -1,168 +1,1086 @@
 use std::collections::HashMap;
-use std::ffi::{OsStr, OsString};
-use std::fs::File;
-use std::io;
-use std::io::{BufReader, Cursor, ErrorKind, SeekFrom};
-use std::path::PathBuf;
-use std::io::Error;
-use crate::parser::dump::get_heap_dump;
-use crate::parser::reader;
-use crate::parser::reader::{AllocSites, ControlSettings, CpuSamples, Frame, HeapSummary, Class, Reader, Thread, Trace, Utf8};
-use crate::io::channel::{Result, Byte, Int, Long};
+use std::fs;
+use std::io::Write;
+use std::thread;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::parser::segment::{read_segment_ids, read_segments, Segment, SegmentDump};
+use crate::parser::snapshot::Snapshot;
+use crate::parser::stack::{JavaThread, StackRecords, StackTrace};
+use crate::parser::timeline::{EventKind, Timeline};
+use crate::parser::dump::{get_type_name, read_heap_record, ArrayData, Dump, GcRoot, Heap, HeapRecord, HistogramEntry, HistogramKey, NormalObject, PrimitiveArray, Value};
+use crate::parser::reader::{AllocSite, AllocSites, CpuSamples, Frame, Class, Reader, Thread, Trace, Utf8};
+use crate::parser::progress::{NoProgress, Phase, Progress, PROGRESS_STEP};
+use crate::parser::visitor::{check_record_end, read_record, Visitor};
+use crate::parser::writer::{Emit, Writer};
+use crate::store::index;
+use crate::store::base::{Database, DB_FILE};
+use crate::store::checkpoint::{Checkpoint, Stage};
+use crate::store::ids::{CompactId, IdMap};
+use crate::store::index::IndexStore;
+use crate::store::objects::ObjectIndex;
+use crate::io::channel::{Byte, Channel, Int, Long};
+use crate::parser::error::{ParseError, Result};
 use derive_getters::Getters;
 
-const HPROF_HEADER_101: &str = "JAVA PROFILE 1.0.1";
-const HPROF_HEADER_102: &str = "JAVA PROFILE 1.0.2";
-
-const HPROF_UTF8: u8 = 0x01;
-const HPROF_LOAD_CLASS: u8 = 0x02;
-const HPROF_UNLOAD_CLASS: u8 = 0x03;
-const HPROF_FRAME: u8 = 0x04;
-const HPROF_TRACE: u8 = 0x05;
-const HPROF_ALLOC_SITES: u8 = 0x06;
-const HPROF_HEAP_SUMMARY: u8 = 0x07;
-const HPROF_START_THREAD: u8 = 0x0A;
-const HPROF_END_THREAD: u8 = 0x0B;
-const HPROF_HEAP_DUMP: u8 = 0x0C;
-const HPROF_CPU_SAMPLES: u8 = 0x0D;
-const HPROF_CONTROL_SETTINGS: u8 = 0x0E;
-
-const HPROF_HEAP_DUMP_SEGMENT: u8 = 0x1C;
-const HPROF_HEAP_DUMP_END: u8 = 0x2C;
+pub const HPROF_HEADER_101: &str = "JAVA PROFILE 1.0.1";
+pub const HPROF_HEADER_102: &str = "JAVA PROFILE 1.0.2";
+/// Android
+pub const HPROF_HEADER_103: &str = "JAVA PROFILE 1.0.3";
+/// 文件头版本字符串的最大长度（不含结尾的 0）
+pub const HPROF_HEADER_MAX_LEN: usize = 20;
+/// 记录头大小：tag (1) + time offset (4) + length (4)
+pub const HPROF_RECORD_HEADER_SIZE: u64 = 9;
+
+pub const HPROF_UTF8: u8 = 0x01;
+pub const HPROF_LOAD_CLASS: u8 = 0x02;
+pub const HPROF_UNLOAD_CLASS: u8 = 0x03;
+pub const HPROF_FRAME: u8 = 0x04;
+pub const HPROF_TRACE: u8 = 0x05;
+pub const HPROF_ALLOC_SITES: u8 = 0x06;
+pub const HPROF_HEAP_SUMMARY: u8 = 0x07;
+pub const HPROF_START_THREAD: u8 = 0x0A;
+pub const HPROF_END_THREAD: u8 = 0x0B;
+pub const HPROF_HEAP_DUMP: u8 = 0x0C;
+pub const HPROF_CPU_SAMPLES: u8 = 0x0D;
+pub const HPROF_CONTROL_SETTINGS: u8 = 0x0E;
+
+pub const HPROF_HEAP_DUMP_SEGMENT: u8 = 0x1C;
+pub const HPROF_HEAP_DUMP_END: u8 = 0x2C;
+
+// 索引中的事件类型
+const EVENT_CLASS_LOAD: u8 = 1;
+const EVENT_CLASS_UNLOAD: u8 = 2;
+const EVENT_THREAD_START: u8 = 3;
+const EVENT_THREAD_END: u8 = 4;
+const EVENT_HEAP_DUMP: u8 = 5;
 
 #[derive(Clone, Debug, Getters)]
 pub struct Hprof {
-    file_name: String,
+    /// 快照文件，大数组的元素按需从中读取
+    file_path: PathBuf,
     id_size: Long,
     version: String,
     timestamp: Long,
+    /// UTF8 常量
+    symbols: HashMap<Long, String>,
+    /// 每次堆转储（HEAP DUMP 或连续的 HEAP DUMP SEGMENT）各为一个快照，
+    /// 没有堆转储记录时为一个空快照；从索引加载时只打开对象索引
+    snapshots: Vec<Snapshot>,
+    /// 当前分析的快照序号
+    selected: usize,
+    /// stack trace serial number -> 调用栈
+    traces: HashMap<Int, StackTrace>,
+    /// 快照时刻的线程及其调用栈
+    threads: Vec<JavaThread>,
+    /// 栈帧、调用栈、START THREAD 记录及 class serial number -> class name ID
+    #[getter(skip)]
+    stacks: StackRecords,
+    /// class object ID -> class name ID
+    class_ids: HashMap<Long, Long>,
+    /// 分配点，hprof agent 输出
+    alloc_sites: Vec<AllocSites>,
+    /// CPU 采样，hprof agent 输出
+    cpu_samples: Vec<CpuSamples>,
+    /// 类加载/卸载与线程启动/结束事件
+    timeline: Timeline,
+    /// 文件被截断时的恢复信息
+    truncation: Option<Truncation>,
 }
 
-/// 解析堆转储快照文件
-pub fn read(file_path: &PathBuf, _: &PathBuf) -> Result<Hprof> {
-    let file_name = file_path.to_str().unwrap().to_string();
-    let mut reader = Reader::new(&file_path)?;
+/// # 两个快照间一个类的变化
+#[derive(Clone, Debug, Getters)]
+pub struct ClassDelta {
+    class_name: String,
+    before: HistogramEntry,
+    after: HistogramEntry,
+}
 
-    // 版本
-    let mut version = String::new();
-    for index in 1..20 {
-        if index > 20 {
-            break;
+impl ClassDelta {
+    /// 对象数量的变化
+    pub fn count_delta(&self) -> i64 {
+        *self.after.count() as i64 - *self.before.count() as i64
+    }
+
+    /// 浅大小的变化
+    pub fn bytes_delta(&self) -> i64 {
+        *self.after.bytes() as i64 - *self.before.bytes() as i64
+    }
+}
+
+/// # 截断文件的恢复信息
+#[derive(Clone, Debug, Getters)]
+pub struct Truncation {
+    /// 最后一条完整记录的结束位置，解析停止于此
+    offset: u64,
+    /// 文件实际大小
+    file_size: u64,
+    /// 被截断的记录类型
+    tag: Byte,
+    /// 按记录头声明的长度，文件末尾缺失的字节数
+    missing_bytes: u64,
+    /// 最后一条完整记录之后无法使用的字节数
+    discarded_bytes: u64,
+    /// 截断前已完整读取的记录数
+    records: u64,
+}
+
+impl Hprof {
+    /// 文件是否被截断，仅包含部分内容
+    pub fn is_partial(&self) -> bool {
+        self.truncation.is_some()
+    }
+
+    /// 根据 stack trace serial number 查找调用栈
+    pub fn get_trace(&self, serial_num: Int) -> Option<&StackTrace> {
+        self.traces.get(&serial_num)
+    }
+
+    /// 根据 class serial number 查找类名
+    pub fn get_class_name(&self, serial_num: Int) -> String {
+        match self.stacks.class_names.get(&serial_num) {
+            Some(name_id) => get_name_from_id(name_id, &self.symbols),
+            None => format!("unresolved class {}", serial_num),
         }
-        let byte = reader.read_char();
-        if byte == '\0' {
-            break;
+    }
+
+    /// 分配点的类名，基本类型数组没有对应的类时按元素类型命名
+    pub fn get_alloc_site_class(&self, site: &AllocSite) -> String {
+        if *site.array_type() != 0 && !self.stacks.class_names.contains_key(site.class_serial_num()) {
+            return format!("{}[]", get_type_name(*site.array_type()));
         }
-        version.push(byte);
+        self.get_class_name(*site.class_serial_num())
     }
 
-    if version != HPROF_HEADER_101 && version != HPROF_HEADER_102 {
-        eprintln!("不支持的版本: {}", version);
-        return Err(Error::new(ErrorKind::Unsupported, format!("不支持的版本: {}", version)));
-    };
+    /// 存活字节数最多的分配点，取最后一次 ALLOC SITES 记录
+    pub fn top_alloc_sites(&self, limit: usize) -> Vec<&AllocSite> {
+        let Some(latest) = self.alloc_sites.last() else {
+            return Vec::new();
+        };
+        let mut sites: Vec<&AllocSite> = latest.sites().iter().collect();
+        sites.sort_by(|a, b| b.live_bytes().cmp(a.live_bytes()));
+        sites.truncate(limit);
+        sites
+    }
 
-    // oop size
-    let id_size = reader.get_id_size();
+    /// 采样次数最多的调用栈，汇总全部 CPU SAMPLES 记录
+    ///
+    /// 返回 (stack trace serial number, 采样次数)
+    pub fn hot_traces(&self, limit: usize) -> Vec<(Int, u64)> {
+        let mut counts: HashMap<Int, u64> = HashMap::new();
+        for sample in self.cpu_samples.iter().flat_map(|samples| samples.samples()) {
+            *counts.entry(*sample.trace_serial_num()).or_default() += *sample.count() as u64;
+        }
+        let mut traces: Vec<(Int, u64)> = counts.into_iter().collect();
+        traces.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        traces.truncate(limit);
+        traces
+    }
 
-    // 时间戳（毫秒）
-    let timestamp = reader.get_timestamp();
+    /// 全部 CPU SAMPLES 记录的采样总数
+    pub fn total_cpu_samples(&self) -> u64 {
+        self.cpu_samples.iter().map(|samples| *samples.num() as u64).sum()
+    }
 
-    let mut symbols: HashMap<u64, String> = HashMap::new();
+    /// 当前快照
+    pub fn snapshot(&self) -> &Snapshot {
+        &self.snapshots[self.selected]
+    }
 
-    loop {
-        let header = reader.get_header();
-        if header.is_err() {
-            let err = header.unwrap_err();
-            if err.kind() != ErrorKind::UnexpectedEof {
-                // 非读取到文件末尾
-                eprintln!("解析异常: {}", err.to_string());
-                return Err(err);
-            }
-            break;
+    /// 当前快照的全部子记录，从索引加载时在第一次调用时读取
+    pub fn dump(&self) -> Result<&Dump> {
+        self.snapshot().dump()
+    }
+
+    /// 选择要分析的快照，序号从 0 开始
+    pub fn select_dump(&mut self, index: usize) -> bool {
+        if index >= self.snapshots.len() {
+            return false;
         }
-        let (tag, _, length) = header.unwrap();
-        match tag {
-            HPROF_UTF8 => {
-                // a UTF8-encoded name
-                let utf8 = reader.read::<Utf8>(length);
-                symbols.insert(*utf8.symbol_id(), utf8.name().to_string());
-            }
-            HPROF_LOAD_CLASS => {
-                // a newly loaded class
-                let class = reader.read::<Class>(length);
-                let class_name = get_name_from_id(class.name_id(), &symbols);
-                println!("{}", class_name)
-            }
-            HPROF_UNLOAD_CLASS => {
-                // an unloading class
-                let ser_num = reader.read_int();
-                println!("unload {}", ser_num)
-            }
-            HPROF_FRAME => {
-                // a Java stack frame
-                let frame = reader.read::<Frame>(length);
-                // 方法名
-                let method_name = symbols.get(frame.method_name());
-                // 方法签名
-                let method_sig = symbols.get(frame.method_sig());
-                // 源文件
-                let src_file = symbols.get(frame.src_file());
-                println!("frame -> {} {} {}", method_name.unwrap(), method_sig.unwrap(), src_file.unwrap());
+        self.selected = index;
+        true
+    }
+
+    /// 根据类对象ID查找类名
+    pub fn get_class_name_by_id(&self, class_id: Long) -> String {
+        match self.class_ids.get(&class_id) {
+            Some(name_id) => get_name_from_id(name_id, &self.symbols),
+            None => format!("unresolved class 0x{:x}", class_id),
+        }
+    }
+
+    /// 快照中按类名统计的对象数量与浅大小
+    pub fn histogram(&self, index: usize) -> HashMap<String, HistogramEntry> {
+        let mut histogram: HashMap<String, HistogramEntry> = HashMap::new();
+        let snapshot = &self.snapshots[index];
+        for (key, entry) in snapshot.histogram(self.id_size as Int) {
+            let name = match key {
+                HistogramKey::Class(class) => self.get_class_name_by_id(snapshot.id(class)),
+                HistogramKey::PrimitiveArray(element_type) => format!("{}[]", get_type_name(element_type)),
+            };
+            // 不同快照中同名类的对象ID可能不同，按类名合并
+            let total = histogram.entry(name).or_default();
+            *total = HistogramEntry::new(total.count() + entry.count(), total.bytes() + entry.bytes());
+        }
+        histogram
+    }
+
+    /// 比较两个快照，按浅大小变化量从大到小排列
+    pub fn compare_dumps(&self, from: usize, to: usize) -> Vec<ClassDelta> {
+        let before = self.histogram(from);
+        let after = self.histogram(to);
+        let mut names: Vec<&String> = before.keys().chain(after.keys()).collect();
+        names.sort();
+        names.dedup();
+        let mut deltas: Vec<ClassDelta> = names.into_iter().map(|name| ClassDelta {
+            class_name: name.clone(),
+            before: before.get(name).copied().unwrap_or_default(),
+            after: after.get(name).copied().unwrap_or_default(),
+        }).filter(|delta| delta.before != delta.after).collect();
+        deltas.sort_by_key(|delta| std::cmp::Reverse(delta.bytes_delta().unsigned_abs()));
+        deltas
+    }
+
+    /// 当前快照时刻已加载且未卸载的类名，按加载顺序排列
+    pub fn live_classes(&self) -> Vec<String> {
+        self.timeline.live_classes(self.selected).iter()
+            .map(|(_, name_id)| get_name_from_id(name_id, &self.symbols))
+            .collect()
+    }
+
+    /// 事件的文字描述
+    pub fn describe_event(&self, kind: &EventKind) -> String {
+        match kind {
+            EventKind::ClassLoad { serial_num, name_id, .. } =>
+                format!("加载类 #{} {}", serial_num, get_name_from_id(name_id, &self.symbols)),
+            EventKind::ClassUnload { serial_num } =>
+                format!("卸载类 #{} {}", serial_num, self.get_class_name(*serial_num)),
+            EventKind::ThreadStart { serial_num, name_id, .. } =>
+                format!("启动线程 #{} {}", serial_num, get_name_from_id(name_id, &self.symbols)),
+            EventKind::ThreadEnd { serial_num } => format!("结束线程 #{}", serial_num),
+            EventKind::HeapDump => "堆转储".to_string(),
+        }
+    }
+
+    /// 只保留指定堆中的类与对象，用于分析 Android 快照的某一个堆
+    pub fn retain_heap(&mut self, heap: Heap) {
+        for snapshot in self.snapshots.iter_mut() {
+            snapshot.retain_heap(heap);
+        }
+    }
+
+    /// 解析实例的字段名及字段值，按类、父类、父类的父类……的顺序排列
+    pub fn get_instance_fields(&self, obj: &NormalObject<CompactId>) -> Result<Vec<(String, Value)>> {
+        let fields = self.snapshot().classes()?.get_instance_fields(obj, self.id_size as Int)?;
+        Ok(fields.into_iter()
+            .map(|field| (get_name_from_id(field.name_id(), &self.symbols), field.value().clone()))
+            .collect())
+    }
+
+    /// 将解析结果写为 HPROF 文件，版本与原文件相同，可用于写出过滤后的快照
+    ///
+    /// UTF8 常量、栈帧及调用栈之后，按原顺序写出类加载/卸载、线程启动/结束及各次堆转储，最后是分配点与 CPU 采样。
+    /// 类常量池及 HEAP DUMP INFO 的堆名称未保留，不再写出
+    pub fn write<W: Write>(&self, out: W) -> Result<()> {
+        let mut writer = Writer::with_version(out, &self.version, self.id_size as Int, self.timestamp)?;
+        let mut source = Reader::new(&self.file_path)?;
+        source.set_id_size(self.id_size as Int);
+        writer.set_source(source);
+
+        let mut ids: Vec<&Long> = self.symbols.keys().collect();
+        ids.sort_unstable();
+        for id in ids {
+            writer.write(HPROF_UTF8, 0, &Utf8::new(*id, &self.symbols[id]))?;
+        }
+        let mut frames: Vec<&Frame> = self.stacks.frames.values().collect();
+        frames.sort_unstable_by_key(|frame| *frame.id());
+        for frame in frames {
+            writer.write(HPROF_FRAME, 0, frame)?;
+        }
+        let mut traces: Vec<&Trace> = self.stacks.traces.values().collect();
+        traces.sort_unstable_by_key(|trace| *trace.stack_trace_nr());
+        for trace in traces {
+            writer.write(HPROF_TRACE, 0, trace)?;
+        }
+        // START THREAD 记录与其事件的顺序相同
+        let mut threads = self.stacks.threads.iter();
+        let mut dumps = self.snapshots.iter().filter(|snapshot| !snapshot.is_empty());
+        for event in self.timeline.events() {
+            let time = *event.time();
+            match *event.kind() {
+                EventKind::ClassLoad { serial_num, class_id, name_id } =>
+                    writer.write(HPROF_LOAD_CLASS, time, &Class::new(serial_num, class_id, name_id))?,
+                EventKind::ClassUnload { serial_num } => writer.write(HPROF_UNLOAD_CLASS, time, &serial_num)?,
+                EventKind::ThreadStart { serial_num, thread_id, name_id } => match threads.next() {
+                    Some(thread) => writer.write(HPROF_START_THREAD, time, thread)?,
+                    None => writer.write(HPROF_START_THREAD, time, &Thread::new(serial_num, thread_id, name_id))?,
+                },
+                EventKind::ThreadEnd { serial_num } => writer.write(HPROF_END_THREAD, time, &serial_num)?,
+                EventKind::HeapDump => {
+                    if let Some(snapshot) = dumps.next() {
+                        writer.write_dump(snapshot.dump()?)?;
+                    }
+                }
             }
-            HPROF_TRACE => {
-                // a Java stack trace
-                let trace = reader.read::<Trace>(length);
-                // println!("{}", trace);
+        }
+        for snapshot in dumps {
+            writer.write_dump(snapshot.dump()?)?;
+        }
+        for sites in &self.alloc_sites {
+            writer.write(HPROF_ALLOC_SITES, 0, sites)?;
+        }
+        for samples in &self.cpu_samples {
+            writer.write(HPROF_CPU_SAMPLES, 0, samples)?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// 以名称解析调用栈及线程
+    fn resolve_stacks(&mut self) {
+        let symbols = &self.symbols;
+        self.traces = self.stacks.resolve_traces(|id| get_name_from_id(id, symbols));
+        let roots: Vec<GcRoot> = self.snapshots.iter().flat_map(|snapshot| snapshot.roots().iter().cloned()).collect();
+        self.threads = self.stacks.resolve_threads(&self.traces, &roots, |id| symbols.get(id).cloned());
+    }
+
+    /// 将解析结果保存为工作目录中的索引、对象索引及数据库，再次打开同一文件时由 `load` 直接加载
+    ///
+    /// 应在 `retain_heap` 等修改之前调用；源文件信息最后写入，保存中途失败时索引不会被使用。
+    /// 各阶段完成后记入清单，中断后再次保存时跳过已完成的阶段
+    pub fn save(&self, work_path: &Path) -> Result<()> {
+        self.save_with_progress(work_path, &NoProgress)
+    }
+
+    /// 同 `save`，并报告保存索引、生成对象索引及写入数据库的进度
+    pub fn save_with_progress(&self, work_path: &Path, progress: &dyn Progress) -> Result<()> {
+        let store = IndexStore::new(work_path);
+        let checkpoint = Checkpoint::open(work_path, &self.file_path)?;
+        store.invalidate()?;
+        let id_size = self.id_size as Int;
+
+        // 各次堆转储的子记录及对象索引，检查点中已保存的分段直接作为子记录索引，不再写入
+        let segments = checkpointed_segments(&checkpoint, self.snapshots.len())?;
+        let pending: Vec<_> = self.snapshots.iter().zip(segments).enumerate()
+            .filter(|(index, _)| !checkpoint.is_done(Stage::Dump(*index)))
+            .map(|(index, (snapshot, segments))| Ok((index, (snapshot.dump()?, segments))))
+            .collect::<Result<_>>()?;
+        let objects: usize = pending.iter().map(|(_, (dump, _))| dump.object_count()).sum();
+        let roots: usize = pending.iter().map(|(_, (dump, _))| dump.roots().len()).sum();
+        progress.start(Phase::Index, (roots + objects) as u64);
+        progress.start(Phase::ObjectIndex, objects as u64);
+        let result = thread::scope(|scope| {
+            let checkpoint = &checkpoint;
+            let handles: Vec<_> = pending.into_iter()
+                .map(|(index, (dump, segments))| {
+                    let dir = snapshot_path(work_path, index);
+                    scope.spawn(move || {
+                        let offsets = match segments {
+                            Some(segments) => index::link_dump_index(&dir, &segments, dump.ids(), id_size, progress)?,
+                            None => index::write_dump_index(&dir, dump, id_size, progress)?,
+                        };
+                        ObjectIndex::write(&dir, dump, &offsets, id_size)?;
+                        // 对象索引按堆转储报告进度
+                        progress.advance(Phase::ObjectIndex, dump.object_count() as u64);
+                        checkpoint.done(Stage::Dump(index))
+                    })
+                })
+                .collect();
+            handles.into_iter()
+                .try_for_each(|handle| handle.join().expect("堆转储索引保存线程异常退出"))
+        });
+        progress.finish(Phase::Index);
+        progress.finish(Phase::ObjectIndex);
+        result?;
+        if !checkpoint.is_done(Stage::Index) {
+            self.write_globals(&store.index_path())?;
+            checkpoint.done(Stage::Index)?;
+        }
+        if !checkpoint.is_done(Stage::Database) {
+            // 中断时数据库可能只写入了一部分
+            let path = work_path.join(DB_FILE);
+            if path.exists() {
+                fs::remove_file(&path).map_err(|err| ParseError::store(err, &path))?;
             }
-            HPROF_ALLOC_SITES => {
-                // a set of heap allocation sites, obtained after GC
-                let sites = reader.read::<AllocSites>(length);
+            Database::open(&path)?.import(self, progress)?;
+            checkpoint.done(Stage::Database)?;
+        }
+        store.commit(&self.file_path)?;
+        checkpoint.finish()
+    }
 
+    /// 全局索引：UTF8 常量、线程及调用栈、类、分配点、CPU 采样、事件、截断信息及各次堆转储的 GC root
+    fn write_globals(&self, path: &Path) -> Result<()> {
+        let id_size = self.id_size as Int;
+        let (mut out, mut encoder) = index::create(path, id_size)?;
+        encoder.put_bytes(self.version.as_bytes());
+        encoder.put_byte(0);
+        encoder.put_long(self.timestamp);
+
+        encoder.put_int(self.symbols.len() as Int);
+        for (id, name) in &self.symbols {
+            encoder.put_int(id_size + name.len() as Int);
+            Utf8::new(*id, name).emit(&mut encoder)?;
+        }
+        encoder.put_int(self.stacks.frames.len() as Int);
+        for frame in self.stacks.frames.values() {
+            frame.emit(&mut encoder)?;
+        }
+        encoder.put_int(self.stacks.traces.len() as Int);
+        for trace in self.stacks.traces.values() {
+            trace.emit(&mut encoder)?;
+        }
+        encoder.put_int(self.stacks.threads.len() as Int);
+        for thread in &self.stacks.threads {
+            thread.emit(&mut encoder)?;
+        }
+        encoder.put_int(self.stacks.class_names.len() as Int);
+        for (serial_num, name_id) in &self.stacks.class_names {
+            encoder.put_int(*serial_num);
+            encoder.put_id(*name_id);
+        }
+        encoder.put_int(self.class_ids.len() as Int);
+        for (class_id, name_id) in &self.class_ids {
+            encoder.put_id(*class_id);
+            encoder.put_id(*name_id);
+        }
+        encoder.put_int(self.alloc_sites.len() as Int);
+        for sites in &self.alloc_sites {
+            sites.emit(&mut encoder)?;
+        }
+        encoder.put_int(self.cpu_samples.len() as Int);
+        for samples in &self.cpu_samples {
+            samples.emit(&mut encoder)?;
+        }
+        encoder.put_int(self.timeline.events().len() as Int);
+        for event in self.timeline.events() {
+            encoder.put_long(*event.record());
+            encoder.put_int(*event.time());
+            match *event.kind() {
+                EventKind::ClassLoad { serial_num, class_id, name_id } => {
+                    encoder.put_byte(EVENT_CLASS_LOAD);
+                    encoder.put_int(serial_num);
+                    encoder.put_id(class_id);
+                    encoder.put_id(name_id);
+                }
+                EventKind::ClassUnload { serial_num } => {
+                    encoder.put_byte(EVENT_CLASS_UNLOAD);
+                    encoder.put_int(serial_num);
+                }
+                EventKind::ThreadStart { serial_num, thread_id, name_id } => {
+                    encoder.put_byte(EVENT_THREAD_START);
+                    encoder.put_int(serial_num);
+                    encoder.put_id(thread_id);
+                    encoder.put_id(name_id);
+                }
+                EventKind::ThreadEnd { serial_num } => {
+                    encoder.put_byte(EVENT_THREAD_END);
+                    encoder.put_int(serial_num);
+                }
+                EventKind::HeapDump => encoder.put_byte(EVENT_HEAP_DUMP),
             }
-            HPROF_HEAP_SUMMARY => {
-                // heap summary
-                let summary = reader.read::<HeapSummary>(length);
-                println!("summary: {}", summary.live())
+        }
+        match &self.truncation {
+            None => encoder.put_byte(0),
+            Some(truncation) => {
+                encoder.put_byte(1);
+                encoder.put_long(truncation.offset);
+                encoder.put_long(truncation.file_size);
+                encoder.put_byte(truncation.tag);
+                encoder.put_long(truncation.missing_bytes);
+                encoder.put_long(truncation.discarded_bytes);
+                encoder.put_long(truncation.records);
             }
-            HPROF_START_THREAD => {
-                // a newly started thread.
-                let thread = reader.read::<Thread>(length);
-                println!("0x{}", format!("{:x}", thread.id()));
+        }
+        encoder.put_int(self.snapshots.len() as Int);
+        for snapshot in &self.snapshots {
+            encoder.put_int(snapshot.roots().len() as Int);
+            for root in snapshot.roots() {
+                root.emit(&mut encoder)?;
             }
-            HPROF_END_THREAD => {
-                // a terminating thread.
-                let thread_serial_num = reader.read_int();
+        }
+        index::flush(&mut out, &mut encoder)?;
+        out.finish()
+    }
+
+    /// 读取基本类型数组的元素
+    pub fn get_array_elements<I: Clone>(&self, array: &PrimitiveArray<I>) -> Result<ArrayData> {
+        match array.elements() {
+            ArrayData::Lazy { .. } => {
+                let mut reader = Reader::new(&self.file_path)?;
+                array.load(&mut reader)
             }
-            HPROF_CPU_SAMPLES => {
-                // a set of sample traces of running threads
-                let samples = reader.read::<CpuSamples>(length);
-                println!("cpu samples: {}", samples.num())
+            elements => Ok(elements.clone()),
+        }
+    }
+}
+
+/// 快照在工作目录中的数据目录，每个堆转储一个
+pub fn snapshot_path(work_path: &Path, index: usize) -> PathBuf {
+    work_path.join(format!("dump-{}", index))
+}
+
+/// 解析堆转储快照文件
+///
+/// `recover` 为 `true` 时，文件被截断不视为错误：解析停止在最后一条完整的记录处，
+/// 此前的内容照常可用，快照标记为不完整
+pub fn read(file_path: &Path, recover: bool) -> Result<Hprof> {
+    read_with_progress(file_path, recover, &NoProgress)
+}
+
+/// 解析堆转储快照文件，各阶段的进度报告给 `progress`
+pub fn read_with_progress(file_path: &Path, recover: bool, progress: &dyn Progress) -> Result<Hprof> {
+    let scan = scan_records(file_path, recover, progress)?;
+    read_heap_dumps(file_path, scan, recover, progress, None)
+}
+
+/// 解析堆转储快照文件，在工作目录中保存检查点
+///
+/// 扫描记录及每个堆转储分段完成后即写入检查点，中断后再次调用时从检查点继续，
+/// 只解析尚未完成的分段。源文件变化后检查点作废
+pub fn read_resumable(file_path: &Path, work_path: &Path, recover: bool, progress: &dyn Progress) -> Result<Hprof> {
+    let checkpoint = Checkpoint::open(work_path, file_path)?;
+    load_seek_index(file_path, &IndexStore::new(work_path))?;
+    let scan = if checkpoint.is_done(Stage::Records) {
+        Scan::load(&checkpoint, file_path)?
+    } else {
+        let scan = scan_records(file_path, recover, progress)?;
+        scan.save(&checkpoint)?;
+        checkpoint.done(Stage::Records)?;
+        scan
+    };
+    // 检查点由 --recover 解析截断的文件时生成
+    if let (Some(offset), false) = (scan.truncated_at, recover) {
+        return Err(ParseError::Truncated { offset, tag: Some(scan.record_tag) });
+    }
+    let hprof = read_heap_dumps(file_path, scan, recover, progress, Some(&checkpoint))?;
+    checkpoint.done(Stage::HeapDump)?;
+    Ok(hprof)
+}
+
+/// 第一遍顺序扫描的结果，堆转储分段只记录了位置
+struct Scan {
+    /// 除堆转储外的全部内容，`snapshots` 为空
+    hprof: Hprof,
+    segments: Vec<Segment>,
+    file_size: u64,
+    /// 扫描因文件截断停止时，最后一条完整记录的结束位置
+    truncated_at: Option<u64>,
+    /// 已完整读取的记录数
+    records: u64,
+    /// 截断时正在读取的记录类型
+    record_tag: Byte,
+    /// 截断时正在读取的记录按其长度应当结束的位置
+    record_end: u64,
+}
+
+impl Scan {
+    /// 分段列表在前，保存索引时由 `read_segment_list` 单独读取
+    fn save(&self, checkpoint: &Checkpoint) -> Result<()> {
+        self.hprof.write_globals(&checkpoint.records_path())?;
+        let (mut out, mut encoder) = index::create(&checkpoint.segments_path(), self.hprof.id_size as Int)?;
+        encoder.put_int(self.segments.len() as Int);
+        for segment in &self.segments {
+            encoder.put_int(*segment.dump() as Int);
+            encoder.put_long(*segment.offset());
+            encoder.put_int(*segment.length());
+        }
+        encoder.put_long(self.file_size);
+        encoder.put_long(self.records);
+        encoder.put_byte(self.record_tag);
+        encoder.put_long(self.record_end);
+        match self.truncated_at {
+            None => encoder.put_byte(0),
+            Some(offset) => {
+                encoder.put_byte(1);
+                encoder.put_long(offset);
             }
-            HPROF_CONTROL_SETTINGS => {
-                // the settings of on/off switches
-                let settings = reader.read::<ControlSettings>(length);
-                println!("settings: {}", settings.flags())
+        }
+        index::flush(&mut out, &mut encoder)?;
+        out.finish()
+    }
+
+    fn load(checkpoint: &Checkpoint, file_path: &Path) -> Result<Self> {
+        let hprof = read_globals(&checkpoint.records_path(), file_path)?;
+        let mut reader = index::open(&checkpoint.segments_path())?;
+        let segments = read_segment_list(&mut reader)?;
+        let file_size = reader.read_long()?;
+        let records = reader.read_long()?;
+        let record_tag = reader.read_byte()?;
+        let record_end = reader.read_long()?;
+        let truncated_at = match reader.read_byte()? {
+            0 => None,
+            _ => Some(reader.read_long()?),
+        };
+        Ok(Scan { hprof, segments, file_size, truncated_at, records, record_tag, record_end })
+    }
+}
+
+fn read_segment_list(reader: &mut Reader) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    for _ in 0..reader.read_int()? {
+        let dump = reader.read_int()? as usize;
+        let offset = reader.read_long()?;
+        segments.push(Segment::new(dump, offset, reader.read_int()?));
+    }
+    Ok(segments)
+}
+
+/// 检查点中每次堆转储已保存的分段文件，按在源文件中的顺序排列
+///
+/// 只有全部分段都已保存时才能直接作为堆转储索引，否则为 `None`，由内存中的结果重新写入
+fn checkpointed_segments(checkpoint: &Checkpoint, dumps: usize) -> Result<Vec<Option<Vec<PathBuf>>>> {
+    let path = checkpoint.segments_path();
+    if !path.is_file() {
+        return Ok(vec![None; dumps]);
+    }
+    let segments = read_segment_list(&mut index::open(&path)?)?;
+    Ok((0..dumps).map(|dump| {
+        let paths: Vec<PathBuf> = segments.iter()
+            .filter(|segment| *segment.dump() == dump)
+            .map(|segment| checkpoint.is_segment_done(*segment.offset())
+                .then(|| checkpoint.segment_path(*segment.offset()))
+                .filter(|path| path.is_file()))
+            .collect::<Option<_>>()?;
+        (!paths.is_empty()).then_some(paths)
+    }).collect())
+}
+
+/// 第一遍顺序读取，堆转储分段只记录位置
+fn scan_records(file_path: &Path, recover: bool, progress: &dyn Progress) -> Result<Scan> {
+    let mut reader = Reader::new(file_path)?;
+    let (version, id_size, timestamp) = read_header(&mut reader)?;
+    let position = reader.position()?;
+    reader.mark_complete(position);
+
+    let mut ctx = Context::default();
+    progress.start(Phase::Records, reader.size());
+    let result = read_records(&mut reader, &mut ctx, recover, progress);
+    progress.finish(Phase::Records);
+    let truncated_at = match result {
+        Ok(()) => None,
+        Err(ParseError::Truncated { .. }) if recover => Some(reader.complete_offset()),
+        Err(err) => return Err(err),
+    };
+
+    let hprof = Hprof {
+        file_path: file_path.to_path_buf(),
+        id_size: id_size as u64,
+        version,
+        timestamp,
+        symbols: ctx.symbols,
+        snapshots: vec![Snapshot::Parsed(Dump::default()); ctx.dumps.max(1)],
+        selected: 0,
+        traces: HashMap::new(),
+        threads: Vec::new(),
+        stacks: ctx.stacks,
+        class_ids: ctx.class_ids,
+        alloc_sites: ctx.alloc_sites,
+        cpu_samples: ctx.cpu_samples,
+        timeline: ctx.timeline,
+        truncation: None,
+    };
+    Ok(Scan {
+        hprof,
+        segments: ctx.segments,
+        file_size: reader.size(),
+        truncated_at,
+        records: ctx.records,
+        record_tag: ctx.record_tag,
+        record_end: ctx.record_end,
+    })
+}
+
+/// 并行解析堆转储分段，按文件顺序合并
+///
+/// 有 `checkpoint` 时，已完成的分段从检查点加载，新完成的分段写入检查点
+fn read_heap_dumps(file_path: &Path, scan: Scan, recover: bool, progress: &dyn Progress,
+                   checkpoint: Option<&Checkpoint>) -> Result<Hprof> {
+    let Scan { mut hprof, segments, file_size, mut truncated_at, records, record_tag, record_end } = scan;
+    let id_size = hprof.id_size as Int;
+    let ids = read_ids(file_path, id_size, &segments, hprof.snapshots.len(), progress, checkpoint)?;
+    let mut dumps: Vec<Dump> = ids.iter().map(|ids| Dump::new(ids.clone())).collect();
+    let done = |segment: &Segment| checkpoint.is_some_and(|checkpoint| checkpoint.is_segment_done(*segment.offset()));
+    let pending: Vec<Segment> = segments.iter().filter(|segment| !done(segment)).copied().collect();
+
+    let total = pending.iter().map(|segment| *segment.length() as u64).sum();
+    progress.start(Phase::HeapDump, total);
+    let results = read_segments(file_path, id_size, &pending, &ids, progress, &|segment, result| {
+        match checkpoint {
+            // 被截断的分段不保存，再次解析时仍按 recover 处理
+            Some(checkpoint) if result.error.is_none() => {
+                index::write_dump(&checkpoint.segment_path(*segment.offset()), &result.dump, id_size, &NoProgress)?;
+                checkpoint.segment_done(*segment.offset())
             }
-            HPROF_HEAP_DUMP => {
-                // denote a heap dump
-                // let _ = get_heap_dump(&mut reader, length);
-                reader.skip(length);
+            _ => Ok(()),
+        }
+    });
+    progress.finish(Phase::HeapDump);
+    let mut results = results?.into_iter();
+
+    for segment in &segments {
+        let result = match checkpoint {
+            Some(checkpoint) if done(segment) => SegmentDump {
+                dump: index::read_dump(&checkpoint.segment_path(*segment.offset()), &ids[*segment.dump()])?,
+                error: None,
+                complete_offset: *segment.offset() + *segment.length() as u64,
+            },
+            _ => results.next().expect("分段解析结果与分段数不一致"),
+        };
+        dumps[*segment.dump()].merge(result.dump);
+        match result.error {
+            None => {}
+            // 只有最后一个分段可能被截断，保留截断前的子记录
+            Some(ParseError::Truncated { .. }) if recover => truncated_at = Some(result.complete_offset),
+            Some(err) => return Err(err),
+        }
+    }
+
+    hprof.snapshots = dumps.into_iter().map(Snapshot::Parsed).collect();
+    hprof.truncation = truncated_at.map(|offset| Truncation {
+        offset,
+        file_size,
+        tag: record_tag,
+        missing_bytes: record_end.saturating_sub(file_size),
+        discarded_bytes: file_size - offset,
+        records,
+    });
+    hprof.resolve_stacks();
+    Ok(hprof)
+}
+
+/// 收集各次堆转储中的对象 ID，生成其 `IdMap`
+///
+/// 有 `checkpoint` 时映射表保存在检查点中，再次解析时直接打开
+fn read_ids(file_path: &Path, id_size: Int, segments: &[Segment], dumps: usize, progress: &dyn Progress,
+            checkpoint: Option<&Checkpoint>) -> Result<Vec<Arc<IdMap>>> {
+    if let Some(checkpoint) = checkpoint.filter(|checkpoint| checkpoint.is_done(Stage::Ids)) {
+        return (0..dumps).map(|dump| Ok(Arc::new(IdMap::open(&checkpoint.ids_path(dump))?))).collect();
+    }
+    let total = segments.iter().map(|segment| *segment.length() as u64).sum();
+    progress.start(Phase::Ids, total);
+    let result = read_segment_ids(file_path, id_size, segments, progress);
+    progress.finish(Phase::Ids);
+    let mut ids = vec![Vec::new(); dumps];
+    for (segment, segment_ids) in segments.iter().zip(result?) {
+        ids[*segment.dump()].extend(segment_ids);
+    }
+    let maps = ids.into_iter().enumerate().map(|(dump, ids)| {
+        let map = IdMap::new(ids)?;
+        match checkpoint {
+            Some(checkpoint) => {
+                let path = checkpoint.ids_path(dump);
+                map.save(&path)?;
+                Ok(Arc::new(IdMap::open(&path)?))
             }
-            HPROF_HEAP_DUMP_SEGMENT => {
-                // denote a heap dump segment
-                reader.skip(length);
+            None => Ok(Arc::new(map)),
+        }
+    }).collect::<Result<Vec<_>>>()?;
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.done(Stage::Ids)?;
+    }
+    Ok(maps)
+}
+
+/// 加载工作目录中的索引，没有索引或索引与文件不一致时返回 `None`
+///
+/// 只读取全局索引并以内存映射打开各次堆转储的对象索引，不读取堆转储索引；
+/// 对象的子记录在用到时才读取，见 `Snapshot`
+pub fn load(file_path: &Path, work_path: &Path) -> Result<Option<Hprof>> {
+    let store = IndexStore::new(work_path);
+    if !store.is_fresh(file_path) {
+        return Ok(None);
+    }
+    load_seek_index(file_path, &store)?;
+    let mut hprof = read_globals(&store.index_path(), file_path)?;
+
+    hprof.snapshots = hprof.snapshots.iter().enumerate()
+        .map(|(index, snapshot)| Snapshot::open(&snapshot_path(work_path, index), snapshot.roots().to_vec()))
+        .collect::<Result<_>>()?;
+    hprof.resolve_stacks();
+    Ok(Some(hprof))
+}
+
+/// 源文件为 gzip 压缩时，从工作目录加载恢复点，没有时生成并保存，此后打开文件不必再全部解压
+fn load_seek_index(file_path: &Path, store: &IndexStore) -> Result<()> {
+    let path = store.seek_index_path();
+    if Channel::load_seek_index(file_path, &path).map_err(|err| ParseError::from_io(err, 0, None))? {
+        Channel::save_seek_index(file_path, &path).map_err(|err| ParseError::store(err, &path))?;
+    }
+    Ok(())
+}
+
+/// 读取 `write_globals` 保存的全局索引，其中的堆转储只有 GC root
+fn read_globals(path: &Path, file_path: &Path) -> Result<Hprof> {
+    let mut reader = index::open(path)?;
+    let id_size = reader.id_size();
+    let version = reader.read_cstr(HPROF_HEADER_MAX_LEN)?;
+    let timestamp = reader.read_long()?;
+
+    let mut symbols = HashMap::new();
+    for _ in 0..reader.read_int()? {
+        let len = reader.read_int()?;
+        let utf8 = reader.read::<Utf8>(len)?;
+        symbols.insert(*utf8.symbol_id(), utf8.name().to_string());
+    }
+    let mut stacks = StackRecords::default();
+    for _ in 0..reader.read_int()? {
+        let frame = reader.read::<Frame>(0)?;
+        stacks.frames.insert(*frame.id(), frame);
+    }
+    for _ in 0..reader.read_int()? {
+        let trace = reader.read::<Trace>(0)?;
+        stacks.traces.insert(*trace.stack_trace_nr(), trace);
+    }
+    for _ in 0..reader.read_int()? {
+        stacks.threads.push(reader.read::<Thread>(0)?);
+    }
+    for _ in 0..reader.read_int()? {
+        let serial_num = reader.read_int()?;
+        stacks.class_names.insert(serial_num, reader.get_id()?);
+    }
+    let mut class_ids = HashMap::new();
+    for _ in 0..reader.read_int()? {
+        let class_id = reader.get_id()?;
+        class_ids.insert(class_id, reader.get_id()?);
+    }
+    let mut alloc_sites = Vec::new();
+    for _ in 0..reader.read_int()? {
+        alloc_sites.push(reader.read::<AllocSites>(0)?);
+    }
+    let mut cpu_samples = Vec::new();
+    for _ in 0..reader.read_int()? {
+        cpu_samples.push(reader.read::<CpuSamples>(0)?);
+    }
+    let mut timeline = Timeline::default();
+    for _ in 0..reader.read_int()? {
+        let record = reader.read_long()?;
+        let time = reader.read_int()?;
+        let kind = match reader.read_byte()? {
+            EVENT_CLASS_LOAD => EventKind::ClassLoad {
+                serial_num: reader.read_int()?,
+                class_id: reader.get_id()?,
+                name_id: reader.get_id()?,
+            },
+            EVENT_CLASS_UNLOAD => EventKind::ClassUnload { serial_num: reader.read_int()? },
+            EVENT_THREAD_START => EventKind::ThreadStart {
+                serial_num: reader.read_int()?,
+                thread_id: reader.get_id()?,
+                name_id: reader.get_id()?,
+            },
+            EVENT_THREAD_END => EventKind::ThreadEnd { serial_num: reader.read_int()? },
+            EVENT_HEAP_DUMP => EventKind::HeapDump,
+            kind => return Err(ParseError::malformed(format!("索引中未知的事件类型: {}", kind))),
+        };
+        timeline.push(record, time, kind);
+    }
+    let truncation = match reader.read_byte()? {
+        0 => None,
+        _ => Some(Truncation {
+            offset: reader.read_long()?,
+            file_size: reader.read_long()?,
+            tag: reader.read_byte()?,
+            missing_bytes: reader.read_long()?,
+            discarded_bytes: reader.read_long()?,
+            records: reader.read_long()?,
+        }),
+    };
+
+    let mut snapshots = Vec::new();
+    for _ in 0..reader.read_int()? {
+        let mut dump = Dump::default();
+        for _ in 0..reader.read_int()? {
+            match read_heap_record(&mut reader)? {
+                record @ HeapRecord::Root(_) => dump.push(record),
+                _ => return Err(ParseError::malformed("全局索引中的 GC root 无法识别".to_string())),
             }
-            HPROF_HEAP_DUMP_END => {
-                //  denotes the end of a heap dump
-                reader.skip(length);
+        }
+        snapshots.push(Snapshot::Parsed(dump));
+    }
+
+    Ok(Hprof {
+        file_path: file_path.to_path_buf(),
+        id_size: id_size as u64,
+        version,
+        timestamp,
+        symbols,
+        snapshots,
+        selected: 0,
+        traces: HashMap::new(),
+        threads: Vec::new(),
+        stacks,
+        class_ids,
+        alloc_sites,
+        cpu_samples,
+        timeline,
+        truncation,
+    })
+}
+
+/// 删除工作目录中的索引及检查点
+pub fn clear_index(work_path: &Path) -> Result<()> {
+    IndexStore::new(work_path).clear()?;
+    Checkpoint::clear(work_path)
+}
+
+/// 读取文件头，返回版本、id 大小、时间戳（毫秒）
+pub fn read_header(reader: &mut Reader) -> Result<(String, Int, Long)> {
+    // 版本
+    let version = reader.read_cstr(HPROF_HEADER_MAX_LEN)?;
+
+    if ![HPROF_HEADER_101, HPROF_HEADER_102, HPROF_HEADER_103].contains(&version.as_str()) {
+        return Err(ParseError::UnsupportedVersion { version });
+    };
+
+    // oop size
+    let id_size = reader.get_id_size()?;
+
+    // 时间戳（毫秒）
+    let timestamp = reader.get_timestamp()?;
+    Ok((version, id_size, timestamp))
+}
+
+/// 解析过程中累积的内容
+#[derive(Default)]
+struct Context {
+    symbols: HashMap<Long, String>,
+    /// 堆转储分段的位置
+    segments: Vec<Segment>,
+    /// 线程及调用栈记录
+    stacks: StackRecords,
+    alloc_sites: Vec<AllocSites>,
+    cpu_samples: Vec<CpuSamples>,
+    timeline: Timeline,
+    /// class object ID -> class name ID
+    class_ids: HashMap<Long, Long>,
+    /// 是否处于由 HEAP DUMP SEGMENT 组成的堆转储中
+    in_dump: bool,
+    /// 已开始的堆转储数
+    dumps: usize,
+    /// 已完整读取的记录数
+    records: u64,
+    /// 正在读取的记录类型
+    record_tag: Byte,
+    /// 正在读取的记录相对文件头时间戳的微秒数
+    record_time: Int,
+    /// 正在读取的记录按其长度应当结束的位置
+    record_end: u64,
+}
+
+impl Context {
+    /// 记录当前记录对应的事件
+    fn event(&mut self, kind: EventKind) {
+        self.timeline.push(self.records, self.record_time, kind);
+    }
+}
+
+/// 依次读取文件头之后的全部记录
+///
+/// 进度包括文件头，结束时报告到文件末尾；出错时剩余部分不再读取，同样计入进度。
+/// 记录内容与长度不一致时，`recover` 为 `true` 则按长度跳到下一条记录
+fn read_records(reader: &mut Reader, ctx: &mut Context, recover: bool, progress: &dyn Progress) -> Result<()> {
+    let mut reported = 0;
+    let mut read = || -> Result<()> {
+        loop {
+            let start = reader.complete_offset();
+            // 至少还需要一个完整的记录头
+            ctx.record_end = start + HPROF_RECORD_HEADER_SIZE;
+            let (tag, time, length) = match reader.get_header()? {
+                Some(header) => header,
+                // 读取到文件末尾
+                None => return Ok(()),
+            };
+            ctx.record_tag = tag;
+            ctx.record_time = time;
+            ctx.record_end += length as u64;
+            read_record(reader, tag, length, ctx)?;
+            match check_record_end(reader, tag, length, ctx.record_end) {
+                Ok(()) => {}
+                // 以记录头声明的长度定位下一条记录
+                Err(ParseError::Malformed { .. }) if recover && ctx.record_end <= reader.size() => {
+                    reader.seek(ctx.record_end)?;
+                }
+                Err(ParseError::Malformed { .. }) if recover => {
+                    return Err(ParseError::Truncated { offset: ctx.record_end, tag: Some(tag) });
+                }
+                Err(err) => return Err(err),
             }
-            _ => {
-                reader.skip(length);
+            ctx.records += 1;
+            let position = reader.position()?;
+            reader.mark_complete(position);
+            if position - reported >= PROGRESS_STEP {
+                progress.advance(Phase::Records, position - reported);
+                reported = position;
             }
         }
+    };
+    let result = read();
+    progress.advance(Phase::Records, reader.size().saturating_sub(reported));
+    result
+}
+
+impl Visitor for Context {
+    fn on_utf8(&mut self, utf8: Utf8) {
+        self.symbols.insert(*utf8.symbol_id(), utf8.name().to_string());
+    }
+
+    fn on_load_class(&mut self, class: Class) {
+        self.stacks.class_names.insert(*class.serial_num(), *class.name_id());
+        self.class_ids.insert(*class.id(), *class.name_id());
+        self.event(EventKind::ClassLoad {
+            serial_num: *class.serial_num(),
+            class_id: *class.id(),
+            name_id: *class.name_id(),
+        });
+    }
+
+    fn on_unload_class(&mut self, serial_num: Int) {
+        self.event(EventKind::ClassUnload { serial_num });
+    }
+
+    fn on_frame(&mut self, frame: Frame) {
+        self.stacks.frames.insert(*frame.id(), frame);
+    }
+
+    fn on_trace(&mut self, trace: Trace) {
+        self.stacks.traces.insert(*trace.stack_trace_nr(), trace);
+    }
+
+    fn on_alloc_sites(&mut self, sites: AllocSites) {
+        self.alloc_sites.push(sites);
+    }
+
+    fn on_start_thread(&mut self, thread: Thread) {
+        self.event(EventKind::ThreadStart {
+            serial_num: *thread.thread_serial_num(),
+            thread_id: *thread.id(),
+            name_id: *thread.t_name_index(),
+        });
+        self.stacks.threads.push(thread);
+    }
+
+    fn on_end_thread(&mut self, serial_num: Int) {
+        self.event(EventKind::ThreadEnd { serial_num });
+    }
+
+    fn on_cpu_samples(&mut self, samples: CpuSamples) {
+        self.cpu_samples.push(samples);
+    }
+
+    /// 第一遍只记录分段的位置，子记录随后并行解析
+    fn on_heap_dump(&mut self, tag: Byte, offset: u64, length: Int) -> bool {
+        if tag == HPROF_HEAP_DUMP || !self.in_dump {
+            self.event(EventKind::HeapDump);
+            self.dumps += 1;
+        }
+        self.in_dump = tag == HPROF_HEAP_DUMP_SEGMENT;
+        self.segments.push(Segment::new(self.dumps - 1, offset, length));
+        false
     }
 
-    Ok(Hprof { file_name, id_size: id_size as u64, version, timestamp })
+    fn on_heap_dump_end(&mut self) {
+        self.in_dump = false;
+    }
 }
 
 fn get_name_from_id(id: &Long, symbols: &HashMap<Long, String>) -> String {
@@ -173,10 +1091,435 @@ fn get_name_from_id(id: &Long, symbols: &HashMap<Long, String>) -> String {
     let name = symbols.get(id);
     if name.is_none() {
         let mut name = String::from("unresolved name ");
-        name.push_str(&*id.to_string());
+        name.push_str(&id.to_string());
         return name;
     }
 
     let name = name.unwrap();
-    name.replace("/", ".")
-}
\ No newline at end of file
+    name.replace('/', ".")
+}
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::parser::dump::{ClassObject, HeapRecord, INT_TYPE};
+    use crate::parser::writer::HprofBuilder;
+    use super::*;
+
+    /// 文件头：JAVA PROFILE 1.0.2，id 大小 4
+    fn header() -> Vec<u8> {
+        let mut data = HPROF_HEADER_102.as_bytes().to_vec();
+        data.push(0);
+        data.extend_from_slice(&4u32.to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        data
+    }
+
+    /// 追加一条记录
+    fn push_record(data: &mut Vec<u8>, tag: Byte, body: &[u8]) {
+        data.push(tag);
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        data.extend_from_slice(body);
+    }
+
+    #[test]
+    fn test_alloc_sites_and_cpu_samples() {
+        let mut data = header();
+        let mut body = 7u32.to_be_bytes().to_vec();
+        body.extend_from_slice(b"java/lang/String");
+        push_record(&mut data, HPROF_UTF8, &body);
+        let body: Vec<u8> = [1u32, 100, 0, 7].iter().flat_map(|v| v.to_be_bytes()).collect();
+        push_record(&mut data, HPROF_LOAD_CLASS, &body);
+
+        let mut body = 0u16.to_be_bytes().to_vec();
+        body.extend_from_slice(&0u32.to_be_bytes()); // cutoff ratio
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend_from_slice(&0u32.to_be_bytes());
+        body.extend_from_slice(&0u64.to_be_bytes());
+        body.extend_from_slice(&0u64.to_be_bytes());
+        body.extend_from_slice(&2u32.to_be_bytes());
+        // array type, class serial, trace serial, live bytes, live inst, bytes allocated, inst allocated
+        for (array_type, class_serial, live_bytes) in [(0u8, 1u32, 64u32), (10, 9, 4096)] {
+            body.push(array_type);
+            for value in [class_serial, 5, live_bytes, 1, live_bytes, 1] {
+                body.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        push_record(&mut data, HPROF_ALLOC_SITES, &body);
+
+        let body: Vec<u8> = [10u32, 2, 3, 5, 7, 6].iter().flat_map(|v| v.to_be_bytes()).collect();
+        push_record(&mut data, HPROF_CPU_SAMPLES, &body);
+        let body: Vec<u8> = [5u32, 1, 5, 5].iter().flat_map(|v| v.to_be_bytes()).collect();
+        push_record(&mut data, HPROF_CPU_SAMPLES, &body);
+
+        let path = std::env::temp_dir().join(format!("jsnap-sites-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let hprof = read(&path, false);
+        let _ = fs::remove_file(&path);
+        let hprof = hprof.unwrap();
+
+        let sites = hprof.top_alloc_sites(10);
+        assert_eq!(sites.len(), 2);
+        assert_eq!(hprof.get_alloc_site_class(sites[0]), "int[]");
+        assert_eq!(hprof.get_alloc_site_class(sites[1]), "java.lang.String");
+        assert_eq!(hprof.hot_traces(2), [(5, 8), (6, 7)]);
+        assert_eq!(hprof.total_cpu_samples(), 15);
+    }
+
+    #[test]
+    fn test_compare_dumps() {
+        let mut data = header();
+        let mut body = 7u32.to_be_bytes().to_vec();
+        body.extend_from_slice(b"Foo");
+        push_record(&mut data, HPROF_UTF8, &body);
+        let body: Vec<u8> = [1u32, 100, 0, 7].iter().flat_map(|v| v.to_be_bytes()).collect();
+        push_record(&mut data, HPROF_LOAD_CLASS, &body);
+        // 两次堆转储，分别有 1 个和 3 个 Foo 实例，每个实例 4 字节
+        for count in [1u32, 3] {
+            let mut body = Vec::new();
+            for id in 0..count {
+                body.push(0x21);
+                for value in [200 + id, 0, 100, 4, 0] {
+                    body.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            push_record(&mut data, HPROF_HEAP_DUMP, &body);
+        }
+
+        let path = std::env::temp_dir().join(format!("jsnap-dumps-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let hprof = read(&path, false);
+        let _ = fs::remove_file(&path);
+        let mut hprof = hprof.unwrap();
+
+        assert_eq!(hprof.snapshots().len(), 2);
+        assert!(hprof.select_dump(1));
+        assert_eq!(hprof.dump().unwrap().objects().len(), 3);
+        assert!(!hprof.select_dump(2));
+        let deltas = hprof.compare_dumps(0, 1);
+        assert_eq!(deltas.len(), 1);
+        assert_eq!(deltas[0].class_name(), "Foo");
+        assert_eq!(deltas[0].count_delta(), 2);
+        assert_eq!(deltas[0].bytes_delta(), 8);
+    }
+
+    /// 记录各阶段报告的总量及已处理字节数
+    #[derive(Default)]
+    struct Recorder {
+        phases: std::sync::Mutex<HashMap<Phase, (u64, u64, bool)>>,
+    }
+
+    impl Progress for Recorder {
+        fn start(&self, phase: Phase, total: u64) {
+            self.phases.lock().unwrap().insert(phase, (total, 0, false));
+        }
+
+        fn advance(&self, phase: Phase, bytes: u64) {
+            self.phases.lock().unwrap().get_mut(&phase).unwrap().1 += bytes;
+        }
+
+        fn finish(&self, phase: Phase) {
+            self.phases.lock().unwrap().get_mut(&phase).unwrap().2 = true;
+        }
+    }
+
+    #[test]
+    fn test_progress_covers_heap_dumps() {
+        let mut data = header();
+        let mut body = Vec::new();
+        for id in 0..3u32 {
+            body.push(0x21);
+            for value in [200 + id, 0, 100, 4, 0] {
+                body.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        push_record(&mut data, HPROF_HEAP_DUMP_SEGMENT, &body);
+        push_record(&mut data, HPROF_HEAP_DUMP_SEGMENT, &body[..21]);
+
+        let path = std::env::temp_dir().join(format!("jsnap-progress-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let recorder = Recorder::default();
+        let hprof = read_with_progress(&path, false, &recorder);
+        let _ = fs::remove_file(&path);
+        assert_eq!(hprof.unwrap().dump().unwrap().objects().len(), 4);
+
+        let phases = recorder.phases.lock().unwrap();
+        assert_eq!(phases[&Phase::Records], (data.len() as u64, data.len() as u64, true));
+        assert_eq!(phases[&Phase::Ids], (84, 84, true));
+        assert_eq!(phases[&Phase::HeapDump], (84, 84, true));
+    }
+
+    #[test]
+    fn test_progress_covers_save() {
+        let data = HprofBuilder::new(4).unwrap()
+            .root(GcRoot::ThreadObj { id: 200, thread_seq: 1, stack_seq: 0 })
+            .class_dump(100, 0, &[])
+            .instance(200, 100, Vec::new())
+            .instance(201, 100, Vec::new())
+            .end_dump()
+            .build().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("jsnap-save-progress-{}", std::process::id()));
+        let path = dir.join("heap.hprof");
+        let work_path = dir.join("work");
+        fs::create_dir_all(&work_path).unwrap();
+        fs::write(&path, &data).unwrap();
+        let recorder = Recorder::default();
+        let hprof = read(&path, false).unwrap();
+        hprof.save_with_progress(&work_path, &recorder).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+
+        let phases = recorder.phases.lock().unwrap();
+        assert_eq!(phases[&Phase::Index], (4, 4, true));
+        assert_eq!(phases[&Phase::ObjectIndex], (3, 3, true));
+        assert_eq!(phases[&Phase::Database], (4, 4, true));
+    }
+
+    #[test]
+    fn test_save_and_load_index() {
+        // 大数组超过内联阈值，加载后仍从源文件读取
+        let large = ArrayData::Int((0..20_000).collect());
+        let data = HprofBuilder::new(4).unwrap()
+            .utf8(7, "Foo")
+            .utf8(8, "main")
+            .load_class(1, 100, 7)
+            .start_thread(1, 300, 8)
+            .root(GcRoot::ThreadObj { id: 300, thread_seq: 1, stack_seq: 0 })
+            .class_dump(100, 0, &[(7, INT_TYPE)])
+            .instance(200, 100, vec![0, 0, 0, 1])
+            .primitive_array(400, ArrayData::Int(vec![1, 2, 3]))
+            .primitive_array(401, large.clone())
+            .end_dump()
+            .build().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("jsnap-index-{}", std::process::id()));
+        let path = dir.join("heap.hprof");
+        let work_path = dir.join("work");
+        fs::create_dir_all(&work_path).unwrap();
+        fs::write(&path, &data).unwrap();
+
+        let hprof = read(&path, false).unwrap();
+        assert!(load(&path, &work_path).unwrap().is_none());
+        hprof.save(&work_path).unwrap();
+        // 加载时不读取堆转储索引，对象数及直方图由对象索引得到
+        let part = index::part_path(&snapshot_path(&work_path, 0), 0);
+        let moved = part.with_extension("moved");
+        fs::rename(&part, &moved).unwrap();
+        let indexed = load(&path, &work_path).unwrap().unwrap();
+        let counts = indexed.snapshot().object_counts();
+        let histogram = indexed.histogram(0);
+        let missing = indexed.dump().is_err();
+        fs::rename(&moved, &part).unwrap();
+        assert!(indexed.snapshot().is_indexed());
+        assert_eq!(counts, hprof.snapshot().object_counts());
+        assert_eq!(histogram, hprof.histogram(0));
+        assert!(missing);
+
+        let loaded = load(&path, &work_path).unwrap().unwrap();
+        assert_eq!(loaded.symbols(), hprof.symbols());
+        assert_eq!(loaded.class_ids(), hprof.class_ids());
+        assert_eq!(loaded.timeline().events().len(), hprof.timeline().events().len());
+        assert_eq!(loaded.threads()[0].name().as_deref(), Some("main"));
+        assert_eq!(loaded.snapshot().roots().len(), 1);
+        // 字段布局只读取类对象
+        let obj = hprof.dump().unwrap().objects()[0].clone();
+        let fields = loaded.get_instance_fields(&obj).unwrap();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].0, "Foo");
+        assert_eq!(loaded.dump().unwrap().objects().len(), 1);
+        assert_eq!(loaded.dump().unwrap().classes().len(), 1);
+        let arrays = loaded.dump().unwrap().primitive_arrays();
+        assert_eq!(*arrays[0].elements(), ArrayData::Int(vec![1, 2, 3]));
+        assert!(matches!(arrays[1].elements(), ArrayData::Lazy { .. }));
+        assert_eq!(loaded.get_array_elements(&arrays[1]).unwrap(), large);
+
+        // 源文件变化后索引失效
+        let mut changed = data.clone();
+        changed.extend_from_slice(&data[data.len() - 9..]);
+        fs::write(&path, &changed).unwrap();
+        let stale = load(&path, &work_path);
+        clear_index(&work_path).unwrap();
+        let cleared = fs::read_dir(&work_path).unwrap().count();
+        let _ = fs::remove_dir_all(&dir);
+        assert!(stale.unwrap().is_none());
+        assert_eq!(cleared, 0);
+    }
+
+    /// 记录堆转储阶段需要解析的字节数
+    #[derive(Default)]
+    struct HeapDumpTotal(std::sync::Mutex<Option<u64>>);
+
+    impl Progress for HeapDumpTotal {
+        fn start(&self, phase: Phase, total: u64) {
+            if phase == Phase::HeapDump {
+                *self.0.lock().unwrap() = Some(total);
+            }
+        }
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        let data = HprofBuilder::new(4).unwrap()
+            .utf8(7, "Foo")
+            .load_class(1, 100, 7)
+            .class_dump(100, 0, &[])
+            .instance(200, 100, Vec::new())
+            .end_dump()
+            .instance(201, 100, Vec::new())
+            .end_dump()
+            .build().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("jsnap-resume-{}", std::process::id()));
+        let path = dir.join("heap.hprof");
+        let work_path = dir.join("work");
+        fs::create_dir_all(&work_path).unwrap();
+        fs::write(&path, &data).unwrap();
+
+        // 解析完成但未保存索引即中断，再次运行时全部分段从检查点加载
+        let first = HeapDumpTotal::default();
+        read_resumable(&path, &work_path, false, &first).unwrap();
+        let resumed = HeapDumpTotal::default();
+        let hprof = read_resumable(&path, &work_path, false, &resumed).unwrap();
+        let manifest = fs::read_to_string(work_path.join("manifest")).unwrap();
+        let mut checkpointed: Vec<Vec<u8>> = fs::read_dir(work_path.join("checkpoint")).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().unwrap().to_string_lossy().starts_with("segment-"))
+            .map(|path| fs::read(path).unwrap())
+            .collect();
+        hprof.save(&work_path).unwrap();
+        let saved = fs::read_to_string(work_path.join("manifest")).unwrap();
+        let finished = !work_path.join("checkpoint").exists();
+        // 保存时直接复用检查点中的分段文件
+        let mut parts: Vec<Vec<u8>> = (0..2)
+            .map(|index| fs::read(index::part_path(&snapshot_path(&work_path, index), 0)).unwrap())
+            .collect();
+        let loaded = load(&path, &work_path).unwrap().unwrap();
+        let loaded_dump = loaded.snapshots()[1].dump().cloned();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(first.0.lock().unwrap().is_some_and(|total| total > 0));
+        assert_eq!(*resumed.0.lock().unwrap(), Some(0));
+        assert_eq!(hprof.snapshots().len(), 2);
+        let dump = hprof.snapshots()[1].dump().unwrap();
+        assert_eq!(dump.id(*dump.objects()[0].id()), 201);
+        assert!(["records", "ids", "heap-dump"].iter().all(|stage| manifest.contains(&format!("done={}\n", stage))));
+        assert_eq!(manifest.matches("segment=").count(), 2);
+        assert!(["dump-0", "dump-1", "index", "database"].iter().all(|stage| saved.contains(&format!("done={}\n", stage))));
+        assert!(finished);
+        checkpointed.sort();
+        parts.sort();
+        assert_eq!(parts, checkpointed);
+        let dump = loaded_dump.unwrap();
+        assert_eq!(dump.id(*dump.objects()[0].id()), 201);
+    }
+
+    #[test]
+    fn test_resume_android_segments() {
+        let mut writer = Writer::with_version(Vec::new(), HPROF_HEADER_103, 4, 0).unwrap();
+        writer.set_segment_size(usize::MAX);
+        // 每组子记录为一个分段，写入最后一条时分段写出
+        let segments: Vec<Vec<HeapRecord>> = vec![
+            vec![HeapRecord::HeapInfo { heap: Heap::App, name_id: 0 },
+                 HeapRecord::Class(ClassObject::new(100, 0, 0, Vec::new(), Vec::new())),
+                 HeapRecord::Instance(NormalObject::new(200, 100, Vec::new()))],
+            vec![HeapRecord::Instance(NormalObject::new(201, 100, Vec::new())),
+                 HeapRecord::HeapInfo { heap: Heap::Default, name_id: 0 },
+                 HeapRecord::Class(ClassObject::new(101, 0, 0, Vec::new(), Vec::new())),
+                 HeapRecord::Instance(NormalObject::new(202, 101, Vec::new()))],
+            vec![HeapRecord::HeapInfo { heap: Heap::Zygote, name_id: 0 }],
+            vec![HeapRecord::Instance(NormalObject::new(203, 100, Vec::new()))],
+        ];
+        for records in &segments {
+            for (index, record) in records.iter().enumerate() {
+                writer.set_segment_size(if index + 1 == records.len() { 0 } else { usize::MAX });
+                writer.write_heap_record(record).unwrap();
+            }
+        }
+        writer.end_heap_dump().unwrap();
+        let data = writer.finish().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("jsnap-resume-android-{}", std::process::id()));
+        let path = dir.join("heap.hprof");
+        let work_path = dir.join("work");
+        fs::create_dir_all(&work_path).unwrap();
+        fs::write(&path, &data).unwrap();
+        let parsed = read_resumable(&path, &work_path, false, &NoProgress).unwrap();
+        let resumed = read_resumable(&path, &work_path, false, &NoProgress).unwrap();
+        resumed.save(&work_path).unwrap();
+        let mut loaded = load(&path, &work_path).unwrap().unwrap();
+        let heaps = |hprof: &Hprof| -> Vec<(Long, Heap)> {
+            let dump = hprof.dump().unwrap();
+            dump.classes().iter().map(|class| (*class.id(), class.heap()))
+                .chain(dump.objects().iter().map(|obj| (dump.id(*obj.id()), obj.heap())))
+                .collect()
+        };
+        let loaded_heaps = heaps(&loaded);
+        // 从对象索引按堆过滤，与过滤解析结果一致
+        let mut app = parsed.clone();
+        app.retain_heap(Heap::App);
+        loaded.retain_heap(Heap::App);
+        let loaded_app = loaded.dump().map(|dump| dump.objects().len());
+        let _ = fs::remove_dir_all(&dir);
+
+        let expected = [(100, Heap::App), (101, Heap::Default), (200, Heap::App), (201, Heap::App),
+                        (202, Heap::Default), (203, Heap::Zygote)];
+        assert_eq!(heaps(&parsed), expected);
+        assert_eq!(heaps(&resumed), expected);
+        assert_eq!(loaded_heaps, expected);
+        assert_eq!(loaded.snapshot().object_counts(), app.snapshot().object_counts());
+        assert_eq!(loaded.histogram(0), app.histogram(0));
+        assert_eq!(loaded_app.unwrap(), 2);
+    }
+
+    #[test]
+    fn test_record_length_mismatch() {
+        let mut data = header();
+        // UNLOAD CLASS 只有 4 个字节的内容，其后的填充按长度跳过
+        push_record(&mut data, HPROF_UNLOAD_CLASS, &[0, 0, 0, 1, 0, 0, 0, 0]);
+        let mut body = 7u32.to_be_bytes().to_vec();
+        body.extend_from_slice(b"java/lang/String");
+        push_record(&mut data, HPROF_UTF8, &body);
+        let path = std::env::temp_dir().join(format!("jsnap-record-length-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+
+        let strict = read(&path, false);
+        struct Ignore;
+        impl Visitor for Ignore {}
+        let streamed = crate::parser::visitor::stream(&path, &mut Ignore);
+        let recovered = read(&path, true);
+        let _ = fs::remove_file(&path);
+
+        let offset = header().len();
+        assert!(matches!(strict, Err(ParseError::Malformed { message }) if message.contains(&format!("position {}", offset))));
+        assert!(matches!(streamed, Err(ParseError::Malformed { .. })));
+        let hprof = recovered.unwrap();
+        assert_eq!(hprof.symbols().get(&7).map(String::as_str), Some("java/lang/String"));
+    }
+
+    #[test]
+    fn test_recover_truncated_file() {
+        let mut data = header();
+        for (id, name) in [(1u32, "java/lang/Object"), (2, "java/lang/String")] {
+            let mut body = id.to_be_bytes().to_vec();
+            body.extend_from_slice(name.as_bytes());
+            push_record(&mut data, HPROF_UTF8, &body);
+        }
+        // 第二条记录缺失最后 4 个字节
+        data.truncate(data.len() - 4);
+        let path = std::env::temp_dir().join(format!("jsnap-recover-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+
+        let strict = read(&path, false);
+        let partial = read(&path, true);
+        let _ = fs::remove_file(&path);
+
+        assert!(matches!(strict, Err(ParseError::Truncated { tag: Some(HPROF_UTF8), .. })));
+        let hprof = partial.unwrap();
+        assert!(hprof.is_partial());
+        assert_eq!(hprof.symbols().len(), 1);
+        let truncation = hprof.truncation().as_ref().unwrap();
+        assert_eq!(*truncation.records(), 1);
+        assert_eq!(*truncation.missing_bytes(), 4);
+        assert_eq!(*truncation.offset(), 31 + 9 + 20);
+    }
+}
@@ -1,3 +1,12 @@
 pub mod hprof;
-mod reader;
-mod dump;
+pub mod error;
+pub mod reader;
+pub mod dump;
+mod segment;
+pub mod snapshot;
+pub mod stack;
+pub mod timeline;
+pub mod progress;
+pub mod validate;
+pub mod visitor;
+pub mod writer;
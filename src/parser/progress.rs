@@ -0,0 +1,93 @@
+use std::fmt;
+use std::fmt::Formatter;
+
+/// 两次进度报告之间至少间隔的字节数
+pub const PROGRESS_STEP: u64 = 1 << 20;
+/// 按对象计数的阶段，两次进度报告之间至少间隔的对象数
+pub const PROGRESS_OBJECTS: u64 = 1 << 14;
+
+/// # a phase of the analysis
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// 顺序读取全部记录：UTF8 常量、类、线程、调用栈，堆转储分段只记录位置
+    Records,
+    /// 并行收集堆转储分段中的对象 ID，生成各次堆转储的 `IdMap`
+    Ids,
+    /// 并行解析堆转储分段中的子记录
+    HeapDump,
+    /// 保存各次堆转储的子记录索引，按对象计数
+    Index,
+    /// 生成对象索引的各列及引用图，按对象计数
+    ObjectIndex,
+    /// 写入数据库，按对象计数
+    Database,
+}
+
+impl Phase {
+    /// 进度以字节计，否则以对象个数计
+    pub fn in_bytes(&self) -> bool {
+        matches!(self, Phase::Records | Phase::Ids | Phase::HeapDump)
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::Records => write!(f, "扫描记录"),
+            Phase::Ids => write!(f, "收集对象 ID"),
+            Phase::HeapDump => write!(f, "解析堆转储"),
+            Phase::Index => write!(f, "保存索引"),
+            Phase::ObjectIndex => write!(f, "生成对象索引"),
+            Phase::Database => write!(f, "写入数据库"),
+        }
+    }
+}
+
+/// # Receives progress of each phase, in bytes or objects
+///
+/// 单位见 `Phase::in_bytes`。堆转储分段由多个线程并行解析，各方法可能被同时调用
+pub trait Progress: Sync {
+    /// 阶段开始，共需处理 `total` 字节或对象
+    fn start(&self, _phase: Phase, _total: u64) {}
+    /// 又处理了 `amount` 字节或对象
+    fn advance(&self, _phase: Phase, _amount: u64) {}
+    /// 阶段结束，包括出错提前结束
+    fn finish(&self, _phase: Phase) {}
+}
+
+/// 不报告进度
+pub struct NoProgress;
+
+impl Progress for NoProgress {}
+
+/// # Counts processed objects and reports them every `PROGRESS_OBJECTS`
+///
+/// 丢弃时报告剩余的计数
+pub struct Counter<'a> {
+    progress: &'a dyn Progress,
+    phase: Phase,
+    pending: u64,
+}
+
+impl<'a> Counter<'a> {
+    pub fn new(progress: &'a dyn Progress, phase: Phase) -> Self {
+        Counter { progress, phase, pending: 0 }
+    }
+
+    /// 又处理了 `count` 个对象
+    pub fn add(&mut self, count: u64) {
+        self.pending += count;
+        if self.pending >= PROGRESS_OBJECTS {
+            self.progress.advance(self.phase, self.pending);
+            self.pending = 0;
+        }
+    }
+}
+
+impl Drop for Counter<'_> {
+    fn drop(&mut self) {
+        if self.pending > 0 {
+            self.progress.advance(self.phase, self.pending);
+        }
+    }
+}
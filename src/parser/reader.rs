@@ -1,13 +1,22 @@
-use std::fmt;
+use std::{fmt, io};
+use std::borrow::Cow;
 use std::fmt::Formatter;
-use crate::io::channel;
-use crate::io::channel::{Channel, Result, Byte, Short, Int, Long};
-use std::path::PathBuf;
+use std::io::ErrorKind;
+use crate::io::channel::{Backend, Channel, Byte, Char, Float, Short, Int, Long};
+use crate::parser::error::{ParseError, Result};
+use std::path::Path;
 use derive_getters::Getters;
 
+/// 预分配的元素个数，不超过 `remaining` 字节能容纳的个数
+///
+/// 元素个数来自文件，损坏的文件可能给出极大的值，不能据此直接分配内存
+pub(crate) fn capacity(count: Int, elem_size: usize, remaining: u64) -> usize {
+    (count as u64).min(remaining / elem_size.max(1) as u64) as usize
+}
+
 /// # A section from the hprof file
-pub trait Section {
-    fn read(reader: &mut Reader, len: Int) -> Self;
+pub trait Section: Sized {
+    fn read(reader: &mut Reader, len: Int) -> Result<Self>;
 }
 
 #[derive(Clone, Debug, Getters)]
@@ -16,16 +25,26 @@ pub struct Utf8 {
     name: String,
 }
 
+impl Utf8 {
+    pub fn new(symbol_id: Long, name: &str) -> Self {
+        Utf8 { symbol_id, name: name.to_string() }
+    }
+}
+
 impl Section for Utf8 {
-    fn read(reader: &mut Reader, len: Int) -> Self {
+    fn read(reader: &mut Reader, len: Int) -> Result<Self> {
         // 常量池
+        if len < reader.id_size {
+            let offset = reader.position()?;
+            return Err(ParseError::malformed(format!("UTF8 记录长度 {} 小于 id 大小，position {}", len, offset)));
+        }
         let length = len - reader.id_size;
-        let symbol_id = reader.get_id();
-        let name = reader.channel.read_str(length as usize).unwrap();
-        Utf8 {
+        let symbol_id = reader.get_id()?;
+        let name = reader.read_str(length as usize)?;
+        Ok(Utf8 {
             symbol_id,
             name,
-        }
+        })
     }
 }
 
@@ -42,30 +61,38 @@ pub struct Class {
     name_id: Long,
 }
 
+impl Class {
+    pub fn new(serial_num: Int, id: Long, name_id: Long) -> Self {
+        Class { id, serial_num, stack_trace_ser: 0, name_id }
+    }
+}
+
 impl Section for Class {
-    fn read(reader: &mut Reader, _: Int) -> Self {
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
         // 常量池
-        let serial_num = reader.read_int();
-        let class_id = reader.get_id();
-        let stack_trace_ser = reader.read_int();
-        let name_id = reader.get_id();
-        Class {
+        let serial_num = reader.read_int()?;
+        let class_id = reader.get_id()?;
+        let stack_trace_ser = reader.read_int()?;
+        let name_id = reader.get_id()?;
+        Ok(Class {
             serial_num,
             id: class_id,
             stack_trace_ser,
             name_id,
-        }
+        })
     }
 }
 
 /// # A Java stack frame
 /// # Examples
-/// ```rust
-/// crate::parser::reader::{Frame};
+/// ```rust,no_run
+/// use std::path::Path;
+/// use jsnap::parser::reader::{Frame, Reader};
 ///
-/// let reader = Reader::new(file_path)?;
-/// ...
-/// let frame = reader.read::<Frame>(0);
+/// let mut reader = Reader::new(Path::new("heap.hprof"))?;
+/// // ... 定位到 FRAME 记录体
+/// let frame = reader.read::<Frame>(0)?;
+/// # Ok::<(), jsnap::ParseError>(())
 /// ```
 #[derive(Clone, Debug, Getters)]
 pub struct Frame {
@@ -81,33 +108,41 @@ pub struct Frame {
     line_nr: Int,
 }
 
+impl Frame {
+    pub fn new(id: Long, method_name: Long, method_sig: Long, src_file: Long, class_ser_num: Int, line_nr: Int) -> Self {
+        Frame { id, method_name, method_sig, src_file, class_ser_num, line_nr }
+    }
+}
+
 impl Section for Frame {
-    fn read(reader: &mut Reader, _: Int) -> Self {
-        let frame_id = reader.get_id();
-        let method_name = reader.get_id();
-        let method_sig = reader.get_id();
-        let src_file = reader.get_id();
-        let class_ser_num = reader.read_int();
-        let line_nr = reader.read_int();
-        Frame {
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
+        let frame_id = reader.get_id()?;
+        let method_name = reader.get_id()?;
+        let method_sig = reader.get_id()?;
+        let src_file = reader.get_id()?;
+        let class_ser_num = reader.read_int()?;
+        let line_nr = reader.read_int()?;
+        Ok(Frame {
             id: frame_id,
             method_name,
             method_sig,
             src_file,
             class_ser_num,
             line_nr,
-        }
+        })
     }
 }
 
 /// # a Java stack trace
 /// # Examples
-/// ```rust
-/// crate::parser::reader::{Frame};
+/// ```rust,no_run
+/// use std::path::Path;
+/// use jsnap::parser::reader::{Reader, Trace};
 ///
-/// let reader = Reader::new(file_path)?;
-/// ...
-/// let trace = reader.read::<Trace>(0);
+/// let mut reader = Reader::new(Path::new("heap.hprof"))?;
+/// // ... 定位到 STACK TRACE 记录体
+/// let trace = reader.read::<Trace>(0)?;
+/// # Ok::<(), jsnap::ParseError>(())
 /// ```
 #[derive(Clone, Debug, Getters)]
 pub struct Trace {
@@ -116,21 +151,27 @@ pub struct Trace {
     frame_ids: Vec<u64>,
 }
 
+impl Trace {
+    pub fn new(stack_trace_nr: Int, thread_nr: Int, frame_ids: Vec<u64>) -> Self {
+        Trace { stack_trace_nr, thread_nr, frame_ids }
+    }
+}
+
 impl Section for Trace {
-    fn read(reader: &mut Reader, _: Int) -> Self {
-        let stack_trace_nr = reader.read_int();
-        let thread_nr = reader.read_int();
-        let frame_count = reader.read_int();
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
+        let stack_trace_nr = reader.read_int()?;
+        let thread_nr = reader.read_int()?;
+        let frame_count = reader.read_int()?;
         let mut frame_ids: Vec<u64> = Vec::new();
         for _ in 0..frame_count {
-            let id = reader.get_id();
+            let id = reader.get_id()?;
             frame_ids.push(id);
         }
-        Trace {
+        Ok(Trace {
             stack_trace_nr,
             thread_nr,
             frame_ids,
-        }
+        })
     }
 }
 
@@ -151,22 +192,35 @@ pub struct Thread {
     p_name_index: Long,
 }
 
+impl Thread {
+    /// 没有线程组的线程
+    pub fn new(thread_serial_num: Int, id: Long, t_name_index: Long) -> Self {
+        Thread { id, thread_serial_num, trace_serial_num: 0, t_name_index, g_name_index: 0, p_name_index: 0 }
+    }
+
+    /// 带调用栈及线程组、父线程组名称的线程
+    pub fn with_group(thread_serial_num: Int, id: Long, trace_serial_num: Int,
+                      t_name_index: Long, g_name_index: Long, p_name_index: Long) -> Self {
+        Thread { id, thread_serial_num, trace_serial_num, t_name_index, g_name_index, p_name_index }
+    }
+}
+
 impl Section for Thread {
-    fn read(reader: &mut Reader, _: Int) -> Self {
-        let thread_serial_num = reader.read_int();
-        let thread_obj_id = reader.get_id();
-        let trace_serial_num = reader.read_int();
-        let t_name_index = reader.get_id();
-        let g_name_index = reader.get_id();
-        let p_name_index = reader.get_id();
-        Thread {
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
+        let thread_serial_num = reader.read_int()?;
+        let thread_obj_id = reader.get_id()?;
+        let trace_serial_num = reader.read_int()?;
+        let t_name_index = reader.get_id()?;
+        let g_name_index = reader.get_id()?;
+        let p_name_index = reader.get_id()?;
+        Ok(Thread {
             trace_serial_num,
             id: thread_obj_id,
             thread_serial_num,
             t_name_index,
             g_name_index,
             p_name_index,
-        }
+        })
     }
 }
 
@@ -179,39 +233,52 @@ pub struct HeapSummary {
 }
 
 impl Section for HeapSummary {
-    fn read(reader: &mut Reader, _: Int) -> Self {
-        let live = reader.read_int();
-        let live_inst = reader.read_int();
-        let allocate = reader.read_long();
-        let allocate_inst = reader.read_long();
-        HeapSummary {
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
+        let live = reader.read_int()?;
+        let live_inst = reader.read_int()?;
+        let allocate = reader.read_long()?;
+        let allocate_inst = reader.read_long()?;
+        Ok(HeapSummary {
             live,
             live_inst,
             allocate,
             allocate_inst,
-        }
+        })
     }
 }
 
 /// # a set of sample traces of running threads
 #[derive(Clone, Debug, Getters)]
 pub struct CpuSamples {
+    /// total number of samples
     num: Int,
+    samples: Vec<CpuSample>,
+}
+
+/// # samples of one stack trace
+#[derive(Clone, Debug, Getters)]
+pub struct CpuSample {
+    /// number of samples
+    count: Int,
+    /// stack trace serial number
+    trace_serial_num: Int,
 }
 
 impl Section for CpuSamples {
-    fn read(reader: &mut Reader, _: Int) -> Self {
-        let total_num = reader.read_int();
-        let trace_count = reader.read_int();
+    fn read(reader: &mut Reader, len: Int) -> Result<Self> {
+        let total_num = reader.read_int()?;
+        let trace_count = reader.read_int()?;
+        let mut samples = Vec::with_capacity(capacity(trace_count, 8, len.saturating_sub(8) as u64));
         for _ in 0..trace_count {
-            let num_element = reader.read_int();
-            let trace_serial_num = reader.read_int();
-            // Todo
+            let count = reader.read_int()?;
+            let trace_serial_num = reader.read_int()?;
+            samples.push(CpuSample { count, trace_serial_num });
         }
 
-        CpuSamples {
-            num: total_num
-        }
+        Ok(CpuSamples {
+            num: total_num,
+            samples,
+        })
     }
 }
 
@@ -226,32 +293,86 @@ pub struct ControlSettings {
 }
 
 impl Section for ControlSettings {
-    fn read(reader: &mut Reader, _: Int) -> Self {
-        let flags = reader.read_int();
-        let depth = reader.read_short();
-        ControlSettings {
+    fn read(reader: &mut Reader, _: Int) -> Result<Self> {
+        let flags = reader.read_int()?;
+        let depth = reader.read_short()?;
+        Ok(ControlSettings {
             flags,
             depth,
-        }
+        })
     }
 }
 
+/// # a set of heap allocation sites, obtained after GC
 #[derive(Clone, Debug, Getters)]
-pub struct AllocSites {}
+pub struct AllocSites {
+    /// 0x0001: incremental vs. complete
+    /// 0x0002: sorted by allocation vs. live
+    /// 0x0004: whether to force a GC
+    flags: Short,
+    /// cutoff ratio
+    cutoff_ratio: Float,
+    /// total live bytes
+    total_live_bytes: Int,
+    /// total live instances
+    total_live_inst: Int,
+    /// total bytes allocated
+    total_bytes_allocated: Long,
+    /// total instances allocated
+    total_inst_allocated: Long,
+    sites: Vec<AllocSite>,
+}
+
+/// # an allocation site
+#[derive(Clone, Debug, Getters)]
+pub struct AllocSite {
+    /// 0: normal object, otherwise the element type of an array
+    array_type: Byte,
+    /// class serial number (may be zero during startup)
+    class_serial_num: Int,
+    /// stack trace serial number
+    trace_serial_num: Int,
+    /// number of bytes alive
+    live_bytes: Int,
+    /// number of instances alive
+    live_inst: Int,
+    /// number of bytes allocated
+    bytes_allocated: Int,
+    /// number of instance allocated
+    inst_allocated: Int,
+}
 
 impl Section for AllocSites {
-    fn read(reader: &mut Reader, len: Int) -> Self {
-        // 0x0001: incremental vs. complete
-        // 0x0002: sorted by allocation vs. live
-        // 0x0004: whether to force a GC
-        let flags = reader.read_short();
-        let cutoff_ratio = reader.read_int();
-        let total_live_bytes = reader.read_int();
-        let total_live_inst = reader.read_int();    // total live instances
-        let total_bytes_allocated = reader.read_long();
-        let total_inst_allocated = reader.read_long();
-        let num_sites = reader.read_int(); // number of sites that follow
-        AllocSites {}
+    fn read(reader: &mut Reader, len: Int) -> Result<Self> {
+        let flags = reader.read_short()?;
+        let cutoff_ratio = Float::from_bits(reader.read_int()?);
+        let total_live_bytes = reader.read_int()?;
+        let total_live_inst = reader.read_int()?;
+        let total_bytes_allocated = reader.read_long()?;
+        let total_inst_allocated = reader.read_long()?;
+        let num_sites = reader.read_int()?; // number of sites that follow
+        // 记录头 34 字节，每个分配点 25 字节
+        let mut sites = Vec::with_capacity(capacity(num_sites, 25, len.saturating_sub(34) as u64));
+        for _ in 0..num_sites {
+            sites.push(AllocSite {
+                array_type: reader.read_byte()?,
+                class_serial_num: reader.read_int()?,
+                trace_serial_num: reader.read_int()?,
+                live_bytes: reader.read_int()?,
+                live_inst: reader.read_int()?,
+                bytes_allocated: reader.read_int()?,
+                inst_allocated: reader.read_int()?,
+            });
+        }
+        Ok(AllocSites {
+            flags,
+            cutoff_ratio,
+            total_live_bytes,
+            total_live_inst,
+            total_bytes_allocated,
+            total_inst_allocated,
+            sites,
+        })
     }
 }
 
@@ -261,71 +382,184 @@ pub struct Reader {
     channel: Channel,
     /// oop id 大小
     id_size: u32,
+    /// 正在读取的记录类型
+    tag: Option<Byte>,
+    /// 文件大小
+    size: u64,
+    /// 最后一条完整记录的结束位置
+    complete_offset: u64,
 }
 
 impl Reader {
-    pub fn new(file_path: &PathBuf) -> Result<Self> {
-        let channel = Channel::open(&file_path)?;
-        Ok(Self { channel, id_size: 0 })
+    pub fn new(file_path: &Path) -> Result<Self> {
+        Self::open_with(file_path, Backend::default())
+    }
+
+    /// 使用指定的读取方式打开文件
+    pub fn open_with(file_path: &Path, backend: Backend) -> Result<Self> {
+        let channel = Channel::open_with(file_path, backend).map_err(|err| ParseError::from_io(err, 0, None))?;
+        let size = channel.size().map_err(|err| ParseError::from_io(err, 0, None))?;
+        Ok(Self { channel, id_size: 0, tag: None, size, complete_offset: 0 })
     }
 
     /// # read a section
-    pub fn read<T: Section>(&mut self, len: Int) -> T {
+    pub fn read<T: Section>(&mut self, len: Int) -> Result<T> {
         T::read(self, len)
     }
 
-    pub fn get_header(&mut self) -> Result<(Byte, Int, Int)> {
-        let tag = self.channel.read_byte()?;
-        let offset = self.channel.read_int()?;
-        let length = self.channel.read_int()?;
-        Ok((tag, offset, length))
+    /// 读取记录头 (tag, time offset, length)，在记录边界处到达文件末尾时返回 `None`
+    pub fn get_header(&mut self) -> Result<Option<(Byte, Int, Int)>> {
+        self.tag = None;
+        let tag = match self.channel.read_byte() {
+            Ok(tag) => tag,
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(self.error(err)),
+        };
+        self.tag = Some(tag);
+        let offset = self.read_int()?;
+        let length = self.read_int()?;
+        Ok(Some((tag, offset, length)))
+    }
+
+    /// 设置正在读取的记录类型，用于错误定位
+    pub fn set_tag(&mut self, tag: Byte) {
+        self.tag = Some(tag);
+    }
+
+    pub fn tag(&self) -> Option<Byte> {
+        self.tag
     }
 
-    pub fn get_id(&mut self) -> Long {
+    /// 文件大小
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// 当前位置之后剩余的字节数
+    pub fn remaining(&mut self) -> Result<u64> {
+        Ok(self.size.saturating_sub(self.position()?))
+    }
+
+    /// 记录最后一条完整记录的结束位置
+    pub fn mark_complete(&mut self, position: u64) {
+        self.complete_offset = position;
+    }
+
+    pub fn complete_offset(&self) -> u64 {
+        self.complete_offset
+    }
+
+    pub fn get_id(&mut self) -> Result<Long> {
         if self.id_size == 4 {
-            self.read_int() as Long
+            Ok(self.read_int()? as Long)
         } else {
             self.read_long()
         }
     }
 
-    pub fn get_timestamp(&mut self) -> Long {
+    pub fn get_timestamp(&mut self) -> Result<Long> {
         self.read_long()
     }
 
-    pub fn get_id_size(&mut self) -> Int {
-        if self.id_size <= 0 {
-            self.id_size = self.read_int();
+    pub fn get_id_size(&mut self) -> Result<Int> {
+        if self.id_size == 0 {
+            let id_size = self.read_int()?;
+            if id_size != 4 && id_size != 8 {
+                let offset = self.position()? - 4;
+                return Err(ParseError::BadIdSize { offset, id_size });
+            }
+            self.id_size = id_size;
         }
+        Ok(self.id_size)
+    }
+
+    /// 使用已知的 oop id 大小，用于从记录中间开始读取
+    pub fn set_id_size(&mut self, id_size: Int) {
+        self.id_size = id_size;
+    }
+
+    /// oop id 大小，需先调用 `get_id_size` 读取
+    pub fn id_size(&self) -> Int {
         self.id_size
     }
 
-    pub fn read_byte(&mut self) -> Byte {
-        self.channel.read_byte().unwrap()
+    pub fn read_byte(&mut self) -> Result<Byte> {
+        let result = self.channel.read_byte();
+        result.map_err(|err| self.error(err))
+    }
+
+    pub fn read_int(&mut self) -> Result<Int> {
+        let result = self.channel.read_int();
+        result.map_err(|err| self.error(err))
     }
 
-    pub fn read_int(&mut self) -> Int {
-        self.channel.read_int().unwrap()
+    pub fn read_long(&mut self) -> Result<Long> {
+        let result = self.channel.read_long();
+        result.map_err(|err| self.error(err))
     }
 
-    pub fn read_long(&mut self) -> Long {
-        self.channel.read_long().unwrap()
+    pub fn read_char(&mut self) -> Result<Char> {
+        let result = self.channel.read_char();
+        result.map_err(|err| self.error(err))
     }
 
-    pub fn read_char(&mut self) -> char {
-        self.channel.read_char().unwrap()
+    pub fn read_cstr(&mut self, max_len: usize) -> Result<String> {
+        let result = self.channel.read_cstr(max_len);
+        result.map_err(|err| self.error(err))
     }
 
-    pub fn read_short(&mut self) -> Short {
-        self.channel.read_short().unwrap()
+    pub fn read_str(&mut self, len: usize) -> Result<String> {
+        let result = self.channel.read_str(len);
+        result.map_err(|err| self.error(err))
     }
 
-    pub fn skip(&mut self, len: Int) {
-        self.channel.skip(len as i64);
+    pub fn read_short(&mut self) -> Result<Short> {
+        let result = self.channel.read_short();
+        result.map_err(|err| self.error(err))
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<Vec<Byte>> {
+        let result = self.channel.read_bytes(len);
+        result.map_err(|err| self.error(err))
+    }
+
+    /// 读取 `len` 个字节，后端支持时不复制文件内容
+    pub fn read_slice(&mut self, len: usize) -> Result<Cow<'_, [Byte]>> {
+        // 借用的结果无法再附加当前位置，读取失败一定发生在文件末尾
+        let (size, tag) = (self.size, self.tag);
+        self.channel.read_slice(len).map_err(|err| ParseError::from_io(err, size, tag))
+    }
+
+    /// 随机读取 `offset` 处的 `len` 个字节，不改变当前位置
+    pub fn slice_at(&mut self, offset: u64, len: usize) -> Result<Cow<'_, [Byte]>> {
+        let (size, tag) = (self.size, self.tag);
+        self.channel.slice_at(offset, len).map_err(|err| ParseError::from_io(err, size, tag))
+    }
+
+    /// 跳过指定字节数，超出文件末尾视为截断
+    pub fn skip(&mut self, len: Long) -> Result<()> {
+        let result = self.channel.skip(len as i64);
+        result.map_err(|err| self.error(err))?;
+        let offset = self.position()?;
+        if offset > self.size {
+            return Err(ParseError::Truncated { offset: self.size, tag: self.tag });
+        }
+        Ok(())
+    }
+
+    pub fn seek(&mut self, position: u64) -> Result<u64> {
+        let result = self.channel.seek(position);
+        result.map_err(|err| self.error(err))
     }
 
     pub fn position(&mut self) -> Result<u64> {
-        self.channel.position()
+        let result = self.channel.position();
+        result.map_err(|err| ParseError::from_io(err, 0, self.tag))
     }
-}
 
+    /// 附加当前位置及记录类型
+    fn error(&mut self, err: io::Error) -> ParseError {
+        let offset = self.channel.position().unwrap_or_default();
+        ParseError::from_io(err, offset, self.tag)
+    }
+}
@@ -0,0 +1,202 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use derive_getters::Getters;
+use crate::io::channel::{Int, Long};
+use crate::parser::dump::{read_heap_record, read_object_id, Dump};
+use crate::parser::error::{ParseError, Result};
+use crate::parser::progress::{Phase, Progress, PROGRESS_STEP};
+use crate::parser::reader::Reader;
+use crate::store::ids::IdMap;
+
+/// # a HEAP DUMP or HEAP DUMP SEGMENT record body
+#[derive(Clone, Copy, Debug, Getters)]
+pub struct Segment {
+    /// 所属堆转储的序号
+    dump: usize,
+    /// 记录体在文件中的偏移
+    offset: u64,
+    /// 记录体长度
+    length: Int,
+}
+
+impl Segment {
+    pub fn new(dump: usize, offset: u64, length: Int) -> Self {
+        Segment { dump, offset, length }
+    }
+}
+
+/// 单个分段的解析结果
+pub struct SegmentDump {
+    /// 出错前已解析的子记录
+    pub dump: Dump,
+    /// 解析错误
+    pub error: Option<ParseError>,
+    /// 最后一条完整子记录的结束位置
+    pub complete_offset: u64,
+}
+
+/// 分段解析完成后的回调，在工作线程中调用，例如保存检查点
+pub type OnSegment<'a> = dyn Fn(&Segment, &SegmentDump) -> Result<()> + Sync + 'a;
+
+/// 在工作线程池中并行解析各分段，结果按分段在文件中的顺序返回
+///
+/// `ids` 为各次堆转储的对象 ID 映射表，由 `read_segment_ids` 收集的对象 ID 生成，同一次堆转储的分段共用。
+/// 每个工作线程各自打开文件，依次领取尚未解析的分段，每个分段完成后调用 `on_segment`
+pub fn read_segments(file_path: &Path, id_size: Int, segments: &[Segment], ids: &[Arc<IdMap>],
+                     progress: &dyn Progress, on_segment: &OnSegment) -> Result<Vec<SegmentDump>> {
+    for_each_segment(file_path, id_size, segments, |reader, segment| {
+        let dump = Dump::new(ids[segment.dump].clone());
+        let result = read_segment(reader, segment, dump, progress)?;
+        on_segment(segment, &result)?;
+        Ok(result)
+    })
+}
+
+/// 在工作线程池中并行收集各分段中的对象 ID，按分段在文件中的顺序返回，向 `Phase::Ids` 报告已读取的字节数
+///
+/// 只读取子记录头，实例字段及数组元素直接跳过。分段出错时停在出错处，错误由随后的 `read_segments` 报告
+pub fn read_segment_ids(file_path: &Path, id_size: Int, segments: &[Segment],
+                        progress: &dyn Progress) -> Result<Vec<Vec<Long>>> {
+    for_each_segment(file_path, id_size, segments, |reader, segment| {
+        reader.seek(segment.offset)?;
+        let end = segment.offset + segment.length as u64;
+        let mut reported = segment.offset;
+        let mut ids = Vec::new();
+        let mut read = || -> Result<()> {
+            let mut position = segment.offset;
+            while position < end {
+                ids.extend(read_object_id(reader)?);
+                position = reader.position()?;
+                if position - reported >= PROGRESS_STEP {
+                    progress.advance(Phase::Ids, position - reported);
+                    reported = position;
+                }
+            }
+            Ok(())
+        };
+        let _ = read();
+        progress.advance(Phase::Ids, end.saturating_sub(reported));
+        Ok(ids)
+    })
+}
+
+/// 每个工作线程各自打开文件，依次领取尚未处理的分段交给 `work`，结果按分段在文件中的顺序返回
+fn for_each_segment<T, F>(file_path: &Path, id_size: Int, segments: &[Segment], work: F) -> Result<Vec<T>>
+where
+    T: Send,
+    F: Fn(&mut Reader, &Segment) -> Result<T> + Sync,
+{
+    let workers = thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+        .min(segments.len());
+    let next = AtomicUsize::new(0);
+
+    let mut results: Vec<(usize, T)> = Vec::with_capacity(segments.len());
+    thread::scope(|scope| -> Result<()> {
+        let handles: Vec<_> = (0..workers).map(|_| {
+            scope.spawn(|| -> Result<Vec<(usize, T)>> {
+                let mut reader = Reader::new(file_path)?;
+                reader.set_id_size(id_size);
+                let mut results = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(segment) = segments.get(index) else { break };
+                    results.push((index, work(&mut reader, segment)?));
+                }
+                Ok(results)
+            })
+        }).collect();
+        for handle in handles {
+            let partial = handle.join().expect("堆转储分段解析线程异常退出")?;
+            results.extend(partial);
+        }
+        Ok(())
+    })?;
+
+    results.sort_by_key(|(index, _)| *index);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// 解析一个分段，子记录追加到 `dump` 中，出错时保留已解析的内容
+///
+/// 与 `get_heap_dump` 相同地遍历子记录，同时按已解析的字节数报告进度
+fn read_segment(reader: &mut Reader, segment: &Segment, mut dump: Dump, progress: &dyn Progress) -> Result<SegmentDump> {
+    reader.seek(segment.offset)?;
+    reader.mark_complete(segment.offset);
+    let end = segment.offset + segment.length as u64;
+    let mut reported = segment.offset;
+    let mut read = || -> Result<()> {
+        let mut position = segment.offset;
+        while position < end {
+            dump.push(read_heap_record(reader)?);
+            position = reader.position()?;
+            reader.mark_complete(position);
+            if position - reported >= PROGRESS_STEP {
+                progress.advance(Phase::HeapDump, position - reported);
+                reported = position;
+            }
+        }
+        Ok(())
+    };
+    let error = read().err();
+    // 出错时剩余部分不再解析，同样计入进度
+    progress.advance(Phase::HeapDump, end.saturating_sub(reported));
+    Ok(SegmentDump { dump, error, complete_offset: reader.complete_offset() })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::parser::dump::NormalObject;
+    use crate::parser::hprof::{read_header, HPROF_HEAP_DUMP_SEGMENT};
+    use crate::parser::progress::NoProgress;
+    use crate::parser::writer::Writer;
+    use super::*;
+
+    #[test]
+    fn test_segments_merged_in_file_order() {
+        let mut writer = Writer::new(Vec::new(), 4, 0).unwrap();
+        writer.set_segment_size(64);
+        for id in 0..100 as Long {
+            writer.write_heap_record(&NormalObject::new(1000 + id, 1, id.to_be_bytes().to_vec())).unwrap();
+        }
+        writer.end_heap_dump().unwrap();
+        let data = writer.finish().unwrap();
+        let path = std::env::temp_dir().join(format!("jsnap-segments-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+
+        let mut reader = Reader::new(&path).unwrap();
+        read_header(&mut reader).unwrap();
+        let mut segments = Vec::new();
+        while let Some((tag, _, length)) = reader.get_header().unwrap() {
+            if tag == HPROF_HEAP_DUMP_SEGMENT {
+                segments.push(Segment::new(0, reader.position().unwrap(), length));
+            }
+            reader.skip(length as Long).unwrap();
+        }
+        let ids = read_segment_ids(&path, 4, &segments, &NoProgress).unwrap();
+        assert_eq!(ids.concat(), (1000..1100).collect::<Vec<Long>>());
+        let ids = Arc::new(IdMap::new(ids.concat()).unwrap());
+        let completed = AtomicUsize::new(0);
+        let results = read_segments(&path, 4, &segments, std::slice::from_ref(&ids), &NoProgress, &|_, _| {
+            completed.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert!(segments.len() > 10);
+        assert_eq!(completed.into_inner(), segments.len());
+        let mut dump = Dump::new(ids.clone());
+        for result in results {
+            assert!(result.error.is_none());
+            dump.merge(result.dump);
+        }
+        let objects: Vec<Long> = dump.objects().iter().map(|obj| dump.id(*obj.id())).collect();
+        assert_eq!(objects, (1000..1100).collect::<Vec<Long>>());
+        // 类定义缺失的类追加在对象之后
+        assert_eq!(ids.assigned(), 101);
+    }
+}
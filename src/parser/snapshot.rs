@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use derive_getters::Getters;
+use crate::io::channel::{Int, Long};
+use crate::parser::dump::{Dump, GcRoot, Heap, HeapRecord, HistogramEntry, HistogramKey};
+use crate::parser::error::Result;
+use crate::store::ids::{CompactId, NO_ID};
+use crate::store::index;
+use crate::store::objects::{ObjectIndex, ObjectKind};
+
+/// # 一次堆转储的快照
+///
+/// 解析得到的快照在内存中；从工作目录加载的快照只打开对象索引，对象的子记录在用到时才从堆转储索引读取
+#[derive(Clone, Debug)]
+pub enum Snapshot {
+    Parsed(Dump),
+    Indexed(Box<IndexedDump>),
+}
+
+/// # 从对象索引加载的快照
+///
+/// 类直方图及对象数由对象索引的各列得到；字段布局只读取类对象的子记录，
+/// 需要全部对象时才读取整个堆转储索引
+#[derive(Clone, Debug)]
+pub struct IndexedDump {
+    /// `dump-{n}/`
+    dir: PathBuf,
+    objects: Arc<ObjectIndex>,
+    /// GC root 保存在全局索引中
+    roots: Vec<GcRoot>,
+    /// `retain_heap` 指定的堆
+    heap: Option<Heap>,
+    /// 只有类对象的堆转储
+    classes: OnceLock<Dump>,
+    /// 全部子记录
+    dump: OnceLock<Dump>,
+}
+
+/// # 快照中各类对象的个数
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Getters)]
+pub struct ObjectCounts {
+    classes: usize,
+    instances: usize,
+    object_arrays: usize,
+    primitive_arrays: usize,
+}
+
+impl Snapshot {
+    /// 打开 `dir` 中的对象索引，`roots` 为全局索引中保存的 GC root
+    pub fn open(dir: &Path, roots: Vec<GcRoot>) -> Result<Self> {
+        Ok(Snapshot::Indexed(Box::new(IndexedDump {
+            dir: dir.to_path_buf(),
+            objects: Arc::new(ObjectIndex::open(dir)?),
+            roots,
+            heap: None,
+            classes: OnceLock::new(),
+            dump: OnceLock::new(),
+        })))
+    }
+
+    /// 全部子记录，从索引加载的快照在第一次调用时读取整个堆转储索引
+    pub fn dump(&self) -> Result<&Dump> {
+        match self {
+            Snapshot::Parsed(dump) => Ok(dump),
+            Snapshot::Indexed(indexed) => indexed.dump(),
+        }
+    }
+
+    /// 只含类对象即可时使用，例如解析实例字段，从索引加载的快照只读取类对象的子记录
+    pub fn classes(&self) -> Result<&Dump> {
+        match self {
+            Snapshot::Parsed(dump) => Ok(dump),
+            Snapshot::Indexed(indexed) => indexed.classes(),
+        }
+    }
+
+    pub fn roots(&self) -> &[GcRoot] {
+        match self {
+            Snapshot::Parsed(dump) => dump.roots(),
+            Snapshot::Indexed(indexed) => &indexed.roots,
+        }
+    }
+
+    /// 紧凑下标对应的 HPROF ID
+    pub fn id(&self, index: CompactId) -> Long {
+        match self {
+            Snapshot::Parsed(dump) => dump.id(index),
+            Snapshot::Indexed(indexed) => indexed.objects.id(index),
+        }
+    }
+
+    /// 是否由对象索引加载
+    pub fn is_indexed(&self) -> bool {
+        matches!(self, Snapshot::Indexed(_))
+    }
+
+    /// 是否没有任何子记录
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Snapshot::Parsed(dump) => dump.is_empty(),
+            Snapshot::Indexed(indexed) => indexed.roots.is_empty() && indexed.retained().next().is_none(),
+        }
+    }
+
+    pub fn object_counts(&self) -> ObjectCounts {
+        let mut counts = ObjectCounts::default();
+        match self {
+            Snapshot::Parsed(dump) => {
+                counts.classes = dump.classes().len();
+                counts.instances = dump.objects().len();
+                counts.object_arrays = dump.object_arrays().len();
+                counts.primitive_arrays = dump.primitive_arrays().len();
+            }
+            Snapshot::Indexed(indexed) => {
+                for index in indexed.retained() {
+                    match indexed.objects.kind(index) {
+                        ObjectKind::Class => counts.classes += 1,
+                        ObjectKind::Instance => counts.instances += 1,
+                        ObjectKind::ObjectArray => counts.object_arrays += 1,
+                        ObjectKind::PrimitiveArray(_) => counts.primitive_arrays += 1,
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    /// 按类统计对象数量与浅大小，见 `Dump::histogram`
+    pub fn histogram(&self, id_size: Int) -> HashMap<HistogramKey, HistogramEntry> {
+        let indexed = match self {
+            Snapshot::Parsed(dump) => return dump.histogram(id_size),
+            Snapshot::Indexed(indexed) => indexed,
+        };
+        let objects = &indexed.objects;
+        let mut histogram: HashMap<HistogramKey, HistogramEntry> = HashMap::new();
+        for index in indexed.retained() {
+            let key = match objects.kind(index) {
+                ObjectKind::Class => continue,
+                ObjectKind::Instance | ObjectKind::ObjectArray =>
+                    HistogramKey::Class(objects.class_index(index).unwrap_or(NO_ID)),
+                ObjectKind::PrimitiveArray(element_type) => HistogramKey::PrimitiveArray(element_type),
+            };
+            let total = histogram.entry(key).or_default();
+            *total = HistogramEntry::new(total.count() + 1, total.bytes() + objects.shallow_size(index));
+        }
+        histogram
+    }
+
+    /// 只保留指定堆中的类与对象，GC root 全部保留
+    pub fn retain_heap(&mut self, heap: Heap) {
+        match self {
+            Snapshot::Parsed(dump) => *dump = dump.filter_heap(heap),
+            Snapshot::Indexed(indexed) => {
+                indexed.heap = Some(heap);
+                indexed.classes = OnceLock::new();
+                indexed.dump = OnceLock::new();
+            }
+        }
+    }
+}
+
+impl IndexedDump {
+    /// 对象索引
+    pub fn objects(&self) -> &ObjectIndex {
+        &self.objects
+    }
+
+    /// `retain_heap` 保留的对象
+    fn retained(&self) -> impl Iterator<Item = CompactId> + '_ {
+        (0..self.objects.len() as CompactId)
+            .filter(|index| self.heap.is_none_or(|heap| self.objects.heap(*index) == heap))
+    }
+
+    fn dump(&self) -> Result<&Dump> {
+        if let Some(dump) = self.dump.get() {
+            return Ok(dump);
+        }
+        let dump = index::read_dump_index(&self.dir, self.objects.ids())?;
+        let dump = match self.heap {
+            Some(heap) => dump.filter_heap(heap),
+            None => dump,
+        };
+        Ok(self.dump.get_or_init(|| dump))
+    }
+
+    fn classes(&self) -> Result<&Dump> {
+        if let Some(dump) = self.dump.get() {
+            return Ok(dump);
+        }
+        if let Some(classes) = self.classes.get() {
+            return Ok(classes);
+        }
+        let mut classes = Dump::new(self.objects.ids().clone());
+        for index in self.retained().filter(|index| self.objects.kind(*index) == ObjectKind::Class) {
+            if let record @ HeapRecord::Class(_) = self.objects.record(index)? {
+                classes.push(record);
+            }
+        }
+        Ok(self.classes.get_or_init(|| classes))
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use derive_getters::Getters;
+use crate::io::channel::{Int, Long};
+use crate::parser::dump::GcRoot;
+use crate::parser::reader::{Frame, Thread, Trace};
+
+/// # line number of a stack frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineNumber {
+    /// > 0: normal line
+    Line(Int),
+    /// 0: no line information available
+    NoInfo,
+    /// -1: unknown location
+    Unknown,
+    /// -2: compiled method
+    Compiled,
+    /// -3: native method
+    Native,
+}
+
+impl From<Int> for LineNumber {
+    fn from(line_nr: Int) -> Self {
+        match line_nr as i32 {
+            0 => LineNumber::NoInfo,
+            -2 => LineNumber::Compiled,
+            -3 => LineNumber::Native,
+            line if line > 0 => LineNumber::Line(line_nr),
+            _ => LineNumber::Unknown,
+        }
+    }
+}
+
+/// # a resolved Java stack frame
+#[derive(Clone, Debug, Getters)]
+pub struct StackFrame {
+    /// 类名
+    class_name: String,
+    /// 方法名
+    method_name: String,
+    /// 方法签名
+    method_sig: String,
+    /// 源码文件名
+    src_file: String,
+    /// 行号
+    line: LineNumber,
+}
+
+impl fmt::Display for StackFrame {
+    /// 与 Java 线程栈一致，例如 `java.lang.Thread.run(Thread.java:833)`
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}(", self.class_name, self.method_name)?;
+        match self.line {
+            LineNumber::Native => write!(f, "Native Method)"),
+            LineNumber::Compiled => write!(f, "Compiled Method)"),
+            _ if self.src_file.is_empty() => write!(f, "Unknown Source)"),
+            LineNumber::Line(line) => write!(f, "{}:{})", self.src_file, line),
+            LineNumber::NoInfo | LineNumber::Unknown => write!(f, "{})", self.src_file),
+        }
+    }
+}
+
+/// # a resolved Java stack trace
+#[derive(Clone, Debug, Getters)]
+pub struct StackTrace {
+    /// stack trace serial number
+    serial_num: Int,
+    /// thread serial number
+    thread_serial_num: Int,
+    /// 栈帧，栈顶在前
+    frames: Vec<StackFrame>,
+}
+
+/// # a Java thread and its stack at the moment of the dump
+#[derive(Clone, Debug, Getters)]
+pub struct JavaThread {
+    /// thread serial number
+    serial_num: Int,
+    /// thread object ID
+    id: Long,
+    /// 线程名，HotSpot 快照只有线程对象没有 START THREAD 记录，线程名未知
+    name: Option<String>,
+    /// 线程组名
+    group_name: Option<String>,
+    /// 父线程组名
+    parent_group_name: Option<String>,
+    /// stack trace serial number
+    trace_serial_num: Int,
+    /// 调用栈
+    stack: Vec<StackFrame>,
+}
+
+impl fmt::Display for JavaThread {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "\"{}\" #{}", name, self.serial_num)?,
+            None => write!(f, "thread #{}", self.serial_num)?,
+        }
+        write!(f, " tid=0x{:x}", self.id)?;
+        if let Some(group) = &self.group_name {
+            write!(f, " group={}", group)?;
+        }
+        for frame in &self.stack {
+            write!(f, "\n\tat {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// 解析过程中收集的线程及调用栈记录
+#[derive(Clone, Debug, Default)]
+pub(crate) struct StackRecords {
+    /// frame ID -> 栈帧
+    pub frames: HashMap<Long, Frame>,
+    /// stack trace serial number -> 调用栈
+    pub traces: HashMap<Int, Trace>,
+    /// START THREAD 记录
+    pub threads: Vec<Thread>,
+    /// class serial number -> class name ID
+    pub class_names: HashMap<Int, Long>,
+}
+
+impl StackRecords {
+    /// 以名称解析调用栈，`name` 根据 UTF8 常量 ID 查找名称
+    pub fn resolve_traces(&self, name: impl Fn(&Long) -> String) -> HashMap<Int, StackTrace> {
+        self.traces.values().map(|trace| {
+            let frames = trace.frame_ids().iter()
+                .map(|frame_id| self.resolve_frame(*frame_id, &name))
+                .collect();
+            let stack = StackTrace {
+                serial_num: *trace.stack_trace_nr(),
+                thread_serial_num: *trace.thread_nr(),
+                frames,
+            };
+            (stack.serial_num, stack)
+        }).collect()
+    }
+
+    fn resolve_frame(&self, frame_id: Long, name: &impl Fn(&Long) -> String) -> StackFrame {
+        let Some(frame) = self.frames.get(&frame_id) else {
+            return StackFrame {
+                class_name: String::new(),
+                method_name: format!("unresolved frame {}", frame_id),
+                method_sig: String::new(),
+                src_file: String::new(),
+                line: LineNumber::Unknown,
+            };
+        };
+        let class_name = match self.class_names.get(frame.class_ser_num()) {
+            Some(name_id) => name(name_id),
+            None => format!("unresolved class {}", frame.class_ser_num()),
+        };
+        StackFrame {
+            class_name,
+            method_name: name(frame.method_name()),
+            method_sig: name(frame.method_sig()),
+            src_file: name(frame.src_file()),
+            line: LineNumber::from(*frame.line_nr()),
+        }
+    }
+
+    /// 线程及其调用栈
+    ///
+    /// 先取 START THREAD 记录，再补充堆转储中只有线程对象 GC root 的线程
+    pub fn resolve_threads(&self, traces: &HashMap<Int, StackTrace>, roots: &[GcRoot],
+                           name: impl Fn(&Long) -> Option<String>) -> Vec<JavaThread> {
+        let stack = |serial_num: Int| traces.get(&serial_num)
+            .map(|trace| trace.frames.clone())
+            .unwrap_or_default();
+        let mut threads: Vec<JavaThread> = self.threads.iter().map(|thread| JavaThread {
+            serial_num: *thread.thread_serial_num(),
+            id: *thread.id(),
+            name: name(thread.t_name_index()),
+            group_name: name(thread.g_name_index()),
+            parent_group_name: name(thread.p_name_index()),
+            trace_serial_num: *thread.trace_serial_num(),
+            stack: stack(*thread.trace_serial_num()),
+        }).collect();
+        for root in roots {
+            if let GcRoot::ThreadObj { id, thread_seq, stack_seq } = root {
+                if threads.iter().any(|thread| thread.serial_num == *thread_seq) {
+                    continue;
+                }
+                threads.push(JavaThread {
+                    serial_num: *thread_seq,
+                    id: *id,
+                    name: None,
+                    group_name: None,
+                    parent_group_name: None,
+                    trace_serial_num: *stack_seq,
+                    stack: stack(*stack_seq),
+                });
+            }
+        }
+        threads.sort_by_key(|thread| thread.serial_num);
+        threads
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_display() {
+        let frame = |line_nr: i32, src_file: &str| StackFrame {
+            class_name: "java.lang.Thread".to_string(),
+            method_name: "run".to_string(),
+            method_sig: "()V".to_string(),
+            src_file: src_file.to_string(),
+            line: LineNumber::from(line_nr as Int),
+        };
+        assert_eq!(frame(833, "Thread.java").to_string(), "java.lang.Thread.run(Thread.java:833)");
+        assert_eq!(frame(0, "Thread.java").to_string(), "java.lang.Thread.run(Thread.java)");
+        assert_eq!(frame(-1, "").to_string(), "java.lang.Thread.run(Unknown Source)");
+        assert_eq!(frame(-2, "Thread.java").to_string(), "java.lang.Thread.run(Compiled Method)");
+        assert_eq!(frame(-3, "Thread.java").to_string(), "java.lang.Thread.run(Native Method)");
+    }
+}
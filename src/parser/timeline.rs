@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use derive_getters::Getters;
+use crate::io::channel::{Int, Long};
+
+/// # a class or thread event from the record stream
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventKind {
+    /// LOAD CLASS
+    ClassLoad { serial_num: Int, class_id: Long, name_id: Long },
+    /// UNLOAD CLASS
+    ClassUnload { serial_num: Int },
+    /// START THREAD
+    ThreadStart { serial_num: Int, thread_id: Long, name_id: Long },
+    /// END THREAD
+    ThreadEnd { serial_num: Int },
+    /// HEAP DUMP 或第一个 HEAP DUMP SEGMENT，即快照时刻
+    HeapDump,
+}
+
+/// # an event at a point of the record stream
+#[derive(Clone, Debug, Getters)]
+pub struct Event {
+    /// 记录序号，事件按此排序
+    record: u64,
+    /// 相对文件头时间戳的微秒数
+    time: Int,
+    kind: EventKind,
+}
+
+/// # class load/unload and thread start/end events in record order
+#[derive(Clone, Debug, Default, Getters)]
+pub struct Timeline {
+    events: Vec<Event>,
+}
+
+impl Timeline {
+    pub fn push(&mut self, record: u64, time: Int, kind: EventKind) {
+        self.events.push(Event { record, time, kind });
+    }
+
+    /// 第 `dump` 次堆转储之前的事件，没有该堆转储时为全部事件
+    fn before_dump(&self, dump: usize) -> &[Event] {
+        let end = self.events.iter()
+            .enumerate()
+            .filter(|(_, event)| event.kind == EventKind::HeapDump)
+            .nth(dump)
+            .map_or(self.events.len(), |(index, _)| index);
+        &self.events[..end]
+    }
+
+    /// 第 `dump` 次堆转储时已加载且未卸载的类，按加载顺序排列
+    ///
+    /// 返回 (class serial number, class name ID)
+    pub fn live_classes(&self, dump: usize) -> Vec<(Int, Long)> {
+        let mut live: HashMap<Int, (usize, Long)> = HashMap::new();
+        for (index, event) in self.before_dump(dump).iter().enumerate() {
+            match event.kind {
+                EventKind::ClassLoad { serial_num, name_id, .. } => {
+                    live.insert(serial_num, (index, name_id));
+                }
+                EventKind::ClassUnload { serial_num } => {
+                    live.remove(&serial_num);
+                }
+                _ => {}
+            }
+        }
+        let mut classes: Vec<(usize, Int, Long)> = live.into_iter()
+            .map(|(serial_num, (index, name_id))| (index, serial_num, name_id))
+            .collect();
+        classes.sort_unstable();
+        classes.into_iter().map(|(_, serial_num, name_id)| (serial_num, name_id)).collect()
+    }
+
+    /// 第 `dump` 次堆转储时已启动且未结束的线程的 serial number
+    pub fn live_threads(&self, dump: usize) -> Vec<Int> {
+        let mut live = Vec::new();
+        for event in self.before_dump(dump) {
+            match event.kind {
+                EventKind::ThreadStart { serial_num, .. } => live.push(serial_num),
+                EventKind::ThreadEnd { serial_num } => live.retain(|serial| *serial != serial_num),
+                _ => {}
+            }
+        }
+        live
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_live_classes_at_dump() {
+        let mut timeline = Timeline::default();
+        timeline.push(0, 0, EventKind::ClassLoad { serial_num: 1, class_id: 10, name_id: 100 });
+        timeline.push(1, 5, EventKind::ClassLoad { serial_num: 2, class_id: 20, name_id: 200 });
+        timeline.push(2, 9, EventKind::ThreadStart { serial_num: 1, thread_id: 30, name_id: 300 });
+        timeline.push(3, 12, EventKind::ClassUnload { serial_num: 1 });
+        timeline.push(4, 15, EventKind::ClassLoad { serial_num: 3, class_id: 40, name_id: 400 });
+        timeline.push(5, 20, EventKind::HeapDump);
+        // 快照之后的事件不影响快照时刻的状态
+        timeline.push(6, 25, EventKind::ClassUnload { serial_num: 2 });
+        timeline.push(7, 30, EventKind::ThreadEnd { serial_num: 1 });
+        timeline.push(8, 35, EventKind::HeapDump);
+
+        assert_eq!(timeline.live_classes(0), [(2, 200), (3, 400)]);
+        assert_eq!(timeline.live_threads(0), [1]);
+        assert_eq!(timeline.live_classes(1), [(3, 400)]);
+        assert!(timeline.live_threads(1).is_empty());
+    }
+}
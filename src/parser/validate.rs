@@ -0,0 +1,409 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::fmt::Formatter;
+use std::path::Path;
+use derive_getters::Getters;
+use crate::io::channel::{Byte, Int, Long};
+use crate::parser::dump::{get_type_size, read_heap_record, Dump, HeapRecord};
+use crate::parser::error::{ParseError, Result};
+use crate::parser::hprof::{HPROF_FRAME, HPROF_HEADER_MAX_LEN, HPROF_HEAP_DUMP, HPROF_HEAP_DUMP_END,
+                           HPROF_HEAP_DUMP_SEGMENT, HPROF_LOAD_CLASS, HPROF_RECORD_HEADER_SIZE,
+                           HPROF_START_THREAD, HPROF_UTF8};
+use crate::parser::reader::{Class, Frame, Reader, Thread, Utf8};
+
+/// # a structural problem found in the file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IssueKind {
+    /// 记录长度超出文件末尾
+    RecordOverrun { tag: Byte, length: Int },
+    /// 记录内容与记录头声明的长度不一致
+    RecordLength { tag: Byte, length: Int, read: u64 },
+    /// 子记录越过所在堆转储分段的末尾
+    CrossSegment { tag: Byte, segment_end: u64 },
+    /// 对象ID重复，`first` 为第一次出现的位置
+    DuplicateId { id: Long, first: u64 },
+    /// 实例或数组引用的类没有 CLASS DUMP
+    MissingClass { class_id: Long },
+    /// 引用的 UTF8 常量不存在
+    MissingUtf8 { name_id: Long },
+    /// 实例数据长度与类的字段布局不符
+    PayloadSize { class_id: Long, expected: u64, actual: u64 },
+    /// 无法解析的内容，跳过所在的记录继续校验
+    Unreadable { message: String },
+}
+
+/// # an issue and where it was found
+#[derive(Clone, Debug, Getters)]
+pub struct Issue {
+    /// 文件字节偏移
+    offset: u64,
+    kind: IssueKind,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>12}  ", self.offset)?;
+        match &self.kind {
+            IssueKind::RecordOverrun { tag, length } =>
+                write!(f, "记录 0x{:02x} 长度 {} 超出文件末尾", tag, length),
+            IssueKind::RecordLength { tag, length, read } =>
+                write!(f, "记录 0x{:02x} 声明长度 {}，实际内容 {} 字节", tag, length, read),
+            IssueKind::CrossSegment { tag, segment_end } =>
+                write!(f, "子记录 0x{:02x} 越过分段末尾 {}", tag, segment_end),
+            IssueKind::DuplicateId { id, first } =>
+                write!(f, "对象ID 0x{:x} 重复，首次出现于 {}", id, first),
+            IssueKind::MissingClass { class_id } =>
+                write!(f, "引用的类 0x{:x} 没有 CLASS DUMP", class_id),
+            IssueKind::MissingUtf8 { name_id } =>
+                write!(f, "引用的 UTF8 常量 0x{:x} 不存在", name_id),
+            IssueKind::PayloadSize { class_id, expected, actual } =>
+                write!(f, "实例数据 {} 字节，类 0x{:x} 的字段布局为 {} 字节", actual, class_id, expected),
+            IssueKind::Unreadable { message } => write!(f, "无法解析: {}", message),
+        }
+    }
+}
+
+/// # result of validating a file
+#[derive(Clone, Debug, Default, Getters)]
+pub struct Report {
+    /// 已检查的记录数
+    records: u64,
+    /// 已检查的子记录数
+    sub_records: u64,
+    /// 按文件偏移排序
+    issues: Vec<Issue>,
+}
+
+impl Report {
+    /// 文件结构是否完整可信
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// 各类问题的数量
+    pub fn summary(&self) -> BTreeMap<&'static str, usize> {
+        let mut summary = BTreeMap::new();
+        for issue in &self.issues {
+            let name = match issue.kind {
+                IssueKind::RecordOverrun { .. } => "记录超出文件末尾",
+                IssueKind::RecordLength { .. } => "记录长度不符",
+                IssueKind::CrossSegment { .. } => "子记录越过分段",
+                IssueKind::DuplicateId { .. } => "对象ID重复",
+                IssueKind::MissingClass { .. } => "类缺失",
+                IssueKind::MissingUtf8 { .. } => "UTF8 常量缺失",
+                IssueKind::PayloadSize { .. } => "实例数据长度不符",
+                IssueKind::Unreadable { .. } => "无法解析",
+            };
+            *summary.entry(name).or_default() += 1;
+        }
+        summary
+    }
+
+    fn push(&mut self, offset: u64, kind: IssueKind) {
+        self.issues.push(Issue { offset, kind });
+    }
+}
+
+/// 一次堆转储中需要在结束时检查的内容
+#[derive(Default)]
+struct Generation {
+    /// 对象ID -> 第一次出现的位置
+    ids: HashMap<Long, u64>,
+    /// 只包含类
+    classes: Dump,
+    /// (位置, 类对象ID)，实例与对象数组引用的类
+    class_refs: Vec<(u64, Long)>,
+    /// (位置, 类对象ID, 实例数据长度)
+    instances: Vec<(u64, Long, u64)>,
+}
+
+/// 校验过程中的状态
+struct Validator {
+    report: Report,
+    id_size: Int,
+    /// 已定义的 UTF8 常量
+    symbols: HashSet<Long>,
+    /// (位置, UTF8 常量ID)，在文件末尾检查
+    name_refs: Vec<(u64, Long)>,
+    generation: Option<Generation>,
+}
+
+/// 校验快照文件的结构
+///
+/// 独立于 `hprof::read` 再读取一遍文件，尽可能在出错后继续检查，
+/// 只有无法定位下一条记录时才停止
+pub fn validate(file_path: &Path) -> Result<Report> {
+    let mut reader = Reader::new(file_path)?;
+    reader.read_cstr(HPROF_HEADER_MAX_LEN)?;
+    let id_size = reader.get_id_size()?;
+    reader.get_timestamp()?;
+
+    let mut validator = Validator {
+        report: Report::default(),
+        id_size,
+        symbols: HashSet::new(),
+        name_refs: Vec::new(),
+        generation: None,
+    };
+    if let Err(err) = validator.read_records(&mut reader) {
+        let offset = err.offset().unwrap_or_else(|| reader.complete_offset());
+        validator.report.push(offset, IssueKind::Unreadable { message: err.to_string() });
+    }
+    validator.end_generation();
+    for (offset, name_id) in std::mem::take(&mut validator.name_refs) {
+        if !validator.symbols.contains(&name_id) {
+            validator.report.push(offset, IssueKind::MissingUtf8 { name_id });
+        }
+    }
+    validator.report.issues.sort_by_key(|issue| issue.offset);
+    Ok(validator.report)
+}
+
+impl Validator {
+    fn read_records(&mut self, reader: &mut Reader) -> Result<()> {
+        let mut in_segments = false;
+        loop {
+            let start = reader.position()?;
+            let (tag, _, length) = match reader.get_header()? {
+                Some(header) => header,
+                None => return Ok(()),
+            };
+            let body = start + HPROF_RECORD_HEADER_SIZE;
+            let end = body + length as u64;
+            if end > reader.size() {
+                self.report.push(start, IssueKind::RecordOverrun { tag, length });
+                return Ok(());
+            }
+            let mut parsed = true;
+            match tag {
+                HPROF_UTF8 => {
+                    let utf8 = reader.read::<Utf8>(length)?;
+                    self.symbols.insert(*utf8.symbol_id());
+                }
+                HPROF_LOAD_CLASS => {
+                    let class = reader.read::<Class>(length)?;
+                    self.name_ref(start, *class.name_id());
+                }
+                HPROF_FRAME => {
+                    let frame = reader.read::<Frame>(length)?;
+                    for name_id in [frame.method_name(), frame.method_sig(), frame.src_file()] {
+                        self.name_ref(start, *name_id);
+                    }
+                }
+                HPROF_START_THREAD => {
+                    let thread = reader.read::<Thread>(length)?;
+                    for name_id in [thread.t_name_index(), thread.g_name_index(), thread.p_name_index()] {
+                        self.name_ref(start, *name_id);
+                    }
+                }
+                HPROF_HEAP_DUMP | HPROF_HEAP_DUMP_SEGMENT => {
+                    if tag == HPROF_HEAP_DUMP || !in_segments {
+                        self.end_generation();
+                        self.generation = Some(Generation::default());
+                    }
+                    in_segments = tag == HPROF_HEAP_DUMP_SEGMENT;
+                    self.read_segment(reader, end)?;
+                    parsed = false;
+                }
+                HPROF_HEAP_DUMP_END => {
+                    in_segments = false;
+                    self.end_generation();
+                    parsed = false;
+                }
+                _ => parsed = false,
+            }
+            let read = reader.position()? - body;
+            if parsed && read != length as u64 {
+                self.report.push(start, IssueKind::RecordLength { tag, length, read });
+            }
+            self.report.records += 1;
+            // 以记录头声明的长度定位下一条记录
+            reader.seek(end)?;
+        }
+    }
+
+    /// 检查一个堆转储分段中的子记录
+    fn read_segment(&mut self, reader: &mut Reader, end: u64) -> Result<()> {
+        let mut position = reader.position()?;
+        while position < end {
+            let record = match read_heap_record(reader) {
+                Ok(record) => record,
+                Err(ParseError::Truncated { .. }) => {
+                    self.report.push(position, IssueKind::CrossSegment { tag: reader.tag().unwrap_or(0), segment_end: end });
+                    return Ok(());
+                }
+                // 子记录没有长度字段，跳过分段剩余的内容
+                Err(err) => {
+                    self.report.push(position, IssueKind::Unreadable { message: err.to_string() });
+                    return Ok(());
+                }
+            };
+            self.report.sub_records += 1;
+            let next = reader.position()?;
+            if next > end {
+                self.report.push(position, IssueKind::CrossSegment { tag: reader.tag().unwrap_or(0), segment_end: end });
+                return Ok(());
+            }
+            self.check_record(position, record);
+            position = next;
+        }
+        Ok(())
+    }
+
+    fn check_record(&mut self, offset: u64, record: HeapRecord) {
+        let id = match &record {
+            HeapRecord::Class(class) => Some(*class.id()),
+            HeapRecord::Instance(obj) => Some(*obj.id()),
+            HeapRecord::ObjectArray(array) => Some(*array.id()),
+            HeapRecord::PrimitiveArray(array) => Some(*array.id()),
+            HeapRecord::HeapInfo { .. } | HeapRecord::Root(_) => None,
+        };
+        let generation = self.generation.get_or_insert_with(Generation::default);
+        if let Some(id) = id {
+            if let Some(first) = generation.ids.get(&id) {
+                self.report.push(offset, IssueKind::DuplicateId { id, first: *first });
+            } else {
+                generation.ids.insert(id, offset);
+            }
+        }
+        match record {
+            HeapRecord::HeapInfo { name_id, .. } => self.name_ref(offset, name_id),
+            HeapRecord::Class(class) => {
+                let names: Vec<Long> = class.static_fields().iter().map(|field| *field.name_id())
+                    .chain(class.fields().iter().map(|field| *field.name_id()))
+                    .collect();
+                generation.classes.push(HeapRecord::Class(class));
+                for name_id in names {
+                    self.name_ref(offset, name_id);
+                }
+            }
+            HeapRecord::Instance(obj) => {
+                generation.class_refs.push((offset, *obj.class_id()));
+                generation.instances.push((offset, *obj.class_id(), obj.payload().len() as u64));
+            }
+            HeapRecord::ObjectArray(array) => generation.class_refs.push((offset, *array.class_id())),
+            HeapRecord::PrimitiveArray(_) | HeapRecord::Root(_) => {}
+        }
+    }
+
+    /// 一次堆转储结束，检查类引用与实例数据长度
+    fn end_generation(&mut self) {
+        let Some(generation) = self.generation.take() else {
+            return;
+        };
+        let mut missing = HashSet::new();
+        for class in generation.classes.classes() {
+            let super_id = *class.super_id();
+            if super_id != 0 && generation.classes.get_class(super_id).is_none() {
+                missing.insert(super_id);
+                self.report.push(generation.ids[class.id()], IssueKind::MissingClass { class_id: super_id });
+            }
+        }
+        for (offset, class_id) in generation.class_refs {
+            if generation.classes.get_class(class_id).is_none() {
+                missing.insert(class_id);
+                self.report.push(offset, IssueKind::MissingClass { class_id });
+            }
+        }
+        let mut layout_sizes: HashMap<Long, Option<u64>> = HashMap::new();
+        for (offset, class_id, actual) in generation.instances {
+            if missing.contains(&class_id) {
+                continue;
+            }
+            let expected = *layout_sizes.entry(class_id).or_insert_with(|| {
+                generation.classes.get_field_layout(class_id).ok().map(|layout| {
+                    layout.iter().map(|field| get_type_size(*field.field_type(), self.id_size) as u64).sum()
+                })
+            });
+            // 父类缺失时无法计算布局，已作为类缺失报告
+            if let Some(expected) = expected {
+                if expected != actual {
+                    self.report.push(offset, IssueKind::PayloadSize { class_id, expected, actual });
+                }
+            }
+        }
+    }
+
+    /// 记录一个 UTF8 常量引用，0 表示没有名称
+    fn name_ref(&mut self, offset: u64, name_id: Long) {
+        if name_id != 0 {
+            self.name_refs.push((offset, name_id));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::parser::hprof::HPROF_HEADER_102;
+    use super::*;
+
+    fn push_record(data: &mut Vec<u8>, tag: Byte, body: &[u8]) {
+        data.push(tag);
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        data.extend_from_slice(body);
+    }
+
+    fn ints(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn test_validate_report() {
+        let mut data = HPROF_HEADER_102.as_bytes().to_vec();
+        data.push(0);
+        data.extend_from_slice(&4u32.to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        // name ID 7 没有对应的 UTF8 记录
+        let load_class = data.len() as u64;
+        push_record(&mut data, HPROF_LOAD_CLASS, &ints(&[1, 100, 0, 7]));
+
+        let segment = data.len() as u64 + HPROF_RECORD_HEADER_SIZE;
+        // 类 100：一个 int 实例字段
+        let mut body = vec![0x20];
+        body.extend(ints(&[100, 0, 0, 0, 0, 0, 0, 0, 4]));
+        body.extend_from_slice(&[0, 0, 0, 0, 0, 1]);
+        body.extend(ints(&[1]));
+        body.push(10);
+        // 实例 200 的数据只有 2 字节
+        let instance = segment + body.len() as u64;
+        body.push(0x21);
+        body.extend(ints(&[200, 0, 100, 2]));
+        body.extend_from_slice(&[0, 0]);
+        // 重复的ID 200，类 300 不存在
+        let duplicate = segment + body.len() as u64;
+        body.push(0x21);
+        body.extend(ints(&[200, 0, 300, 0]));
+        // 最后一个子记录越过分段末尾
+        let crossing = segment + body.len() as u64;
+        body.push(0x21);
+        body.extend(ints(&[400, 0, 100]));
+        push_record(&mut data, HPROF_HEAP_DUMP_SEGMENT, &body);
+        push_record(&mut data, HPROF_HEAP_DUMP_END, &[]);
+        // 长度超出文件末尾
+        let overrun = data.len() as u64;
+        data.push(HPROF_UTF8);
+        data.extend(ints(&[0, 100]));
+
+        let path = std::env::temp_dir().join(format!("jsnap-validate-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let report = validate(&path);
+        let _ = fs::remove_file(&path);
+        let report = report.unwrap();
+
+        let issues: Vec<(u64, IssueKind)> = report.issues().iter()
+            .map(|issue| (*issue.offset(), issue.kind().clone()))
+            .collect();
+        assert_eq!(issues, [
+            (load_class, IssueKind::MissingUtf8 { name_id: 7 }),
+            (segment, IssueKind::MissingUtf8 { name_id: 1 }),
+            (instance, IssueKind::PayloadSize { class_id: 100, expected: 4, actual: 2 }),
+            (duplicate, IssueKind::DuplicateId { id: 200, first: instance }),
+            (duplicate, IssueKind::MissingClass { class_id: 300 }),
+            (crossing, IssueKind::CrossSegment { tag: 0x21, segment_end: overrun - HPROF_RECORD_HEADER_SIZE }),
+            (overrun, IssueKind::RecordOverrun { tag: HPROF_UTF8, length: 100 }),
+        ]);
+        assert_eq!(*report.records(), 3);
+    }
+}
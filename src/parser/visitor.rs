@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::path::Path;
+use crate::io::channel::{Byte, Int, Long};
+use crate::parser::dump::{get_heap_dump, ClassObject, GcRoot, Heap, HeapRecord, NormalObject, ObjectArray, PrimitiveArray};
+use crate::parser::error::{ParseError, Result};
+use crate::parser::hprof::{read_header, HPROF_ALLOC_SITES, HPROF_CONTROL_SETTINGS, HPROF_CPU_SAMPLES, HPROF_END_THREAD,
+                           HPROF_FRAME, HPROF_HEAP_DUMP, HPROF_HEAP_DUMP_END, HPROF_HEAP_DUMP_SEGMENT,
+                           HPROF_HEAP_SUMMARY, HPROF_LOAD_CLASS, HPROF_RECORD_HEADER_SIZE, HPROF_START_THREAD,
+                           HPROF_TRACE, HPROF_UNLOAD_CLASS, HPROF_UTF8};
+use crate::parser::reader::{AllocSites, Class, ControlSettings, CpuSamples, Frame, HeapSummary, Reader, Thread, Trace, Utf8};
+
+/// # Callbacks for each record and heap dump sub-record
+///
+/// 每条记录读取后立即交给对应的方法，不保留在内存中，可以单遍处理比内存更大的快照。
+/// 所有方法都有空的默认实现，只需实现关心的记录类型
+/// # Examples
+/// ```rust,no_run
+/// use std::path::Path;
+/// use jsnap::parser::dump::NormalObject;
+/// use jsnap::parser::visitor::{stream, Visitor};
+///
+/// #[derive(Default)]
+/// struct Counter {
+///     instances: u64,
+///     bytes: u64,
+/// }
+///
+/// impl Visitor for Counter {
+///     fn on_instance(&mut self, obj: NormalObject) {
+///         self.instances += 1;
+///         self.bytes += obj.payload().len() as u64;
+///     }
+/// }
+///
+/// let mut counter = Counter::default();
+/// stream(Path::new("heap.hprof"), &mut counter)?;
+/// # Ok::<(), jsnap::ParseError>(())
+/// ```
+pub trait Visitor {
+    /// 文件头：版本、id 大小、时间戳（毫秒）
+    fn on_header(&mut self, _version: &str, _id_size: Int, _timestamp: Long) {}
+    /// 每条记录开始时调用，随后调用该记录类型对应的方法
+    fn on_record(&mut self, _tag: Byte, _time: Int, _length: Int) {}
+    fn on_utf8(&mut self, _utf8: Utf8) {}
+    fn on_load_class(&mut self, _class: Class) {}
+    fn on_unload_class(&mut self, _serial_num: Int) {}
+    fn on_frame(&mut self, _frame: Frame) {}
+    fn on_trace(&mut self, _trace: Trace) {}
+    fn on_alloc_sites(&mut self, _sites: AllocSites) {}
+    fn on_heap_summary(&mut self, _summary: HeapSummary) {}
+    fn on_start_thread(&mut self, _thread: Thread) {}
+    fn on_end_thread(&mut self, _serial_num: Int) {}
+    fn on_cpu_samples(&mut self, _samples: CpuSamples) {}
+    fn on_control_settings(&mut self, _settings: ControlSettings) {}
+    /// HEAP DUMP 或 HEAP DUMP SEGMENT 记录体开始于 `offset`，返回 `false` 时跳过其中的子记录
+    fn on_heap_dump(&mut self, _tag: Byte, _offset: u64, _length: Int) -> bool {
+        true
+    }
+    fn on_heap_dump_end(&mut self) {}
+    /// Android: 之后的对象属于 `heap`
+    fn on_heap_info(&mut self, _heap: Heap, _name_id: Long) {}
+    fn on_gc_root(&mut self, _root: GcRoot) {}
+    fn on_class_dump(&mut self, _class: ClassObject) {}
+    fn on_instance(&mut self, _obj: NormalObject) {}
+    fn on_object_array(&mut self, _array: ObjectArray) {}
+    fn on_primitive_array(&mut self, _array: PrimitiveArray) {}
+}
+
+/// 单遍读取快照文件，每条记录交给 `visitor`
+pub fn stream<V: Visitor>(file_path: &Path, visitor: &mut V) -> Result<()> {
+    let mut reader = Reader::new(file_path)?;
+    let (version, id_size, timestamp) = read_header(&mut reader)?;
+    visitor.on_header(&version, id_size, timestamp);
+    while let Some((tag, time, length)) = reader.get_header()? {
+        visitor.on_record(tag, time, length);
+        let end = reader.position()? + length as u64;
+        read_record(&mut reader, tag, length, visitor)?;
+        check_record_end(&mut reader, tag, length, end)?;
+    }
+    Ok(())
+}
+
+/// 读取一条记录后检查是否正好读到记录头长度所指的结束位置 `end`
+///
+/// 记录内容与长度不一致时，之后的记录头都会错位，返回 `Malformed`
+pub fn check_record_end(reader: &mut Reader, tag: Byte, length: Int, end: u64) -> Result<()> {
+    let position = reader.position()?;
+    if position == end {
+        return Ok(());
+    }
+    let body = end - length as u64;
+    Err(ParseError::malformed(format!("记录长度与内容不一致: 长度 {}，实际读取 {} 字节，position {}，记录类型 0x{:02x}",
+                                      length, position.saturating_sub(body), body - HPROF_RECORD_HEADER_SIZE, tag)))
+}
+
+/// 读取一条记录的内容，交给 `visitor`
+pub fn read_record<V: Visitor + ?Sized>(reader: &mut Reader, tag: Byte, length: Int, visitor: &mut V) -> Result<()> {
+    match tag {
+        // a UTF8-encoded name
+        HPROF_UTF8 => visitor.on_utf8(reader.read::<Utf8>(length)?),
+        // a newly loaded class
+        HPROF_LOAD_CLASS => visitor.on_load_class(reader.read::<Class>(length)?),
+        // an unloading class
+        HPROF_UNLOAD_CLASS => visitor.on_unload_class(reader.read_int()?),
+        // a Java stack frame
+        HPROF_FRAME => visitor.on_frame(reader.read::<Frame>(length)?),
+        // a Java stack trace
+        HPROF_TRACE => visitor.on_trace(reader.read::<Trace>(length)?),
+        // a set of heap allocation sites, obtained after GC
+        HPROF_ALLOC_SITES => visitor.on_alloc_sites(reader.read::<AllocSites>(length)?),
+        // heap summary
+        HPROF_HEAP_SUMMARY => visitor.on_heap_summary(reader.read::<HeapSummary>(length)?),
+        // a newly started thread.
+        HPROF_START_THREAD => visitor.on_start_thread(reader.read::<Thread>(length)?),
+        // a terminating thread.
+        HPROF_END_THREAD => visitor.on_end_thread(reader.read_int()?),
+        // a set of sample traces of running threads
+        HPROF_CPU_SAMPLES => visitor.on_cpu_samples(reader.read::<CpuSamples>(length)?),
+        // the settings of on/off switches
+        HPROF_CONTROL_SETTINGS => visitor.on_control_settings(reader.read::<ControlSettings>(length)?),
+        HPROF_HEAP_DUMP | HPROF_HEAP_DUMP_SEGMENT => {
+            // denote a heap dump, or a heap dump segment
+            let offset = reader.position()?;
+            if visitor.on_heap_dump(tag, offset, length) {
+                get_heap_dump(reader, length, visitor)?;
+            } else {
+                reader.skip(length as Long)?;
+            }
+        }
+        HPROF_HEAP_DUMP_END => {
+            //  denotes the end of a heap dump
+            visitor.on_heap_dump_end();
+            reader.skip(length as Long)?;
+        }
+        _ => reader.skip(length as Long)?,
+    }
+    Ok(())
+}
+
+/// 将一条堆转储子记录交给 `visitor`
+pub fn visit_heap_record<V: Visitor + ?Sized>(visitor: &mut V, record: HeapRecord) {
+    match record {
+        HeapRecord::HeapInfo { heap, name_id } => visitor.on_heap_info(heap, name_id),
+        HeapRecord::Root(root) => visitor.on_gc_root(root),
+        HeapRecord::Class(class) => visitor.on_class_dump(class),
+        HeapRecord::Instance(obj) => visitor.on_instance(obj),
+        HeapRecord::ObjectArray(array) => visitor.on_object_array(array),
+        HeapRecord::PrimitiveArray(array) => visitor.on_primitive_array(array),
+    }
+}
+
+/// # Prints records as they are read, for debugging
+#[derive(Default)]
+pub struct DebugVisitor {
+    /// UTF8 常量，用于打印类名
+    symbols: HashMap<Long, String>,
+}
+
+impl Visitor for DebugVisitor {
+    fn on_header(&mut self, version: &str, id_size: Int, timestamp: Long) {
+        println!("{}, id size {}, timestamp {}", version, id_size, timestamp);
+    }
+
+    fn on_utf8(&mut self, utf8: Utf8) {
+        self.symbols.insert(*utf8.symbol_id(), utf8.name().to_string());
+    }
+
+    fn on_load_class(&mut self, class: Class) {
+        match self.symbols.get(class.name_id()) {
+            Some(name) => println!("{}", name.replace('/', ".")),
+            None => println!("unresolved name {}", class.name_id()),
+        }
+    }
+
+    fn on_unload_class(&mut self, serial_num: Int) {
+        println!("unload {}", serial_num);
+    }
+
+    fn on_heap_summary(&mut self, summary: HeapSummary) {
+        println!("summary: {}", summary.live());
+    }
+
+    fn on_control_settings(&mut self, settings: ControlSettings) {
+        println!("settings: {}", settings.flags());
+    }
+
+    fn on_gc_root(&mut self, root: GcRoot) {
+        if let GcRoot::ThreadObj { id, .. } | GcRoot::ThreadBlock { id, .. } = root {
+            println!("gc-root, thread {}", id);
+        }
+    }
+
+    fn on_class_dump(&mut self, class: ClassObject) {
+        println!("class {}", class.id());
+    }
+
+    fn on_instance(&mut self, obj: NormalObject) {
+        println!("obj {}", obj.id());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::parser::hprof::HPROF_HEADER_102;
+    use super::*;
+
+    /// 统计各类记录的数量
+    #[derive(Default)]
+    struct Counter {
+        records: u64,
+        utf8: u64,
+        roots: u64,
+        instances: u64,
+        payload: u64,
+    }
+
+    impl Visitor for Counter {
+        fn on_record(&mut self, _: Byte, _: Int, _: Int) {
+            self.records += 1;
+        }
+
+        fn on_utf8(&mut self, _: Utf8) {
+            self.utf8 += 1;
+        }
+
+        fn on_gc_root(&mut self, _: GcRoot) {
+            self.roots += 1;
+        }
+
+        fn on_instance(&mut self, obj: NormalObject) {
+            self.instances += 1;
+            self.payload += obj.payload().len() as u64;
+        }
+    }
+
+    #[test]
+    fn test_stream_counts() {
+        let mut data = HPROF_HEADER_102.as_bytes().to_vec();
+        data.push(0);
+        data.extend_from_slice(&4u32.to_be_bytes());
+        data.extend_from_slice(&0u64.to_be_bytes());
+        let mut records = vec![(HPROF_UTF8, [7u32.to_be_bytes().to_vec(), b"Foo".to_vec()].concat())];
+        let mut body = vec![0xff];
+        body.extend_from_slice(&200u32.to_be_bytes());
+        for id in [200u32, 201] {
+            body.push(0x21);
+            for value in [id, 0, 100, 4, 0] {
+                body.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        records.push((HPROF_HEAP_DUMP_SEGMENT, body));
+        records.push((HPROF_HEAP_DUMP_END, Vec::new()));
+        for (tag, body) in records {
+            data.push(tag);
+            data.extend_from_slice(&0u32.to_be_bytes());
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend_from_slice(&body);
+        }
+
+        let path = std::env::temp_dir().join(format!("jsnap-visitor-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let mut counter = Counter::default();
+        let result = stream(&path, &mut counter);
+        let _ = fs::remove_file(&path);
+        result.unwrap();
+
+        assert_eq!(counter.records, 3);
+        assert_eq!(counter.utf8, 1);
+        assert_eq!(counter.roots, 1);
+        assert_eq!(counter.instances, 2);
+        assert_eq!(counter.payload, 8);
+    }
+}
@@ -0,0 +1,827 @@
+use std::io::Write;
+use crate::io::channel::{Byte, Int, Long};
+use crate::parser::dump::{get_type_size, ArrayData, ClassObject, Dump, FieldInfo, GcRoot, Heap, HeapRecord, NormalObject, ObjectArray,
+                          PrimitiveArray, Value, HPROF_GC_CLASS_DUMP, HPROF_GC_INSTANCE_DUMP,
+                          HPROF_GC_OBJ_ARRAY_DUMP, HPROF_GC_PRIM_ARRAY_DUMP, HPROF_GC_PRIM_ARRAY_NODATA_DUMP,
+                          HPROF_GC_ROOT_DEBUGGER, HPROF_GC_ROOT_FINALIZING, HPROF_GC_ROOT_INTERNED_STRING,
+                          HPROF_GC_ROOT_JAVA_FRAME, HPROF_GC_ROOT_JNI_GLOBAL, HPROF_GC_ROOT_JNI_LOCAL,
+                          HPROF_GC_ROOT_JNI_MONITOR, HPROF_GC_ROOT_MONITOR_USED, HPROF_GC_ROOT_NATIVE_STACK,
+                          HPROF_GC_ROOT_REFERENCE_CLEANUP, HPROF_GC_ROOT_STICKY_CLASS, HPROF_GC_ROOT_THREAD_BLOCK,
+                          HPROF_GC_ROOT_THREAD_OBJ, HPROF_GC_ROOT_UNKNOWN, HPROF_GC_ROOT_VM_INTERNAL,
+                          HPROF_GC_UNREACHABLE, HPROF_HEAP_DUMP_INFO};
+use crate::parser::error::{ParseError, Result};
+use crate::parser::hprof::{HPROF_HEADER_101, HPROF_HEADER_102, HPROF_HEADER_103, HPROF_HEAP_DUMP_END,
+                           HPROF_HEAP_DUMP_SEGMENT, HPROF_LOAD_CLASS, HPROF_START_THREAD, HPROF_UTF8};
+use crate::parser::reader::{AllocSites, Class, ControlSettings, CpuSamples, Frame, HeapSummary, Reader, Thread, Trace, Utf8};
+use crate::store::ids::{CompactId, IdMap};
+
+/// 堆转储分段达到该字节数后写出，开始下一个分段
+pub const DEFAULT_SEGMENT_SIZE: usize = 1 << 30;
+
+/// # A section written to the hprof file, the reverse of `Section`
+///
+/// 顶层记录只写记录体，记录头由 `Writer` 写入；堆转储子记录包含其 tag
+pub trait Emit {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()>;
+}
+
+/// # Big-endian encoder of a record body
+pub struct Encoder {
+    buf: Vec<u8>,
+    /// oop id 大小
+    id_size: Int,
+    /// 是否允许 Android (JAVA PROFILE 1.0.3) 扩展的子记录
+    android: bool,
+    /// 原快照文件，用于写出未读入内存的大数组
+    source: Option<Reader>,
+}
+
+impl Encoder {
+    /// `android` 为 `true` 时允许 Android 扩展的子记录
+    pub fn new(id_size: Int, android: bool) -> Self {
+        Encoder { buf: Vec::new(), id_size, android, source: None }
+    }
+
+    /// 已编码的内容
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    pub fn put_byte(&mut self, value: Byte) {
+        self.buf.push(value);
+    }
+
+    pub fn put_short(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_int(&mut self, value: Int) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_long(&mut self, value: Long) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    /// 按 id 大小写入 ID
+    pub fn put_id(&mut self, id: Long) {
+        if self.id_size == 4 {
+            self.put_int(id as Int);
+        } else {
+            self.put_long(id);
+        }
+    }
+
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// 写入值，不含类型
+    pub fn put_value(&mut self, value: &Value) {
+        match *value {
+            Value::Id(id) => self.put_id(id),
+            Value::Bool(b) => self.put_byte(b as Byte),
+            Value::Char(c) => self.put_short(c),
+            Value::Float(bits) => self.put_int(bits),
+            Value::Double(bits) => self.put_long(bits),
+            Value::Byte(b) => self.put_byte(b),
+            Value::Short(s) => self.put_short(s),
+            Value::Int(i) => self.put_int(i),
+            Value::Long(l) => self.put_long(l),
+        }
+    }
+
+    /// 写入基本类型数组的元素
+    fn put_array(&mut self, data: &ArrayData) {
+        match data {
+            ArrayData::Bool(v) => v.iter().for_each(|b| self.put_byte(*b as Byte)),
+            ArrayData::Char(v) | ArrayData::Short(v) => v.iter().for_each(|c| self.put_short(*c)),
+            ArrayData::Float(v) => v.iter().for_each(|f| self.put_int(f.to_bits())),
+            ArrayData::Double(v) => v.iter().for_each(|d| self.put_long(d.to_bits())),
+            ArrayData::Byte(v) => self.put_bytes(v),
+            ArrayData::Int(v) => v.iter().for_each(|i| self.put_int(*i)),
+            ArrayData::Long(v) => v.iter().for_each(|l| self.put_long(*l)),
+            ArrayData::Lazy { .. } | ArrayData::NoData => {}
+        }
+    }
+
+    /// Android 扩展的子记录只能写入 1.0.3 文件
+    fn require_android(&self, name: &str) -> Result<()> {
+        if self.android {
+            Ok(())
+        } else {
+            Err(ParseError::malformed(format!("{} 只能写入 {} 文件", name, HPROF_HEADER_103)))
+        }
+    }
+}
+
+/// # Hprof File Writer
+///
+/// 依次写出记录，堆转储子记录缓存后按 HEAP DUMP SEGMENT 分段写出
+/// # Examples
+/// ```rust,no_run
+/// use std::fs::File;
+/// use std::io::BufWriter;
+/// use jsnap::parser::dump::{GcRoot, NormalObject};
+/// use jsnap::parser::hprof::{HPROF_LOAD_CLASS, HPROF_UTF8};
+/// use jsnap::parser::reader::{Class, Utf8};
+/// use jsnap::parser::writer::Writer;
+///
+/// let out = BufWriter::new(File::create("out.hprof")?);
+/// let mut writer = Writer::new(out, 8, 0)?;
+/// writer.write(HPROF_UTF8, 0, &Utf8::new(1, "Foo"))?;
+/// writer.write(HPROF_LOAD_CLASS, 0, &Class::new(1, 100, 1))?;
+/// writer.write_heap_record(&GcRoot::StickyClass { id: 100 })?;
+/// writer.write_heap_record(&NormalObject::new(200, 100, Vec::new()))?;
+/// writer.finish()?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct Writer<W: Write> {
+    out: W,
+    /// 顶层记录体
+    record: Encoder,
+    /// 尚未写出的堆转储子记录
+    segment: Encoder,
+    segment_size: usize,
+    /// 已写出的字节数
+    position: u64,
+}
+
+impl<W: Write> Writer<W> {
+    /// 写入 JAVA PROFILE 1.0.2 文件头
+    pub fn new(out: W, id_size: Int, timestamp: Long) -> Result<Self> {
+        Self::with_version(out, HPROF_HEADER_102, id_size, timestamp)
+    }
+
+    /// 写入指定版本的文件头，1.0.3 允许 Android 扩展的子记录
+    pub fn with_version(out: W, version: &str, id_size: Int, timestamp: Long) -> Result<Self> {
+        if ![HPROF_HEADER_101, HPROF_HEADER_102, HPROF_HEADER_103].contains(&version) {
+            return Err(ParseError::UnsupportedVersion { version: version.to_string() });
+        }
+        if id_size != 4 && id_size != 8 {
+            return Err(ParseError::BadIdSize { offset: version.len() as u64 + 1, id_size });
+        }
+        let android = version == HPROF_HEADER_103;
+        let mut writer = Writer {
+            out,
+            record: Encoder::new(id_size, android),
+            segment: Encoder::new(id_size, android),
+            segment_size: DEFAULT_SEGMENT_SIZE,
+            position: 0,
+        };
+        let mut header = version.as_bytes().to_vec();
+        header.push(0);
+        header.extend_from_slice(&id_size.to_be_bytes());
+        header.extend_from_slice(&timestamp.to_be_bytes());
+        writer.put(&header, None)?;
+        Ok(writer)
+    }
+
+    /// 原快照文件，写出未读入内存的大数组时从中读取元素
+    pub fn set_source(&mut self, source: Reader) {
+        self.segment.source = Some(source);
+    }
+
+    /// 设置堆转储分段大小
+    pub fn set_segment_size(&mut self, size: usize) {
+        self.segment_size = size;
+    }
+
+    /// 写出一条顶层记录，`time` 为相对文件头时间戳的微秒数
+    pub fn write<T: Emit + ?Sized>(&mut self, tag: Byte, time: Int, record: &T) -> Result<()> {
+        self.record.buf.clear();
+        record.emit(&mut self.record)?;
+        let body = std::mem::take(&mut self.record.buf);
+        let result = self.put_record(tag, time, &body);
+        self.record.buf = body;
+        result
+    }
+
+    /// 追加一条堆转储子记录，分段达到大小后写出
+    pub fn write_heap_record<T: Emit + ?Sized>(&mut self, record: &T) -> Result<()> {
+        let start = self.segment.buf.len();
+        if let Err(err) = record.emit(&mut self.segment) {
+            // 丢弃写了一半的子记录
+            self.segment.buf.truncate(start);
+            return Err(err);
+        }
+        if self.segment.buf.len() >= self.segment_size {
+            self.flush_segment()?;
+        }
+        Ok(())
+    }
+
+    /// 写出剩余的子记录并结束当前堆转储，之后的子记录属于下一次堆转储
+    pub fn end_heap_dump(&mut self) -> Result<()> {
+        self.flush_segment()?;
+        self.put_record(HPROF_HEAP_DUMP_END, 0, &[])
+    }
+
+    /// 写出一次完整的堆转储
+    ///
+    /// 依次写出 GC root、类、实例、对象数组、基本类型数组；1.0.3 文件在所属堆变化处写出
+    /// HEAP DUMP INFO，堆名称未保留，名称 ID 为 0
+    pub fn write_dump(&mut self, dump: &Dump) -> Result<()> {
+        for root in dump.roots() {
+            self.write_heap_record(root)?;
+        }
+        let mut heap = None;
+        for class in dump.classes() {
+            self.switch_heap(&mut heap, class.heap())?;
+            self.write_heap_record(class)?;
+        }
+        for obj in dump.objects() {
+            self.switch_heap(&mut heap, obj.heap())?;
+            self.write_heap_record(&Resolved::new(obj, dump.ids()))?;
+        }
+        for array in dump.object_arrays() {
+            self.switch_heap(&mut heap, array.heap())?;
+            self.write_heap_record(&Resolved::new(array, dump.ids()))?;
+        }
+        for array in dump.primitive_arrays() {
+            self.switch_heap(&mut heap, array.heap())?;
+            self.write_heap_record(&Resolved::new(array, dump.ids()))?;
+        }
+        self.end_heap_dump()
+    }
+
+    fn switch_heap(&mut self, current: &mut Option<Heap>, heap: Heap) -> Result<()> {
+        if !self.segment.android || *current == Some(heap) {
+            return Ok(());
+        }
+        *current = Some(heap);
+        self.write_heap_record(&HeapRecord::HeapInfo { heap, name_id: 0 })
+    }
+
+    /// 结束写入，返回底层输出
+    pub fn finish(mut self) -> Result<W> {
+        if !self.segment.buf.is_empty() {
+            self.end_heap_dump()?;
+        }
+        let position = self.position;
+        self.out.flush().map_err(|err| ParseError::from_io(err, position, None))?;
+        Ok(self.out)
+    }
+
+    fn flush_segment(&mut self) -> Result<()> {
+        if self.segment.buf.is_empty() {
+            return Ok(());
+        }
+        let body = std::mem::take(&mut self.segment.buf);
+        let result = self.put_record(HPROF_HEAP_DUMP_SEGMENT, 0, &body);
+        self.segment.buf = body;
+        self.segment.buf.clear();
+        result
+    }
+
+    fn put_record(&mut self, tag: Byte, time: Int, body: &[u8]) -> Result<()> {
+        let length = Int::try_from(body.len())
+            .map_err(|_| ParseError::malformed(format!("记录 0x{:02x} 长度 {} 超出 u32", tag, body.len())))?;
+        let mut header = [0u8; 9];
+        header[0] = tag;
+        header[1..5].copy_from_slice(&time.to_be_bytes());
+        header[5..].copy_from_slice(&length.to_be_bytes());
+        self.put(&header, Some(tag))?;
+        self.put(body, Some(tag))
+    }
+
+    fn put(&mut self, bytes: &[u8], tag: Option<Byte>) -> Result<()> {
+        let position = self.position;
+        self.out.write_all(bytes).map_err(|err| ParseError::from_io(err, position, tag))?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+}
+
+/// UNLOAD CLASS 及 END THREAD 的记录体
+impl Emit for Int {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_int(*self);
+        Ok(())
+    }
+}
+
+impl Emit for Utf8 {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_id(*self.symbol_id());
+        encoder.put_bytes(self.name().as_bytes());
+        Ok(())
+    }
+}
+
+impl Emit for Class {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_int(*self.serial_num());
+        encoder.put_id(*self.id());
+        encoder.put_int(*self.stack_trace_ser());
+        encoder.put_id(*self.name_id());
+        Ok(())
+    }
+}
+
+impl Emit for Frame {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_id(*self.id());
+        encoder.put_id(*self.method_name());
+        encoder.put_id(*self.method_sig());
+        encoder.put_id(*self.src_file());
+        encoder.put_int(*self.class_ser_num());
+        encoder.put_int(*self.line_nr());
+        Ok(())
+    }
+}
+
+impl Emit for Trace {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_int(*self.stack_trace_nr());
+        encoder.put_int(*self.thread_nr());
+        encoder.put_int(self.frame_ids().len() as Int);
+        self.frame_ids().iter().for_each(|id| encoder.put_id(*id));
+        Ok(())
+    }
+}
+
+impl Emit for Thread {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_int(*self.thread_serial_num());
+        encoder.put_id(*self.id());
+        encoder.put_int(*self.trace_serial_num());
+        encoder.put_id(*self.t_name_index());
+        encoder.put_id(*self.g_name_index());
+        encoder.put_id(*self.p_name_index());
+        Ok(())
+    }
+}
+
+impl Emit for HeapSummary {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_int(*self.live());
+        encoder.put_int(*self.live_inst());
+        encoder.put_long(*self.allocate());
+        encoder.put_long(*self.allocate_inst());
+        Ok(())
+    }
+}
+
+impl Emit for CpuSamples {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_int(*self.num());
+        encoder.put_int(self.samples().len() as Int);
+        for sample in self.samples() {
+            encoder.put_int(*sample.count());
+            encoder.put_int(*sample.trace_serial_num());
+        }
+        Ok(())
+    }
+}
+
+impl Emit for ControlSettings {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_int(*self.flags());
+        encoder.put_short(*self.depth());
+        Ok(())
+    }
+}
+
+impl Emit for AllocSites {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_short(*self.flags());
+        encoder.put_int(self.cutoff_ratio().to_bits());
+        encoder.put_int(*self.total_live_bytes());
+        encoder.put_int(*self.total_live_inst());
+        encoder.put_long(*self.total_bytes_allocated());
+        encoder.put_long(*self.total_inst_allocated());
+        encoder.put_int(self.sites().len() as Int);
+        for site in self.sites() {
+            encoder.put_byte(*site.array_type());
+            encoder.put_int(*site.class_serial_num());
+            encoder.put_int(*site.trace_serial_num());
+            encoder.put_int(*site.live_bytes());
+            encoder.put_int(*site.live_inst());
+            encoder.put_int(*site.bytes_allocated());
+            encoder.put_int(*site.inst_allocated());
+        }
+        Ok(())
+    }
+}
+
+impl Emit for GcRoot {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        let android = |encoder: &mut Encoder, tag: Byte, id: Long| -> Result<()> {
+            encoder.require_android("Android GC root")?;
+            encoder.put_byte(tag);
+            encoder.put_id(id);
+            Ok(())
+        };
+        match *self {
+            GcRoot::Unknown { id } => {
+                encoder.put_byte(HPROF_GC_ROOT_UNKNOWN);
+                encoder.put_id(id);
+            }
+            GcRoot::JniGlobal { id, global_ref_id } => {
+                encoder.put_byte(HPROF_GC_ROOT_JNI_GLOBAL);
+                encoder.put_id(id);
+                encoder.put_id(global_ref_id);
+            }
+            GcRoot::JniLocal { id, thread_seq, depth } => {
+                encoder.put_byte(HPROF_GC_ROOT_JNI_LOCAL);
+                encoder.put_id(id);
+                encoder.put_int(thread_seq);
+                encoder.put_int(depth);
+            }
+            GcRoot::JavaFrame { id, thread_seq, depth } => {
+                encoder.put_byte(HPROF_GC_ROOT_JAVA_FRAME);
+                encoder.put_id(id);
+                encoder.put_int(thread_seq);
+                encoder.put_int(depth);
+            }
+            GcRoot::NativeStack { id, thread_seq } => {
+                encoder.put_byte(HPROF_GC_ROOT_NATIVE_STACK);
+                encoder.put_id(id);
+                encoder.put_int(thread_seq);
+            }
+            GcRoot::StickyClass { id } => {
+                encoder.put_byte(HPROF_GC_ROOT_STICKY_CLASS);
+                encoder.put_id(id);
+            }
+            GcRoot::ThreadBlock { id, thread_seq } => {
+                encoder.put_byte(HPROF_GC_ROOT_THREAD_BLOCK);
+                encoder.put_id(id);
+                encoder.put_int(thread_seq);
+            }
+            GcRoot::MonitorUsed { id } => {
+                encoder.put_byte(HPROF_GC_ROOT_MONITOR_USED);
+                encoder.put_id(id);
+            }
+            GcRoot::ThreadObj { id, thread_seq, stack_seq } => {
+                encoder.put_byte(HPROF_GC_ROOT_THREAD_OBJ);
+                encoder.put_id(id);
+                encoder.put_int(thread_seq);
+                encoder.put_int(stack_seq);
+            }
+            GcRoot::InternedString { id } => android(encoder, HPROF_GC_ROOT_INTERNED_STRING, id)?,
+            GcRoot::Finalizing { id } => android(encoder, HPROF_GC_ROOT_FINALIZING, id)?,
+            GcRoot::Debugger { id } => android(encoder, HPROF_GC_ROOT_DEBUGGER, id)?,
+            GcRoot::ReferenceCleanup { id } => android(encoder, HPROF_GC_ROOT_REFERENCE_CLEANUP, id)?,
+            GcRoot::VmInternal { id } => android(encoder, HPROF_GC_ROOT_VM_INTERNAL, id)?,
+            GcRoot::Unreachable { id } => android(encoder, HPROF_GC_UNREACHABLE, id)?,
+            GcRoot::JniMonitor { id, thread_seq, depth } => {
+                android(encoder, HPROF_GC_ROOT_JNI_MONITOR, id)?;
+                encoder.put_int(thread_seq);
+                encoder.put_int(depth);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Emit for ClassObject {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        encoder.put_byte(HPROF_GC_CLASS_DUMP);
+        encoder.put_id(*self.id());
+        encoder.put_int(*self.stack_trace_ser());
+        encoder.put_id(*self.super_id());
+        encoder.put_id(*self.class_loader_id());
+        encoder.put_id(*self.signers_id());
+        encoder.put_id(*self.protection_domain_id());
+        // reserved
+        encoder.put_id(0);
+        encoder.put_id(0);
+        encoder.put_int(*self.instance_size());
+        // 解析时未保留常量池
+        encoder.put_short(0);
+        encoder.put_short(self.static_fields().len() as u16);
+        for field in self.static_fields() {
+            encoder.put_id(*field.name_id());
+            encoder.put_byte(field.value().value_type());
+            encoder.put_value(field.value());
+        }
+        encoder.put_short(self.fields().len() as u16);
+        for field in self.fields() {
+            encoder.put_id(*field.name_id());
+            encoder.put_byte(*field.field_type());
+        }
+        Ok(())
+    }
+}
+
+impl Emit for NormalObject {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        emit_instance(self, |id| id, encoder)
+    }
+}
+
+impl Emit for ObjectArray {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        emit_object_array(self, |id| id, encoder)
+    }
+}
+
+impl Emit for PrimitiveArray {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        emit_primitive_array(self, |id| id, encoder)
+    }
+}
+
+/// # A `Dump` row with its compact indexes resolved back to HPROF IDs
+pub struct Resolved<'a, T> {
+    row: &'a T,
+    ids: &'a IdMap,
+}
+
+impl<'a, T> Resolved<'a, T> {
+    pub fn new(row: &'a T, ids: &'a IdMap) -> Self {
+        Resolved { row, ids }
+    }
+}
+
+impl Emit for Resolved<'_, NormalObject<CompactId>> {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        emit_instance(self.row, |index| self.ids.id(index), encoder)
+    }
+}
+
+impl Emit for Resolved<'_, ObjectArray<CompactId>> {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        emit_object_array(self.row, |index| self.ids.id(index), encoder)
+    }
+}
+
+impl Emit for Resolved<'_, PrimitiveArray<CompactId>> {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        emit_primitive_array(self.row, |index| self.ids.id(index), encoder)
+    }
+}
+
+fn emit_instance<I: Copy>(obj: &NormalObject<I>, id: impl Fn(I) -> Long, encoder: &mut Encoder) -> Result<()> {
+    encoder.put_byte(HPROF_GC_INSTANCE_DUMP);
+    encoder.put_id(id(*obj.id()));
+    encoder.put_int(*obj.stack_trace_ser());
+    encoder.put_id(id(*obj.class_id()));
+    encoder.put_int(obj.payload().len() as Int);
+    encoder.put_bytes(obj.payload());
+    Ok(())
+}
+
+fn emit_object_array<I: Copy>(array: &ObjectArray<I>, id: impl Fn(I) -> Long, encoder: &mut Encoder) -> Result<()> {
+    encoder.put_byte(HPROF_GC_OBJ_ARRAY_DUMP);
+    encoder.put_id(id(*array.id()));
+    encoder.put_int(*array.stack_trace_ser());
+    encoder.put_int(array.elements().len() as Int);
+    encoder.put_id(id(*array.class_id()));
+    array.elements().iter().for_each(|element| encoder.put_id(id(*element)));
+    Ok(())
+}
+
+fn emit_primitive_array<I: Copy>(array: &PrimitiveArray<I>, id: impl Fn(I) -> Long, encoder: &mut Encoder) -> Result<()> {
+    let nodata = matches!(array.elements(), ArrayData::NoData);
+    if nodata {
+        encoder.require_android("PRIMITIVE ARRAY NODATA")?;
+        encoder.put_byte(HPROF_GC_PRIM_ARRAY_NODATA_DUMP);
+    } else {
+        encoder.put_byte(HPROF_GC_PRIM_ARRAY_DUMP);
+    }
+    encoder.put_id(id(*array.id()));
+    encoder.put_int(*array.stack_trace_ser());
+    encoder.put_int(*array.len());
+    encoder.put_byte(*array.element_type());
+    match array.elements() {
+        ArrayData::Lazy { .. } => {
+            let Some(source) = encoder.source.as_mut() else {
+                return Err(ParseError::malformed(format!("数组 {} 的元素不在内存中，需要原快照文件", id(*array.id()))));
+            };
+            let data = array.load(source)?;
+            encoder.put_array(&data);
+        }
+        data => encoder.put_array(data),
+    }
+    Ok(())
+}
+
+impl Emit for HeapRecord {
+    fn emit(&self, encoder: &mut Encoder) -> Result<()> {
+        match self {
+            HeapRecord::HeapInfo { heap, name_id } => {
+                encoder.require_android("HEAP DUMP INFO")?;
+                encoder.put_byte(HPROF_HEAP_DUMP_INFO);
+                encoder.put_int(Int::from(*heap));
+                encoder.put_id(*name_id);
+                Ok(())
+            }
+            HeapRecord::Root(root) => root.emit(encoder),
+            HeapRecord::Class(class) => class.emit(encoder),
+            HeapRecord::Instance(obj) => obj.emit(encoder),
+            HeapRecord::ObjectArray(array) => array.emit(encoder),
+            HeapRecord::PrimitiveArray(array) => array.emit(encoder),
+        }
+    }
+}
+
+/// # Builds a small HPROF 1.0.2 file in memory
+///
+/// 用于测试，id 大小无效时 `new` 返回错误；之后各方法的第一个错误保留下来，由 `build` 返回，
+/// 出错后的调用不再写入
+/// # Examples
+/// ```rust
+/// use jsnap::parser::dump::GcRoot;
+/// use jsnap::parser::writer::HprofBuilder;
+///
+/// let data = HprofBuilder::new(4)?
+///     .utf8(1, "Foo")
+///     .load_class(1, 100, 1)
+///     .class_dump(100, 0, &[])
+///     .root(GcRoot::StickyClass { id: 100 })
+///     .instance(200, 100, Vec::new())
+///     .build()?;
+/// assert!(data.starts_with(b"JAVA PROFILE 1.0.2\0"));
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct HprofBuilder {
+    writer: Writer<Vec<u8>>,
+    id_size: Int,
+    /// 第一个写入错误
+    error: Option<ParseError>,
+}
+
+impl HprofBuilder {
+    /// id 大小只能是 4 或 8
+    pub fn new(id_size: Int) -> Result<Self> {
+        let writer = Writer::new(Vec::new(), id_size, 0)?;
+        Ok(HprofBuilder { writer, id_size, error: None })
+    }
+
+    /// 尚未出错时写入，出错时记录错误
+    fn write_with(mut self, write: impl FnOnce(&mut Writer<Vec<u8>>) -> Result<()>) -> Self {
+        if self.error.is_none() {
+            self.error = write(&mut self.writer).err();
+        }
+        self
+    }
+
+    /// UTF8 常量
+    pub fn utf8(self, symbol_id: Long, name: &str) -> Self {
+        self.write_with(|writer| writer.write(HPROF_UTF8, 0, &Utf8::new(symbol_id, name)))
+    }
+
+    /// LOAD CLASS 记录
+    pub fn load_class(self, serial_num: Int, class_id: Long, name_id: Long) -> Self {
+        self.write_with(|writer| writer.write(HPROF_LOAD_CLASS, 0, &Class::new(serial_num, class_id, name_id)))
+    }
+
+    /// START THREAD 记录
+    pub fn start_thread(self, serial_num: Int, thread_id: Long, name_id: Long) -> Self {
+        self.write_with(|writer| writer.write(HPROF_START_THREAD, 0, &Thread::new(serial_num, thread_id, name_id)))
+    }
+
+    /// 任意顶层记录
+    pub fn record<T: Emit>(self, tag: Byte, record: &T) -> Self {
+        self.write_with(|writer| writer.write(tag, 0, record))
+    }
+
+    /// 任意堆转储子记录
+    pub fn heap_record<T: Emit>(self, record: &T) -> Self {
+        self.write_with(|writer| writer.write_heap_record(record))
+    }
+
+    pub fn root(self, root: GcRoot) -> Self {
+        self.heap_record(&root)
+    }
+
+    /// 类对象，`fields` 为实例字段 (name ID, type)，实例大小按字段类型计算
+    pub fn class_dump(self, id: Long, super_id: Long, fields: &[(Long, Byte)]) -> Self {
+        let instance_size = fields.iter().map(|(_, t)| get_type_size(*t, self.id_size)).sum();
+        let fields = fields.iter().map(|(name_id, t)| FieldInfo::new(*name_id, *t)).collect();
+        let class = ClassObject::new(id, super_id, instance_size, Vec::new(), fields);
+        self.heap_record(&class)
+    }
+
+    pub fn instance(self, id: Long, class_id: Long, payload: Vec<Byte>) -> Self {
+        self.heap_record(&NormalObject::new(id, class_id, payload))
+    }
+
+    pub fn object_array(self, id: Long, class_id: Long, elements: Vec<Long>) -> Self {
+        self.heap_record(&ObjectArray::new(id, class_id, elements))
+    }
+
+    /// 基本类型数组，元素必须在内存中
+    pub fn primitive_array(self, id: Long, elements: ArrayData) -> Self {
+        self.write_with(|writer| writer.write_heap_record(&PrimitiveArray::new(id, elements)?))
+    }
+
+    /// 结束当前堆转储，之后的子记录属于下一次堆转储
+    pub fn end_dump(self) -> Self {
+        self.write_with(|writer| writer.end_heap_dump())
+    }
+
+    /// 文件内容，写入出错时返回第一个错误
+    pub fn build(self) -> Result<Vec<u8>> {
+        match self.error {
+            Some(err) => Err(err),
+            None => self.writer.finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::parser::dump::INT_TYPE;
+    use crate::parser::hprof::{read, Hprof, HPROF_FRAME, HPROF_TRACE};
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_back() {
+        let data = HprofBuilder::new(4).unwrap()
+            .utf8(1, "Foo")
+            .utf8(2, "count")
+            .load_class(1, 100, 1)
+            .root(GcRoot::StickyClass { id: 100 })
+            .class_dump(100, 0, &[(2, INT_TYPE)])
+            .instance(200, 100, 42u32.to_be_bytes().to_vec())
+            .object_array(300, 100, vec![200, 0])
+            .primitive_array(400, ArrayData::Char("hi".encode_utf16().collect()))
+            .end_dump()
+            .instance(201, 100, 7u32.to_be_bytes().to_vec())
+            .build().unwrap();
+
+        let path = std::env::temp_dir().join(format!("jsnap-writer-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let hprof = read(&path, false).unwrap();
+        // 解析结果再次写出，子记录已按类型排列，内容不变
+        let mut rewritten = Vec::new();
+        let result = hprof.write(&mut rewritten);
+        let _ = fs::remove_file(&path);
+        result.unwrap();
+        assert_eq!(rewritten, data);
+
+        assert_eq!(hprof.snapshots().len(), 2);
+        let dump = hprof.dump().unwrap();
+        assert_eq!(dump.roots().len(), 1);
+        let fields = hprof.get_instance_fields(&dump.objects()[0]).unwrap();
+        assert_eq!(fields[0].0, "count");
+        assert!(matches!(fields[0].1, Value::Int(42)));
+        let elements: Vec<Long> = dump.object_arrays()[0].elements().iter().map(|index| dump.id(*index)).collect();
+        assert_eq!(elements, [200, 0]);
+        assert_eq!(dump.primitive_arrays()[0].elements().to_utf16_string().unwrap(), "hi");
+    }
+
+    #[test]
+    fn test_write_threads_and_stacks() {
+        let data = HprofBuilder::new(4).unwrap()
+            .utf8(1, "Worker")
+            .utf8(2, "run")
+            .utf8(3, "()V")
+            .utf8(4, "Worker.java")
+            .utf8(5, "worker-1")
+            .utf8(6, "pool")
+            .utf8(7, "main")
+            .record(HPROF_FRAME, &Frame::new(20, 2, 3, 4, 1, 12))
+            .record(HPROF_FRAME, &Frame::new(21, 2, 3, 4, 1, -3i32 as Int))
+            .record(HPROF_TRACE, &Trace::new(9, 1, vec![20, 21]))
+            .load_class(1, 100, 1)
+            .record(HPROF_START_THREAD, &Thread::with_group(1, 300, 9, 5, 6, 7))
+            .root(GcRoot::ThreadObj { id: 300, thread_seq: 1, stack_seq: 9 })
+            .end_dump()
+            .build().unwrap();
+
+        let path = std::env::temp_dir().join(format!("jsnap-writer-threads-{}.hprof", std::process::id()));
+        fs::write(&path, &data).unwrap();
+        let hprof = read(&path, false).unwrap();
+        let mut rewritten = Vec::new();
+        hprof.write(&mut rewritten).unwrap();
+        fs::write(&path, &rewritten).unwrap();
+        let reread = read(&path, false);
+        let _ = fs::remove_file(&path);
+        let reread = reread.unwrap();
+        assert_eq!(rewritten, data);
+
+        let describe = |hprof: &Hprof| -> Vec<String> {
+            hprof.threads().iter()
+                .map(|thread| format!("{} trace={} parent={:?}", thread, thread.trace_serial_num(), thread.parent_group_name()))
+                .collect()
+        };
+        assert_eq!(describe(&reread), describe(&hprof));
+        let thread = &reread.threads()[0];
+        assert_eq!(thread.name().as_deref(), Some("worker-1"));
+        assert_eq!(thread.group_name().as_deref(), Some("pool"));
+        assert_eq!(*thread.trace_serial_num(), 9);
+        let stack: Vec<String> = thread.stack().iter().map(|frame| frame.to_string()).collect();
+        assert_eq!(stack, ["Worker.run(Worker.java:12)", "Worker.run(Native Method)"]);
+    }
+
+    #[test]
+    fn test_builder_returns_first_error() {
+        let result = HprofBuilder::new(4).unwrap()
+            .utf8(1, "Foo")
+            .primitive_array(400, ArrayData::Lazy { offset: 0 })
+            .primitive_array(401, ArrayData::NoData)
+            .build();
+
+        assert!(matches!(result, Err(ParseError::Malformed { message }) if message.contains("400")));
+        assert!(PrimitiveArray::new(401, ArrayData::NoData).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::io::{stdout, IsTerminal};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use indicatif::{HumanBytes, HumanCount, HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use jsnap::parser::progress::{Phase, Progress};
+
+/// 非终端时每完成多少百分比输出一行
+const LOG_PERCENT_STEP: u64 = 10;
+
+/// 每个阶段一个进度条，显示吞吐量及剩余时间
+///
+/// 标准输出不是终端时（例如重定向到文件）不绘制进度条，改为按百分比输出日志行
+pub struct ConsoleProgress {
+    /// 终端时的进度条
+    bars: Option<MultiProgress>,
+    phases: Mutex<HashMap<Phase, PhaseState>>,
+}
+
+struct PhaseState {
+    bar: Option<ProgressBar>,
+    total: u64,
+    done: u64,
+    started: Instant,
+    /// 已输出日志的百分比
+    logged: u64,
+}
+
+impl ConsoleProgress {
+    pub fn new() -> ConsoleProgress {
+        let bars = stdout().is_terminal()
+            .then(|| MultiProgress::with_draw_target(ProgressDrawTarget::stdout()));
+        ConsoleProgress { bars, phases: Mutex::new(HashMap::new()) }
+    }
+
+    /// 清除全部进度条
+    pub fn clear(&self) {
+        if let Some(bars) = &self.bars {
+            let _ = bars.clear();
+        }
+    }
+}
+
+impl Progress for ConsoleProgress {
+    fn start(&self, phase: Phase, total: u64) {
+        let bar = self.bars.as_ref().map(|bars| {
+            let template = if phase.in_bytes() {
+                "{prefix:10} [{elapsed_precise}] {bar:40.cyan/blue} {bytes:>10}/{total_bytes:10} {binary_bytes_per_sec:>12} 剩余 {eta}"
+            } else {
+                "{prefix:10} [{elapsed_precise}] {bar:40.cyan/blue} {human_pos:>10}/{human_len:10} {per_sec:>12} 剩余 {eta}"
+            };
+            let style = ProgressStyle::with_template(template)
+                .unwrap()
+                .progress_chars("##-");
+            let bar = bars.add(ProgressBar::new(total));
+            bar.set_style(style);
+            bar.set_prefix(phase.to_string());
+            bar
+        });
+        if bar.is_none() {
+            println!("{}: 共 {}", phase, format_amount(phase, total));
+        }
+        let state = PhaseState { bar, total, done: 0, started: Instant::now(), logged: 0 };
+        self.phases.lock().unwrap().insert(phase, state);
+    }
+
+    fn advance(&self, phase: Phase, amount: u64) {
+        let mut phases = self.phases.lock().unwrap();
+        let Some(state) = phases.get_mut(&phase) else { return };
+        state.done = (state.done + amount).min(state.total);
+        if let Some(bar) = &state.bar {
+            bar.set_position(state.done);
+            return;
+        }
+        if state.total == 0 {
+            return;
+        }
+        let percent = state.done * 100 / state.total;
+        if percent < state.logged + LOG_PERCENT_STEP {
+            return;
+        }
+        state.logged = percent - percent % LOG_PERCENT_STEP;
+        let elapsed = state.started.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 { state.done as f64 / elapsed } else { 0.0 };
+        let eta = if rate > 0.0 { (state.total - state.done) as f64 / rate } else { 0.0 };
+        println!("{}: {}%，{}/s，剩余 {}", phase, percent, format_amount(phase, rate as u64),
+                 HumanDuration(Duration::from_secs_f64(eta)));
+    }
+
+    fn finish(&self, phase: Phase) {
+        let Some(state) = self.phases.lock().unwrap().remove(&phase) else { return };
+        match state.bar {
+            Some(bar) => bar.finish(),
+            None => println!("{}: 完成，用时 {}", phase, HumanDuration(state.started.elapsed())),
+        }
+    }
+}
+
+/// 按阶段的单位显示字节数或对象个数
+fn format_amount(phase: Phase, value: u64) -> String {
+    if phase.in_bytes() {
+        HumanBytes(value).to_string()
+    } else {
+        format!("{} 个对象", HumanCount(value))
+    }
+}
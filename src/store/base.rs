@@ -1,41 +1,270 @@
-use std::path::PathBuf;
-use rusqlite::{Connection, params, Result};
-use derive_getters::Getters;
+use std::path::Path;
+use rusqlite::{Connection, Params, Row, Statement};
+use crate::io::channel::{Int, Long};
+use crate::parser::dump::Dump;
+use crate::parser::error::Result;
+use crate::parser::hprof::Hprof;
+use crate::parser::progress::{Counter, Phase, Progress};
+use crate::parser::timeline::EventKind;
+use crate::store::class::{Class, ClassDump, Field};
+use crate::store::heap::{Array, Instance, Reference, Root};
 
-pub trait Object {}
+/// 工作目录中的数据库文件
+pub const DB_FILE: &str = "jsnap.db";
+/// 每个事务写入的行数
+const BATCH_SIZE: usize = 10_000;
+/// 对象数超过该值的堆转储只写入类、字段及 GC root，对象及引用由 `ObjectIndex` 查询
+const MAX_DB_OBJECTS: usize = 1_000_000;
+
+/// # a row of a table
+///
+/// 列的顺序在 `CREATE_SQL`、`INSERT_SQL`、`SELECT_SQL` 及 `from_row` 中保持一致
+pub trait Object: Sized {
+    /// 建表及索引
+    const CREATE_SQL: &'static str;
+    /// 插入一行
+    const INSERT_SQL: &'static str;
+    /// 查询全部列，之后可接 WHERE 条件
+    const SELECT_SQL: &'static str;
+
+    /// 以预编译的 `INSERT_SQL` 插入本行
+    fn insert(&self, stmt: &mut Statement) -> rusqlite::Result<()>;
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self>;
+}
 
 pub trait DBStore {
-    fn insert<T: Object>(&self, obj: &T) -> bool;
-    fn update<T: Object>(&self, obj: &T) -> bool;
+    /// 创建 `T` 的表
+    fn create<T: Object>(&self) -> Result<()>;
+    /// 分批插入，每批在一个事务中提交，返回插入的行数
+    fn insert<T: Object>(&mut self, rows: impl IntoIterator<Item = T>) -> Result<u64>;
+    /// 以同一条预编译语句逐行执行更新，分批提交，返回更新的行数
+    fn update<P: Params>(&mut self, sql: &str, rows: impl IntoIterator<Item = P>) -> Result<u64>;
+    /// 以预编译语句查询，`filter` 为接在 `SELECT_SQL` 之后的条件
+    fn query<T: Object, P: Params>(&self, filter: &str, params: P) -> Result<Vec<T>>;
 }
 
-#[derive(Default, Getters)]
-pub struct Class {
-    serial: u64,
-    id: u64,
-    name_id: u64,
-    name: String,
-    status: u8,
+/// # SQLite database in the work directory
+///
+/// 保存类、字段、实例、数组、引用及 GC root，可以不加载快照直接查询
+pub struct Database {
+    conn: Connection,
 }
 
-impl Class {
-    const NAME: &'static str = "class.data";
-    const CREATE_SQL: &'static str = "CREATE TABLE IF NOT EXISTS tb_class (serial BIGINT PRIMARY KEY, id BIGINT, name_id BIGINT, name TEXT, status INT default '1')";
-    const INSERT_SQL: &'static str = "INSERT INTO tb_class (serial, id, name_id, name, status) VALUES (?1, ?2, ?3, ?4, ?5)";
-    const UPDATE_SQL: &'static str = "UPDATE tb_class set status = 0 where serial = ?1";
+impl Database {
+    /// 打开或创建数据库，并创建全部表
+    pub fn open(path: &Path) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        // 数据库可以随时从快照文件重建，不需要每次提交都落盘
+        conn.execute_batch("PRAGMA synchronous = OFF;")?;
+        let db = Database { conn };
+        db.create::<Class>()?;
+        db.create::<ClassDump>()?;
+        db.create::<Field>()?;
+        db.create::<Instance>()?;
+        db.create::<Array>()?;
+        db.create::<Reference>()?;
+        db.create::<Root>()?;
+        Ok(db)
+    }
+
+    /// 写入解析结果
+    ///
+    /// 类加载事件按时间线顺序在一个事务中写入：LOAD CLASS 插入状态为已加载的类，同一 serial number 再次加载时覆盖，
+    /// UNLOAD CLASS 将其标记为已卸载；
+    /// 每次堆转储的内容以序号区分，对象过多的堆转储不写入实例、数组及引用。
+    /// 以类、GC root、实例及数组的行数向 `Phase::Database` 报告进度
+    pub fn import(&mut self, hprof: &Hprof, progress: &dyn Progress) -> Result<()> {
+        let name = |name_id| hprof.symbols().get(&name_id).cloned().unwrap_or_default();
+        // 按时间线顺序逐条插入或更新，同一个类卸载后再次加载时状态为已加载
+        let tx = self.conn.transaction()?;
+        {
+            let mut load = tx.prepare_cached(Class::INSERT_SQL)?;
+            let mut unload = tx.prepare_cached(Class::UPDATE_SQL)?;
+            for event in hprof.timeline().events() {
+                match *event.kind() {
+                    EventKind::ClassLoad { serial_num, class_id, name_id } =>
+                        Class::new(serial_num, class_id, name_id, name(name_id)).insert(&mut load)?,
+                    EventKind::ClassUnload { serial_num } => {
+                        unload.execute([serial_num as i64])?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        tx.commit()?;
+
+        let id_size = *hprof.id_size() as Int;
+        let dumps = hprof.snapshots().iter().map(|snapshot| snapshot.dump()).collect::<Result<Vec<_>>>()?;
+        let total = dumps.iter()
+            .map(|dump| {
+                let objects = dump.object_count() - dump.classes().len();
+                let objects = if objects > MAX_DB_OBJECTS { 0 } else { objects };
+                (dump.classes().len() + dump.roots().len() + objects) as u64
+            })
+            .sum();
+        progress.start(Phase::Database, total);
+        let result = self.import_dumps(&dumps, id_size, name, progress);
+        progress.finish(Phase::Database);
+        result
+    }
+
+    fn import_dumps(&mut self, dumps: &[&Dump], id_size: Int, name: impl Fn(Long) -> String,
+                    progress: &dyn Progress) -> Result<()> {
+        let mut counter = Counter::new(progress, Phase::Database);
+        for (index, dump) in dumps.iter().enumerate() {
+            let index = index as Int;
+            self.insert(dump.classes().iter().map(|class| ClassDump::of(index, class)).inspect(|_| counter.add(1)))?;
+            self.insert(dump.classes().iter().flat_map(|class| Field::of(index, class, &name)))?;
+            self.insert(dump.roots().iter().map(|root| Root::of(index, root)).inspect(|_| counter.add(1)))?;
+            let objects = dump.objects().len() + dump.object_arrays().len() + dump.primitive_arrays().len();
+            if objects > MAX_DB_OBJECTS {
+                continue;
+            }
+            self.insert(dump.objects().iter().map(|obj| Instance::of(index, obj, dump.ids())).inspect(|_| counter.add(1)))?;
+            self.insert(dump.object_arrays().iter().map(|array| Array::of_objects(index, array, dump.ids()))
+                .inspect(|_| counter.add(1)))?;
+            self.insert(dump.primitive_arrays().iter().map(|array| Array::of_primitives(index, array, dump.ids()))
+                .inspect(|_| counter.add(1)))?;
+            self.insert(Reference::of_dump(index, dump, id_size))?;
+        }
+        Ok(())
+    }
 }
 
-impl Object for Class {}
+impl DBStore for Database {
+    fn create<T: Object>(&self) -> Result<()> {
+        self.conn.execute_batch(T::CREATE_SQL)?;
+        Ok(())
+    }
+
+    fn insert<T: Object>(&mut self, rows: impl IntoIterator<Item = T>) -> Result<u64> {
+        let mut rows = rows.into_iter().peekable();
+        let mut count = 0;
+        while rows.peek().is_some() {
+            let tx = self.conn.transaction()?;
+            {
+                let mut stmt = tx.prepare_cached(T::INSERT_SQL)?;
+                for row in rows.by_ref().take(BATCH_SIZE) {
+                    row.insert(&mut stmt)?;
+                    count += 1;
+                }
+            }
+            tx.commit()?;
+        }
+        Ok(count)
+    }
 
-impl DBStore for Class {
-    fn insert<T: Object>(&self, obj: &T) -> bool {
-        todo!()
+    fn update<P: Params>(&mut self, sql: &str, rows: impl IntoIterator<Item = P>) -> Result<u64> {
+        let mut rows = rows.into_iter().peekable();
+        let mut count = 0;
+        while rows.peek().is_some() {
+            let tx = self.conn.transaction()?;
+            {
+                let mut stmt = tx.prepare_cached(sql)?;
+                for params in rows.by_ref().take(BATCH_SIZE) {
+                    count += stmt.execute(params)? as u64;
+                }
+            }
+            tx.commit()?;
+        }
+        Ok(count)
     }
 
-    fn update<T: Object>(&self, obj: &T) -> bool {
-        todo!()
+    fn query<T: Object, P: Params>(&self, filter: &str, params: P) -> Result<Vec<T>> {
+        let mut stmt = self.conn.prepare_cached(&format!("{} {}", T::SELECT_SQL, filter))?;
+        let rows = stmt.query_map(params, T::from_row)?.collect::<rusqlite::Result<Vec<T>>>()?;
+        Ok(rows)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::parser::dump::{GcRoot, OBJECT_TYPE};
+    use crate::parser::hprof::{read, HPROF_UNLOAD_CLASS};
+    use crate::parser::progress::NoProgress;
+    use crate::parser::writer::HprofBuilder;
+    use super::*;
+
+    #[test]
+    fn test_import() {
+        let data = HprofBuilder::new(4).unwrap()
+            .utf8(7, "Node")
+            .utf8(8, "next")
+            .utf8(9, "Gone")
+            .load_class(1, 100, 7)
+            .load_class(2, 101, 9)
+            .record(HPROF_UNLOAD_CLASS, &2u32)
+            .root(GcRoot::StickyClass { id: 100 })
+            .root(GcRoot::JavaFrame { id: 200, thread_seq: 1, depth: 0 })
+            .class_dump(100, 0, &[(8, OBJECT_TYPE)])
+            .instance(200, 100, 201u32.to_be_bytes().to_vec())
+            .instance(201, 100, 0u32.to_be_bytes().to_vec())
+            .object_array(300, 100, vec![200, 0, 201])
+            .end_dump()
+            .build().unwrap();
 
+        let dir = std::env::temp_dir().join(format!("jsnap-db-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("heap.hprof");
+        fs::write(&path, &data).unwrap();
+        let hprof = read(&path, false).unwrap();
+        let mut db = Database::open(&dir.join(DB_FILE)).unwrap();
+        db.import(&hprof, &NoProgress).unwrap();
 
+        let classes: Vec<Class> = db.query("ORDER BY serial", []).unwrap();
+        let status: Vec<_> = classes.iter().map(|class| (class.name().as_str(), *class.status())).collect();
+        assert_eq!(status, [("Node", Class::LOADED), ("Gone", Class::UNLOADED)]);
+
+        let fields: Vec<Field> = db.query("WHERE class_id = ?1", [100]).unwrap();
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].name(), "next");
+        let instances: Vec<Instance> = db.query("WHERE dump = 0 AND class_id = ?1", [100]).unwrap();
+        assert_eq!(instances.len(), 2);
+        let referrers: Vec<Reference> = db.query("WHERE dump = 0 AND to_id = ?1 ORDER BY from_id", [201]).unwrap();
+        let from: Vec<_> = referrers.iter().map(|reference| *reference.from_id()).collect();
+        assert_eq!(from, [200, 300]);
+        let roots: Vec<Root> = db.query("WHERE thread_seq IS NOT NULL", []).unwrap();
+        assert_eq!(roots.len(), 1);
+        assert_eq!(*roots[0].id(), 200);
+
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_import_reloaded_class() {
+        let data = HprofBuilder::new(4).unwrap()
+            .utf8(7, "Reloaded")
+            .utf8(8, "Gone")
+            .load_class(1, 100, 7)
+            .record(HPROF_UNLOAD_CLASS, &1u32)
+            .load_class(1, 101, 7)
+            .load_class(2, 102, 8)
+            .record(HPROF_UNLOAD_CLASS, &2u32)
+            .class_dump(101, 0, &[])
+            .instance(200, 101, Vec::new())
+            .instance(200, 101, Vec::new())
+            .end_dump()
+            .build().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("jsnap-db-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("heap.hprof");
+        fs::write(&path, &data).unwrap();
+        let hprof = read(&path, false).unwrap();
+        let mut db = Database::open(&dir.join(DB_FILE)).unwrap();
+        let result = db.import(&hprof, &NoProgress);
+        let classes: Vec<Class> = db.query("ORDER BY serial", []).unwrap();
+        let instances: Vec<Instance> = db.query("WHERE dump = 0", []).unwrap();
+        drop(db);
+        let _ = fs::remove_dir_all(&dir);
+
+        // 重复的对象只写入一次
+        result.unwrap();
+        assert_eq!(instances.len(), 1);
+        let status: Vec<_> = classes.iter().map(|class| (*class.id(), *class.status())).collect();
+        assert_eq!(status, [(101, Class::LOADED), (102, Class::UNLOADED)]);
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fmt::Formatter;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::result;
+use std::str::FromStr;
+use std::sync::Mutex;
+use crate::parser::error::{ParseError, Result};
+use crate::store::index::{remove_file, IndexStore, SourceMeta};
+
+/// 已完成的阶段及分段，开头为源文件信息
+const MANIFEST_FILE: &str = "manifest";
+/// 尚未合并的中间结果
+const CHECKPOINT_DIR: &str = "checkpoint";
+/// 第一遍扫描得到的全局记录
+const RECORDS_FILE: &str = "records";
+/// 堆转储分段的位置及扫描状态
+const SEGMENTS_FILE: &str = "segments";
+
+/// # A step of building the index
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// 顺序扫描全部记录
+    Records,
+    /// 收集各次堆转储的对象 ID
+    Ids,
+    /// 解析全部堆转储分段
+    HeapDump,
+    /// 第 n 次堆转储的子记录及对象索引
+    Dump(usize),
+    /// 全局索引
+    Index,
+    /// SQLite 数据库
+    Database,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Records => write!(f, "records"),
+            Stage::Ids => write!(f, "ids"),
+            Stage::HeapDump => write!(f, "heap-dump"),
+            Stage::Dump(index) => write!(f, "dump-{}", index),
+            Stage::Index => write!(f, "index"),
+            Stage::Database => write!(f, "database"),
+        }
+    }
+}
+
+impl FromStr for Stage {
+    type Err = ();
+
+    fn from_str(name: &str) -> result::Result<Self, Self::Err> {
+        match name {
+            "records" => Ok(Stage::Records),
+            "ids" => Ok(Stage::Ids),
+            "heap-dump" => Ok(Stage::HeapDump),
+            "index" => Ok(Stage::Index),
+            "database" => Ok(Stage::Database),
+            name => name.strip_prefix("dump-").and_then(|index| index.parse().ok()).map(Stage::Dump).ok_or(()),
+        }
+    }
+}
+
+/// # Progress of building the index of one snapshot file
+///
+/// 清单 `{work}/manifest` 依次追加完成的阶段及分段，中途中断后重新运行时跳过已完成的部分：
+/// ```text
+/// version=6            源文件信息，与当前文件不一致时丢弃全部检查点
+/// size=...
+/// mtime=...
+/// header=...
+/// done=records         已完成的阶段
+/// done=ids             各次堆转储的对象 ID 映射表已保存为 checkpoint/ids-{n}
+/// segment=1234         已完成的堆转储分段，中间结果为 checkpoint/segment-1234
+/// ```
+pub struct Checkpoint {
+    work_path: PathBuf,
+    stages: Mutex<HashSet<Stage>>,
+    /// 已完成分段在文件中的偏移
+    segments: Mutex<HashSet<u64>>,
+    manifest: Mutex<File>,
+}
+
+impl Checkpoint {
+    /// 打开工作目录中的检查点，源文件变化或中间结果已删除时清除旧的检查点及索引重新开始
+    pub fn open(work_path: &Path, file_path: &Path) -> Result<Self> {
+        let meta = SourceMeta::of(file_path).map_err(|err| ParseError::store(err, file_path))?;
+        let manifest_path = work_path.join(MANIFEST_FILE);
+        let text = fs::read_to_string(&manifest_path).unwrap_or_default();
+        let mut stages = HashSet::new();
+        let mut segments = HashSet::new();
+        let resumable = work_path.join(CHECKPOINT_DIR).is_dir();
+        if resumable && SourceMeta::parse(&text).is_some_and(|saved| saved == meta) {
+            for (key, value) in text.lines().filter_map(|line| line.split_once('=')) {
+                match key {
+                    "done" => stages.extend(value.parse::<Stage>().ok()),
+                    "segment" => segments.extend(value.parse::<u64>().ok()),
+                    _ => {}
+                }
+            }
+        } else {
+            Checkpoint::clear(work_path)?;
+            IndexStore::new(work_path).clear()?;
+            fs::create_dir_all(work_path).map_err(|err| ParseError::store(err, work_path))?;
+            fs::write(&manifest_path, meta.to_text()).map_err(|err| ParseError::store(err, &manifest_path))?;
+        }
+        let dir = work_path.join(CHECKPOINT_DIR);
+        fs::create_dir_all(&dir).map_err(|err| ParseError::store(err, &dir))?;
+        let manifest = OpenOptions::new().append(true).open(&manifest_path)
+            .map_err(|err| ParseError::store(err, &manifest_path))?;
+        Ok(Checkpoint {
+            work_path: work_path.to_path_buf(),
+            stages: Mutex::new(stages),
+            segments: Mutex::new(segments),
+            manifest: Mutex::new(manifest),
+        })
+    }
+
+    pub fn is_done(&self, stage: Stage) -> bool {
+        self.stages.lock().unwrap().contains(&stage)
+    }
+
+    /// 记录阶段完成，阶段的结果应已全部写入
+    pub fn done(&self, stage: Stage) -> Result<()> {
+        self.append(&format!("done={}\n", stage))?;
+        self.stages.lock().unwrap().insert(stage);
+        Ok(())
+    }
+
+    /// 第一遍扫描得到的全局记录
+    pub fn records_path(&self) -> PathBuf {
+        self.work_path.join(CHECKPOINT_DIR).join(RECORDS_FILE)
+    }
+
+    /// 堆转储分段的位置
+    pub fn segments_path(&self) -> PathBuf {
+        self.work_path.join(CHECKPOINT_DIR).join(SEGMENTS_FILE)
+    }
+
+    /// 第 `dump` 次堆转储的对象 ID 映射表，见 `IdMap::write`
+    pub fn ids_path(&self, dump: usize) -> PathBuf {
+        self.work_path.join(CHECKPOINT_DIR).join(format!("ids-{}", dump))
+    }
+
+    /// 位于 `offset` 的分段解析后的子记录
+    pub fn segment_path(&self, offset: u64) -> PathBuf {
+        self.work_path.join(CHECKPOINT_DIR).join(format!("segment-{}", offset))
+    }
+
+    pub fn is_segment_done(&self, offset: u64) -> bool {
+        self.segments.lock().unwrap().contains(&offset)
+    }
+
+    /// 记录分段完成，其子记录应已写入 `segment_path`
+    pub fn segment_done(&self, offset: u64) -> Result<()> {
+        self.append(&format!("segment={}\n", offset))?;
+        self.segments.lock().unwrap().insert(offset);
+        Ok(())
+    }
+
+    /// 索引完成后删除中间结果，清单保留
+    pub fn finish(&self) -> Result<()> {
+        let dir = self.work_path.join(CHECKPOINT_DIR);
+        if dir.is_dir() {
+            fs::remove_dir_all(&dir).map_err(|err| ParseError::store(err, &dir))?;
+        }
+        Ok(())
+    }
+
+    /// 删除清单及中间结果
+    pub fn clear(work_path: &Path) -> Result<()> {
+        remove_file(&work_path.join(MANIFEST_FILE))?;
+        let dir = work_path.join(CHECKPOINT_DIR);
+        if dir.is_dir() {
+            fs::remove_dir_all(&dir).map_err(|err| ParseError::store(err, &dir))?;
+        }
+        Ok(())
+    }
+
+    /// 追加一行并立即落盘，中断后清单中只有已完成的部分
+    fn append(&self, line: &str) -> Result<()> {
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.write_all(line.as_bytes())
+            .and_then(|_| manifest.sync_data())
+            .map_err(|err| ParseError::store(err, &self.work_path.join(MANIFEST_FILE)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reopen_and_invalidate() {
+        let dir = std::env::temp_dir().join(format!("jsnap-checkpoint-{}", std::process::id()));
+        let work_path = dir.join("work");
+        fs::create_dir_all(&work_path).unwrap();
+        let path = dir.join("heap.hprof");
+        fs::write(&path, b"JAVA PROFILE 1.0.2\0").unwrap();
+
+        let checkpoint = Checkpoint::open(&work_path, &path).unwrap();
+        assert!(!checkpoint.is_done(Stage::Records));
+        checkpoint.done(Stage::Records).unwrap();
+        checkpoint.done(Stage::Dump(1)).unwrap();
+        checkpoint.segment_done(31).unwrap();
+        drop(checkpoint);
+
+        let reopened = Checkpoint::open(&work_path, &path).unwrap();
+        let resumed = (reopened.is_done(Stage::Records), reopened.is_done(Stage::Dump(1)),
+                       reopened.is_done(Stage::Dump(0)), reopened.is_segment_done(31));
+        drop(reopened);
+
+        // 源文件变化后重新开始
+        fs::write(&path, b"JAVA PROFILE 1.0.2\0\0").unwrap();
+        let changed = Checkpoint::open(&work_path, &path).unwrap();
+        let restarted = (changed.is_done(Stage::Records), changed.is_segment_done(31));
+        drop(changed);
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(resumed, (true, true, false, true));
+        assert_eq!(restarted, (false, false));
+    }
+}
@@ -1,25 +1,181 @@
-use std::path::PathBuf;
 use derive_getters::Getters;
-use rusqlite::Connection;
-use crate::store::base::{DBStore, Object};
-
-const NAME: &'static str = "class.data";
-const CREATE_SQL: &'static str = "CREATE TABLE IF NOT EXISTS tb_class (serial BIGINT PRIMARY KEY, id BIGINT, name_id BIGINT, name TEXT, status INT default '1')";
-const INSERT_SQL: &'static str = "INSERT INTO tb_class (serial, id, name_id, name, status) VALUES (?1, ?2, ?3, ?4, ?5)";
-const UPDATE_SQL: &'static str = "UPDATE tb_class set status = 0 where serial = ?1";
-
-// #[derive(Default, Getters)]
-// pub struct Class {
-//     serial: u64,
-//     id: u64,
-//     name_id: u64,
-//     name: String,
-//     status: u8,
-// }
-//
-// impl Class {
-//
-//
-//
-// }
+use rusqlite::{params, Row, Statement};
+use crate::io::channel::{Byte, Int, Long};
+use crate::parser::dump::{ClassObject, Value};
+use crate::store::base::Object;
 
+/// # a class loaded by LOAD CLASS
+///
+/// UNLOAD CLASS 不删除记录，只将 `status` 标记为已卸载
+#[derive(Clone, Debug, Default, Getters)]
+pub struct Class {
+    serial: Int,
+    id: Long,
+    name_id: Long,
+    name: String,
+    status: u8,
+}
+
+impl Class {
+    pub const LOADED: u8 = 1;
+    pub const UNLOADED: u8 = 0;
+    /// 按 serial number 标记为已卸载
+    pub const UPDATE_SQL: &'static str = "UPDATE tb_class SET status = 0 WHERE serial = ?1";
+
+    pub fn new(serial: Int, id: Long, name_id: Long, name: String) -> Self {
+        Class { serial, id, name_id, name, status: Class::LOADED }
+    }
+}
+
+impl Object for Class {
+    const CREATE_SQL: &'static str = "CREATE TABLE IF NOT EXISTS tb_class (serial BIGINT PRIMARY KEY, id BIGINT, name_id BIGINT, name TEXT, status INT DEFAULT 1);
+        CREATE INDEX IF NOT EXISTS idx_class_name ON tb_class (name);";
+    const INSERT_SQL: &'static str = "INSERT OR REPLACE INTO tb_class (serial, id, name_id, name, status) VALUES (?1, ?2, ?3, ?4, ?5)";
+    const SELECT_SQL: &'static str = "SELECT serial, id, name_id, name, status FROM tb_class";
+
+    fn insert(&self, stmt: &mut Statement) -> rusqlite::Result<()> {
+        stmt.execute(params![self.serial, self.id as i64, self.name_id as i64, self.name, self.status])?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Class {
+            serial: row.get(0)?,
+            id: row.get::<_, i64>(1)? as Long,
+            name_id: row.get::<_, i64>(2)? as Long,
+            name: row.get(3)?,
+            status: row.get(4)?,
+        })
+    }
+}
+
+/// # a class object of a heap dump
+#[derive(Clone, Debug, Getters)]
+pub struct ClassDump {
+    /// 堆转储序号
+    dump: Int,
+    id: Long,
+    super_id: Long,
+    class_loader_id: Long,
+    instance_size: Int,
+    heap: Int,
+}
+
+impl ClassDump {
+    pub fn of(dump: Int, class: &ClassObject) -> Self {
+        ClassDump {
+            dump,
+            id: *class.id(),
+            super_id: *class.super_id(),
+            class_loader_id: *class.class_loader_id(),
+            instance_size: *class.instance_size(),
+            heap: class.heap().into(),
+        }
+    }
+}
+
+impl Object for ClassDump {
+    const CREATE_SQL: &'static str = "CREATE TABLE IF NOT EXISTS tb_class_dump (dump INT, id BIGINT, super_id BIGINT, class_loader_id BIGINT, instance_size INT, heap INT, PRIMARY KEY (dump, id))";
+    const INSERT_SQL: &'static str = "INSERT OR IGNORE INTO tb_class_dump (dump, id, super_id, class_loader_id, instance_size, heap) VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
+    const SELECT_SQL: &'static str = "SELECT dump, id, super_id, class_loader_id, instance_size, heap FROM tb_class_dump";
+
+    fn insert(&self, stmt: &mut Statement) -> rusqlite::Result<()> {
+        stmt.execute(params![self.dump, self.id as i64, self.super_id as i64, self.class_loader_id as i64,
+            self.instance_size, self.heap])?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(ClassDump {
+            dump: row.get(0)?,
+            id: row.get::<_, i64>(1)? as Long,
+            super_id: row.get::<_, i64>(2)? as Long,
+            class_loader_id: row.get::<_, i64>(3)? as Long,
+            instance_size: row.get(4)?,
+            heap: row.get(5)?,
+        })
+    }
+}
+
+/// # a static or instance field declared by a class
+#[derive(Clone, Debug, Getters)]
+pub struct Field {
+    dump: Int,
+    class_id: Long,
+    /// 声明顺序，静态字段与实例字段分别计数
+    idx: Int,
+    name_id: Long,
+    name: String,
+    field_type: Byte,
+    is_static: bool,
+    /// 静态字段的值，浮点数为其位模式；实例字段为 `None`
+    value: Option<i64>,
+}
+
+impl Field {
+    /// 类声明的全部字段，静态字段在前
+    pub fn of(dump: Int, class: &ClassObject, name: impl Fn(Long) -> String) -> Vec<Self> {
+        let statics = class.static_fields().iter().enumerate().map(|(idx, field)| Field {
+            dump,
+            class_id: *class.id(),
+            idx: idx as Int,
+            name_id: *field.name_id(),
+            name: name(*field.name_id()),
+            field_type: field.value().value_type(),
+            is_static: true,
+            value: Some(value_bits(field.value())),
+        });
+        let fields = class.fields().iter().enumerate().map(|(idx, field)| Field {
+            dump,
+            class_id: *class.id(),
+            idx: idx as Int,
+            name_id: *field.name_id(),
+            name: name(*field.name_id()),
+            field_type: *field.field_type(),
+            is_static: false,
+            value: None,
+        });
+        statics.chain(fields).collect()
+    }
+}
+
+/// 字段值以整数保存
+fn value_bits(value: &Value) -> i64 {
+    match *value {
+        Value::Id(id) => id as i64,
+        Value::Bool(value) => value as i64,
+        Value::Char(value) => value as i64,
+        Value::Float(bits) => bits as i64,
+        Value::Double(bits) => bits as i64,
+        Value::Byte(value) => value as i64,
+        Value::Short(value) => value as i64,
+        Value::Int(value) => value as i64,
+        Value::Long(value) => value as i64,
+    }
+}
+
+impl Object for Field {
+    const CREATE_SQL: &'static str = "CREATE TABLE IF NOT EXISTS tb_field (dump INT, class_id BIGINT, idx INT, name_id BIGINT, name TEXT, type INT, is_static INT, value BIGINT);
+        CREATE INDEX IF NOT EXISTS idx_field_class ON tb_field (dump, class_id);";
+    const INSERT_SQL: &'static str = "INSERT INTO tb_field (dump, class_id, idx, name_id, name, type, is_static, value) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)";
+    const SELECT_SQL: &'static str = "SELECT dump, class_id, idx, name_id, name, type, is_static, value FROM tb_field";
+
+    fn insert(&self, stmt: &mut Statement) -> rusqlite::Result<()> {
+        stmt.execute(params![self.dump, self.class_id as i64, self.idx, self.name_id as i64, self.name,
+            self.field_type, self.is_static, self.value])?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Field {
+            dump: row.get(0)?,
+            class_id: row.get::<_, i64>(1)? as Long,
+            idx: row.get(2)?,
+            name_id: row.get::<_, i64>(3)? as Long,
+            name: row.get(4)?,
+            field_type: row.get(5)?,
+            is_static: row.get(6)?,
+            value: row.get(7)?,
+        })
+    }
+}
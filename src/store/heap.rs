@@ -0,0 +1,193 @@
+use derive_getters::Getters;
+use rusqlite::{params, Row, Statement};
+use crate::io::channel::{Byte, Int, Long};
+use crate::parser::dump::{Dump, GcRoot, NormalObject, ObjectArray, PrimitiveArray, OBJECT_TYPE};
+use crate::store::base::Object;
+use crate::store::ids::{CompactId, IdMap};
+
+/// # an instance of a heap dump
+#[derive(Clone, Debug, Getters)]
+pub struct Instance {
+    dump: Int,
+    id: Long,
+    class_id: Long,
+    /// 字段数据的字节数
+    size: Int,
+    heap: Int,
+}
+
+impl Instance {
+    /// 紧凑下标经 `ids` 换回 HPROF ID
+    pub fn of(dump: Int, obj: &NormalObject<CompactId>, ids: &IdMap) -> Self {
+        Instance {
+            dump,
+            id: ids.id(*obj.id()),
+            class_id: ids.id(*obj.class_id()),
+            size: obj.payload().len() as Int,
+            heap: obj.heap().into(),
+        }
+    }
+}
+
+impl Object for Instance {
+    const CREATE_SQL: &'static str = "CREATE TABLE IF NOT EXISTS tb_instance (dump INT, id BIGINT, class_id BIGINT, size INT, heap INT, PRIMARY KEY (dump, id));
+        CREATE INDEX IF NOT EXISTS idx_instance_class ON tb_instance (dump, class_id);";
+    const INSERT_SQL: &'static str = "INSERT OR IGNORE INTO tb_instance (dump, id, class_id, size, heap) VALUES (?1, ?2, ?3, ?4, ?5)";
+    const SELECT_SQL: &'static str = "SELECT dump, id, class_id, size, heap FROM tb_instance";
+
+    fn insert(&self, stmt: &mut Statement) -> rusqlite::Result<()> {
+        stmt.execute(params![self.dump, self.id as i64, self.class_id as i64, self.size, self.heap])?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Instance {
+            dump: row.get(0)?,
+            id: row.get::<_, i64>(1)? as Long,
+            class_id: row.get::<_, i64>(2)? as Long,
+            size: row.get(3)?,
+            heap: row.get(4)?,
+        })
+    }
+}
+
+/// # an object or primitive array of a heap dump
+#[derive(Clone, Debug, Getters)]
+pub struct Array {
+    dump: Int,
+    id: Long,
+    /// 对象数组的类，基本类型数组为 0
+    class_id: Long,
+    element_type: Byte,
+    length: Int,
+    heap: Int,
+}
+
+impl Array {
+    /// 紧凑下标经 `ids` 换回 HPROF ID
+    pub fn of_objects(dump: Int, array: &ObjectArray<CompactId>, ids: &IdMap) -> Self {
+        Array {
+            dump,
+            id: ids.id(*array.id()),
+            class_id: ids.id(*array.class_id()),
+            element_type: OBJECT_TYPE,
+            length: *array.len(),
+            heap: array.heap().into(),
+        }
+    }
+
+    pub fn of_primitives(dump: Int, array: &PrimitiveArray<CompactId>, ids: &IdMap) -> Self {
+        Array {
+            dump,
+            id: ids.id(*array.id()),
+            class_id: 0,
+            element_type: *array.element_type(),
+            length: *array.len(),
+            heap: array.heap().into(),
+        }
+    }
+}
+
+impl Object for Array {
+    const CREATE_SQL: &'static str = "CREATE TABLE IF NOT EXISTS tb_array (dump INT, id BIGINT, class_id BIGINT, element_type INT, length INT, heap INT, PRIMARY KEY (dump, id))";
+    const INSERT_SQL: &'static str = "INSERT OR IGNORE INTO tb_array (dump, id, class_id, element_type, length, heap) VALUES (?1, ?2, ?3, ?4, ?5, ?6)";
+    const SELECT_SQL: &'static str = "SELECT dump, id, class_id, element_type, length, heap FROM tb_array";
+
+    fn insert(&self, stmt: &mut Statement) -> rusqlite::Result<()> {
+        stmt.execute(params![self.dump, self.id as i64, self.class_id as i64, self.element_type, self.length, self.heap])?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Array {
+            dump: row.get(0)?,
+            id: row.get::<_, i64>(1)? as Long,
+            class_id: row.get::<_, i64>(2)? as Long,
+            element_type: row.get(3)?,
+            length: row.get(4)?,
+            heap: row.get(5)?,
+        })
+    }
+}
+
+/// # a non-null reference between two objects
+#[derive(Clone, Debug, Getters)]
+pub struct Reference {
+    dump: Int,
+    from_id: Long,
+    to_id: Long,
+    /// 字段名 ID，对象数组元素为 0
+    name_id: Long,
+}
+
+impl Reference {
+    /// 堆转储中的全部引用，以 HPROF ID 表示
+    pub fn of_dump(dump: Int, heap: &Dump, id_size: Int) -> impl Iterator<Item = Reference> + '_ {
+        heap.references(id_size).map(move |(from, to, name_id)| Reference {
+            dump,
+            from_id: heap.id(from),
+            to_id: heap.id(to),
+            name_id,
+        })
+    }
+}
+
+impl Object for Reference {
+    const CREATE_SQL: &'static str = "CREATE TABLE IF NOT EXISTS tb_reference (dump INT, from_id BIGINT, to_id BIGINT, name_id BIGINT);
+        CREATE INDEX IF NOT EXISTS idx_reference_from ON tb_reference (dump, from_id);
+        CREATE INDEX IF NOT EXISTS idx_reference_to ON tb_reference (dump, to_id);";
+    const INSERT_SQL: &'static str = "INSERT INTO tb_reference (dump, from_id, to_id, name_id) VALUES (?1, ?2, ?3, ?4)";
+    const SELECT_SQL: &'static str = "SELECT dump, from_id, to_id, name_id FROM tb_reference";
+
+    fn insert(&self, stmt: &mut Statement) -> rusqlite::Result<()> {
+        stmt.execute(params![self.dump, self.from_id as i64, self.to_id as i64, self.name_id as i64])?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Reference {
+            dump: row.get(0)?,
+            from_id: row.get::<_, i64>(1)? as Long,
+            to_id: row.get::<_, i64>(2)? as Long,
+            name_id: row.get::<_, i64>(3)? as Long,
+        })
+    }
+}
+
+/// # a GC root of a heap dump
+#[derive(Clone, Debug, Getters)]
+pub struct Root {
+    dump: Int,
+    id: Long,
+    /// 子记录类型
+    tag: Byte,
+    /// 所在线程，与线程无关的根为 `None`
+    thread_seq: Option<Int>,
+}
+
+impl Root {
+    pub fn of(dump: Int, root: &GcRoot) -> Self {
+        Root { dump, id: root.id(), tag: root.tag(), thread_seq: root.thread_seq() }
+    }
+}
+
+impl Object for Root {
+    const CREATE_SQL: &'static str = "CREATE TABLE IF NOT EXISTS tb_gc_root (dump INT, id BIGINT, tag INT, thread_seq INT);
+        CREATE INDEX IF NOT EXISTS idx_gc_root_id ON tb_gc_root (dump, id);";
+    const INSERT_SQL: &'static str = "INSERT INTO tb_gc_root (dump, id, tag, thread_seq) VALUES (?1, ?2, ?3, ?4)";
+    const SELECT_SQL: &'static str = "SELECT dump, id, tag, thread_seq FROM tb_gc_root";
+
+    fn insert(&self, stmt: &mut Statement) -> rusqlite::Result<()> {
+        stmt.execute(params![self.dump, self.id as i64, self.tag, self.thread_seq])?;
+        Ok(())
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Root {
+            dump: row.get(0)?,
+            id: row.get::<_, i64>(1)? as Long,
+            tag: row.get(2)?,
+            thread_seq: row.get(3)?,
+        })
+    }
+}
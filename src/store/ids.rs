@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Mutex;
+use crate::io::channel::Long;
+use crate::parser::error::{ParseError, Result};
+use crate::store::objects::Column;
+
+/// 对象在堆转储中的紧凑下标
+pub type CompactId = u32;
+/// 表示没有对应对象：HPROF ID 0 (null)，或类对象本身的类
+pub const NO_ID: CompactId = CompactId::MAX;
+
+/// # Dense remapping from HPROF object IDs to compact indexes
+///
+/// 64 位 JVM 的对象 ID 占 8 字节，`Dump` 的对象表、对象数组元素、引用列表及对象索引都只保存 4 字节的 `CompactId`，
+/// 需要时再换回 HPROF ID。堆转储中的对象按 ID 升序编号为 `0..n`，查找为二分查找，
+/// 这部分在解析分段之前由各分段中的对象 ID 生成，见 `segment::read_segment_ids`，
+/// 保存在检查点及对象索引的 `object.ids` 中。
+///
+/// 被引用却不在堆转储中的 ID，例如类定义缺失的类，解析时按出现顺序编号在 `n` 之后，只在内存中保留
+pub struct IdMap {
+    /// 堆转储中的对象 ID，升序
+    ids: Ids,
+    /// 之后追加的 ID，解析分段的各线程共用
+    extra: Mutex<Extra>,
+}
+
+enum Ids {
+    Memory(Vec<Long>),
+    Mapped(Column),
+}
+
+#[derive(Default)]
+struct Extra {
+    index: HashMap<Long, CompactId>,
+    ids: Vec<Long>,
+}
+
+impl IdMap {
+    /// 由堆转储中的对象 ID 生成，`ids` 不必有序，重复的 ID 只保留一个
+    pub fn new(mut ids: Vec<Long>) -> Result<Self> {
+        ids.sort_unstable();
+        ids.dedup();
+        check_len(ids.len())?;
+        Ok(IdMap { ids: Ids::Memory(ids), extra: Mutex::default() })
+    }
+
+    /// 保存堆转储中的对象 ID，之后追加的 ID 不保存
+    pub fn save(&self, path: &Path) -> Result<()> {
+        match &self.ids {
+            Ids::Memory(ids) => Column::write(path, ids),
+            Ids::Mapped(ids) => Column::write(path, &(0..ids.len()).map(|index| ids.get(index)).collect::<Vec<_>>()),
+        }
+    }
+
+    /// 保存之后追加的 ID，按追加顺序排列
+    pub fn save_extra(&self, path: &Path) -> Result<()> {
+        Column::write(path, &self.extra.lock().unwrap().ids)
+    }
+
+    /// 依次追加 `save_extra` 保存的 ID，追加的 ID 与保存时的紧凑下标相同
+    pub fn load_extra(&self, path: &Path) -> Result<()> {
+        let extra = Column::open(path)?;
+        for index in 0..extra.len() {
+            self.intern(extra.get(index));
+        }
+        Ok(())
+    }
+
+    /// 打开 `save` 保存的映射表，只做内存映射
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(IdMap { ids: Ids::Mapped(Column::open(path)?), extra: Mutex::default() })
+    }
+
+    /// 堆转储中的对象个数，不含之后追加的 ID
+    pub fn len(&self) -> usize {
+        match &self.ids {
+            Ids::Memory(ids) => ids.len(),
+            Ids::Mapped(ids) => ids.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 已分配的紧凑下标个数，包括之后追加的 ID
+    pub fn assigned(&self) -> usize {
+        self.len() + self.extra.lock().unwrap().ids.len()
+    }
+
+    /// HPROF ID 对应的紧凑下标，未分配时为 `None`
+    pub fn compact(&self, id: Long) -> Option<CompactId> {
+        let found = match &self.ids {
+            Ids::Memory(ids) => ids.binary_search(&id).ok(),
+            Ids::Mapped(ids) => ids.search(id),
+        };
+        match found {
+            Some(index) => Some(index as CompactId),
+            None => self.extra.lock().unwrap().index.get(&id).copied(),
+        }
+    }
+
+    /// HPROF ID 对应的紧凑下标，不在堆转储中的 ID 在此分配，0 为 `NO_ID`
+    ///
+    /// 紧凑下标用尽时同样返回 `NO_ID`，此时对象数已超过 `IdMap::new` 的上限
+    pub fn intern(&self, id: Long) -> CompactId {
+        if id == 0 {
+            return NO_ID;
+        }
+        if let Some(index) = self.compact(id) {
+            return index;
+        }
+        let mut extra = self.extra.lock().unwrap();
+        let next = self.len() + extra.ids.len();
+        if next >= NO_ID as usize {
+            return NO_ID;
+        }
+        let index = *extra.index.entry(id).or_insert(next as CompactId);
+        if index as usize == next {
+            extra.ids.push(id);
+        }
+        index
+    }
+
+    /// 紧凑下标对应的 HPROF ID，`NO_ID` 为 0
+    pub fn id(&self, index: CompactId) -> Long {
+        if index == NO_ID {
+            return 0;
+        }
+        let index = index as usize;
+        match &self.ids {
+            Ids::Memory(ids) if index < ids.len() => ids[index],
+            Ids::Mapped(ids) if index < ids.len() => ids.get(index),
+            _ => self.extra.lock().unwrap().ids[index - self.len()],
+        }
+    }
+}
+
+impl Default for IdMap {
+    fn default() -> Self {
+        IdMap { ids: Ids::Memory(Vec::new()), extra: Mutex::default() }
+    }
+}
+
+impl fmt::Debug for IdMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdMap")
+            .field("len", &self.len())
+            .field("assigned", &self.assigned())
+            .finish()
+    }
+}
+
+fn check_len(len: usize) -> Result<()> {
+    if len >= NO_ID as usize {
+        return Err(ParseError::malformed(format!("对象数 {} 超过紧凑下标的上限 {}", len, NO_ID)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    fn test_id_map() {
+        let path = std::env::temp_dir().join(format!("jsnap-ids-{}", std::process::id()));
+        let ids = vec![0x7f00_0000_2000, 0x7f00_0000_1000, 0x7f00_0000_1010];
+        IdMap::new(ids).unwrap().save(&path).unwrap();
+        let map = IdMap::open(&path).unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.compact(0x7f00_0000_1010), Some(1));
+        assert_eq!(map.compact(0x7f00_0000_1008), None);
+        assert_eq!(map.id(2), 0x7f00_0000_2000);
+
+        // 不在映射表中的 ID 编号在对象之后
+        assert_eq!(map.intern(0x7f00_0000_1008), 3);
+        assert_eq!(map.intern(0x7f00_0000_1008), 3);
+        assert_eq!(map.intern(0x7f00_0000_2000), 2);
+        assert_eq!(map.id(3), 0x7f00_0000_1008);
+        assert_eq!((map.len(), map.assigned()), (3, 4));
+        assert_eq!(map.intern(0), NO_ID);
+        assert_eq!(map.id(NO_ID), 0);
+
+        let extra = path.with_extension("extra");
+        map.save_extra(&extra).unwrap();
+        let reopened = IdMap::new(vec![0x7f00_0000_1000, 0x7f00_0000_1010, 0x7f00_0000_2000]).unwrap();
+        reopened.load_extra(&extra).unwrap();
+        let _ = fs::remove_file(&extra);
+        assert_eq!(reopened.compact(0x7f00_0000_1008), Some(3));
+
+        let map = IdMap::new(vec![30, 10, 20, 10]).unwrap();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.compact(20), Some(1));
+    }
+}
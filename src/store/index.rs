@@ -1,4 +1,449 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use crate::io::channel::{Int, Long};
+use crate::parser::dump::{read_heap_record, read_heap_record_at, ArrayData, Dump, Heap, HeapRecord};
+use crate::parser::error::{ParseError, Result};
+use crate::parser::progress::{Counter, Phase, Progress};
+use crate::parser::reader::Reader;
+use crate::parser::writer::{Emit, Encoder, Resolved};
+use crate::store::base::DB_FILE;
+use crate::store::ids::{CompactId, IdMap};
 
-struct IndexStore {
+/// 索引格式版本，格式变化后旧索引不再使用
+pub const INDEX_VERSION: Int = 6;
+/// 索引文件开头的标识
+pub const INDEX_MAGIC: &str = "JSNAP INDEX";
+/// 参与计算文件头哈希的字节数
+const HEADER_HASH_BYTES: u64 = 64 * 1024;
 
-}
\ No newline at end of file
+/// 编码缓冲区写出的阈值
+const FLUSH_BYTES: usize = 1 << 20;
+
+/// 源文件信息
+const SOURCE_FILE: &str = "source";
+/// 全局索引：UTF8 常量、类、线程、调用栈及事件；在 dump-{n}/ 中为各部分的列表
+const INDEX_FILE: &str = "index";
+/// 堆转储索引的一个部分
+const PART_FILE: &str = "part";
+/// gzip 压缩文件的恢复点
+const SEEK_INDEX_FILE: &str = "seek-index";
+
+/// # identity of the snapshot file an index was built from
+///
+/// 大小、修改时间或文件开头内容任一不同，即认为索引已过期
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceMeta {
+    version: Int,
+    size: u64,
+    /// 修改时间，距 UNIX 纪元的纳秒数
+    mtime: u128,
+    /// 文件开头 64 KiB 的 FNV-1a 哈希
+    header_hash: u64,
+}
+
+impl SourceMeta {
+    pub fn of(file_path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(file_path)?;
+        let mtime = metadata.modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos());
+        let mut header = Vec::new();
+        File::open(file_path)?.take(HEADER_HASH_BYTES).read_to_end(&mut header)?;
+        Ok(SourceMeta { version: INDEX_VERSION, size: metadata.len(), mtime, header_hash: fnv1a(&header) })
+    }
+
+    pub(crate) fn parse(text: &str) -> Option<Self> {
+        let fields: HashMap<&str, &str> = text.lines().filter_map(|line| line.split_once('=')).collect();
+        Some(SourceMeta {
+            version: fields.get("version")?.parse().ok()?,
+            size: fields.get("size")?.parse().ok()?,
+            mtime: fields.get("mtime")?.parse().ok()?,
+            header_hash: u64::from_str_radix(fields.get("header")?, 16).ok()?,
+        })
+    }
+
+    pub(crate) fn to_text(&self) -> String {
+        format!("version={}\nsize={}\nmtime={}\nheader={:016x}\n", self.version, self.size, self.mtime, self.header_hash)
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, b| (hash ^ *b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// # Index files of one snapshot in its work directory
+///
+/// ```text
+/// {work}/source        源文件信息，最后写入，存在即表示索引完整
+/// {work}/seek-index    源文件为 gzip 压缩时随机访问所用的恢复点，其中记录了对应的源文件信息
+/// {work}/index         全局索引
+/// {work}/dump-{n}/index  第 n 次堆转储索引的各部分，见 `write_parts`
+/// {work}/dump-{n}/part-{k}  第 n 次堆转储的子记录，按 `write_dump` 的格式分为一个或多个部分
+/// {work}/dump-{n}/object.*, refs.*  第 n 次堆转储的对象索引，见 `ObjectIndex`
+/// {work}/jsnap.db      SQLite 数据库
+/// {work}/manifest      已完成的阶段，见 `Checkpoint`
+/// {work}/checkpoint/   尚未完成时的中间结果
+/// ```
+pub struct IndexStore {
+    work_path: PathBuf,
+}
+
+impl IndexStore {
+    pub fn new(work_path: &Path) -> Self {
+        IndexStore { work_path: work_path.to_path_buf() }
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.work_path.join(INDEX_FILE)
+    }
+
+    pub fn seek_index_path(&self) -> PathBuf {
+        self.work_path.join(SEEK_INDEX_FILE)
+    }
+
+    /// 索引完整且与 `file_path` 当前的内容一致
+    pub fn is_fresh(&self, file_path: &Path) -> bool {
+        let Ok(text) = fs::read_to_string(self.work_path.join(SOURCE_FILE)) else { return false };
+        let Ok(current) = SourceMeta::of(file_path) else { return false };
+        SourceMeta::parse(&text).is_some_and(|meta| meta == current) && self.index_path().is_file()
+    }
+
+    /// 全部索引写入后记录源文件信息
+    pub fn commit(&self, file_path: &Path) -> Result<()> {
+        let meta = SourceMeta::of(file_path).map_err(|err| ParseError::store(err, file_path))?;
+        let path = self.work_path.join(SOURCE_FILE);
+        fs::write(&path, meta.to_text()).map_err(|err| ParseError::store(err, &path))
+    }
+
+    /// 删除源文件信息，索引重新写入完成前不再使用
+    pub fn invalidate(&self) -> Result<()> {
+        remove_file(&self.work_path.join(SOURCE_FILE))
+    }
+
+    /// 删除索引，源文件信息最先删除，中途失败时索引不会被误用
+    ///
+    /// 恢复点只与源文件有关，源文件不变时继续使用
+    pub fn clear(&self) -> Result<()> {
+        remove_file(&self.work_path.join(SOURCE_FILE))?;
+        remove_file(&self.index_path())?;
+        remove_file(&self.work_path.join(DB_FILE))?;
+        if !self.work_path.is_dir() {
+            return Ok(());
+        }
+        let store_err = |err| ParseError::store(err, &self.work_path);
+        for entry in fs::read_dir(&self.work_path).map_err(store_err)? {
+            let path = entry.map_err(store_err)?.path();
+            let is_dump = path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("dump-"));
+            if is_dump && path.is_dir() {
+                fs::remove_dir_all(&path).map_err(|err| ParseError::store(err, &path))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 删除文件，文件不存在时不报错
+pub(crate) fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(ParseError::store(err, path)),
+        _ => Ok(()),
+    }
+}
+
+/// # An index file being written, buffered
+///
+/// 写入失败时的错误带有文件路径
+pub struct IndexFile {
+    path: PathBuf,
+    out: BufWriter<File>,
+}
+
+impl IndexFile {
+    fn write_all(&mut self, bytes: &[u8]) -> Result<()> {
+        self.out.write_all(bytes).map_err(|err| ParseError::store(err, &self.path))
+    }
+
+    /// 写出缓冲的全部内容
+    pub fn finish(mut self) -> Result<()> {
+        self.out.flush().map_err(|err| ParseError::store(err, &self.path))
+    }
+}
+
+/// 创建索引文件，写入标识及版本
+pub fn create(path: &Path, id_size: Int) -> Result<(IndexFile, Encoder)> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|err| ParseError::store(err, parent))?;
+    }
+    let file = File::create(path).map_err(|err| ParseError::store(err, path))?;
+    let mut encoder = Encoder::new(id_size, true);
+    encoder.put_bytes(INDEX_MAGIC.as_bytes());
+    encoder.put_byte(0);
+    encoder.put_int(INDEX_VERSION);
+    encoder.put_int(id_size);
+    Ok((IndexFile { path: path.to_path_buf(), out: BufWriter::new(file) }, encoder))
+}
+
+/// 写出编码缓冲区中的内容，返回写出的字节数
+pub fn flush(out: &mut IndexFile, encoder: &mut Encoder) -> Result<u64> {
+    out.write_all(encoder.as_bytes())?;
+    let len = encoder.as_bytes().len() as u64;
+    encoder.clear();
+    Ok(len)
+}
+
+/// 缓冲区达到 `FLUSH_BYTES` 后写出，返回写出的字节数
+fn flush_full(out: &mut IndexFile, encoder: &mut Encoder) -> Result<u64> {
+    if encoder.as_bytes().len() >= FLUSH_BYTES {
+        return flush(out, encoder);
+    }
+    Ok(0)
+}
+
+/// 打开索引文件，校验标识及版本
+pub fn open(path: &Path) -> Result<Reader> {
+    let mut reader = Reader::new(path).map_err(|err| match err {
+        ParseError::Io { source, .. } => ParseError::store(source, path),
+        err => err,
+    })?;
+    let magic = reader.read_cstr(INDEX_MAGIC.len() + 1)?;
+    let version = reader.read_int()?;
+    if magic != INDEX_MAGIC || version != INDEX_VERSION {
+        return Err(ParseError::malformed(format!("不是可用的索引文件: {}", path.display())));
+    }
+    let id_size = reader.read_int()?;
+    reader.set_id_size(id_size);
+    Ok(reader)
+}
+
+/// 保存一次堆转储的全部子记录
+///
+/// 子记录按 HPROF 格式编码；未读入内存的大数组以 PRIMITIVE ARRAY NODATA 占位，
+/// 其在源文件中的偏移列在末尾，加载后仍从源文件按需读取。
+/// 尚未指定堆的对象（检查点中分段开头的对象）最先写出，其余对象所属的堆变化时写出 HEAP DUMP INFO，
+/// 第一个指定了堆的对象前总有一条，末尾再记录最后指定的堆，加载后与 `Dump::merge` 合并各分段的结果一致。
+/// 对象以 HPROF ID 写出，与堆转储的 `IdMap` 无关。
+/// 返回对象的紧凑下标 -> 其子记录在索引文件中的偏移，可用 `read_heap_record_at` 直接读取。
+/// 每写入一条子记录向 `Phase::Index` 报告一个对象
+pub fn write_dump(path: &Path, dump: &Dump, id_size: Int, progress: &dyn Progress) -> Result<HashMap<CompactId, u64>> {
+    let (mut out, mut encoder) = create(path, id_size)?;
+    let mut counter = Counter::new(progress, Phase::Index);
+    let count = dump.roots().len() + dump.object_count();
+    encoder.put_long(count as Long);
+    let mut offsets = HashMap::with_capacity(count - dump.roots().len());
+    let mut written = 0;
+
+    let mut heap = None;
+    let mut switch_heap = |encoder: &mut Encoder, next: Option<Heap>| -> Result<()> {
+        match next {
+            Some(next) if heap != Some(next) => {
+                heap = Some(next);
+                HeapRecord::HeapInfo { heap: next, name_id: 0 }.emit(encoder)
+            }
+            _ => Ok(()),
+        }
+    };
+    for root in dump.roots() {
+        root.emit(&mut encoder)?;
+        written += flush_full(&mut out, &mut encoder)?;
+        counter.add(1);
+    }
+    let mut lazy = Vec::new();
+    for tagged in [false, true] {
+        for class in dump.classes().iter().filter(|class| class.heap_tag().is_some() == tagged) {
+            switch_heap(&mut encoder, class.heap_tag())?;
+            offsets.insert(dump.ids().intern(*class.id()), written + encoder.as_bytes().len() as u64);
+            class.emit(&mut encoder)?;
+            written += flush_full(&mut out, &mut encoder)?;
+            counter.add(1);
+        }
+        for obj in dump.objects().iter().filter(|obj| obj.heap_tag().is_some() == tagged) {
+            switch_heap(&mut encoder, obj.heap_tag())?;
+            offsets.insert(*obj.id(), written + encoder.as_bytes().len() as u64);
+            Resolved::new(obj, dump.ids()).emit(&mut encoder)?;
+            written += flush_full(&mut out, &mut encoder)?;
+            counter.add(1);
+        }
+        for array in dump.object_arrays().iter().filter(|array| array.heap_tag().is_some() == tagged) {
+            switch_heap(&mut encoder, array.heap_tag())?;
+            offsets.insert(*array.id(), written + encoder.as_bytes().len() as u64);
+            Resolved::new(array, dump.ids()).emit(&mut encoder)?;
+            written += flush_full(&mut out, &mut encoder)?;
+            counter.add(1);
+        }
+        for array in dump.primitive_arrays().iter().filter(|array| array.heap_tag().is_some() == tagged) {
+            switch_heap(&mut encoder, array.heap_tag())?;
+            offsets.insert(*array.id(), written + encoder.as_bytes().len() as u64);
+            match array.elements() {
+                ArrayData::Lazy { offset } => {
+                    lazy.push((dump.id(*array.id()), *offset));
+                    Resolved::new(&array.without_elements(), dump.ids()).emit(&mut encoder)?;
+                }
+                _ => Resolved::new(array, dump.ids()).emit(&mut encoder)?,
+            }
+            written += flush_full(&mut out, &mut encoder)?;
+            counter.add(1);
+        }
+    }
+
+    encoder.put_int(lazy.len() as Int);
+    for (id, offset) in lazy {
+        encoder.put_id(id);
+        encoder.put_long(offset);
+    }
+    // 最后指定的堆，之后的分段开头的对象属于该堆，分段中可能只有 HEAP DUMP INFO
+    match dump.heap_tag() {
+        None => encoder.put_byte(0),
+        Some(heap) => {
+            encoder.put_byte(1);
+            encoder.put_int(Int::from(heap));
+        }
+    }
+    flush(&mut out, &mut encoder)?;
+    out.finish()?;
+    Ok(offsets)
+}
+
+/// 一次堆转储索引的第 `k` 个部分，`dir` 为 `dump-{n}/`
+pub fn part_path(dir: &Path, k: usize) -> PathBuf {
+    dir.join(format!("{}-{}", PART_FILE, k))
+}
+
+/// 将一次堆转储保存为只有一个部分的索引，返回对象的紧凑下标 -> 子记录的偏移
+pub fn write_dump_index(dir: &Path, dump: &Dump, id_size: Int, progress: &dyn Progress) -> Result<HashMap<CompactId, u64>> {
+    let offsets = write_dump(&part_path(dir, 0), dump, id_size, progress)?;
+    write_parts(dir, id_size, 1)?;
+    Ok(offsets)
+}
+
+/// 以检查点中已保存的分段作为堆转储索引的各部分，`segments` 按在源文件中的顺序排列
+///
+/// 分段文件与 `write_dump` 的格式相同，硬链接到 `dir` 中而不重新写入，文件系统不支持硬链接时才复制。
+/// 只读取各部分以得到子记录的偏移，返回对象在 `ids` 中的紧凑下标 -> 各部分依次相连后的偏移
+pub fn link_dump_index(dir: &Path, segments: &[PathBuf], ids: &IdMap, id_size: Int,
+                       progress: &dyn Progress) -> Result<HashMap<CompactId, u64>> {
+    fs::create_dir_all(dir).map_err(|err| ParseError::store(err, dir))?;
+    let mut offsets = HashMap::new();
+    let mut base = 0;
+    for (k, segment) in segments.iter().enumerate() {
+        let part = part_path(dir, k);
+        remove_file(&part)?;
+        fs::hard_link(segment, &part)
+            .or_else(|_| fs::copy(segment, &part).map(|_| ()))
+            .map_err(|err| ParseError::store(err, &part))?;
+        base += read_offsets(&part, base, ids, &mut offsets, progress)?;
+    }
+    write_parts(dir, id_size, segments.len())?;
+    Ok(offsets)
+}
+
+/// 记录 `dir` 中的前 `count` 个部分及其长度，各部分应已写入
+///
+/// ```text
+/// 索引文件头 | u32 部分数 | 各部分的 u64 长度 ...
+/// ```
+fn write_parts(dir: &Path, id_size: Int, count: usize) -> Result<()> {
+    let (mut out, mut encoder) = create(&dir.join(INDEX_FILE), id_size)?;
+    encoder.put_int(count as Int);
+    for k in 0..count {
+        let part = part_path(dir, k);
+        let len = fs::metadata(&part).map_err(|err| ParseError::store(err, &part))?.len();
+        encoder.put_long(len);
+    }
+    flush(&mut out, &mut encoder)?;
+    out.finish()
+}
+
+/// 各部分的路径及长度
+fn read_parts(dir: &Path) -> Result<Vec<(PathBuf, u64)>> {
+    let mut reader = open(&dir.join(INDEX_FILE))?;
+    let count = reader.read_int()? as usize;
+    let mut parts = Vec::with_capacity(count.min(1024));
+    for k in 0..count {
+        parts.push((part_path(dir, k), reader.read_long()?));
+    }
+    Ok(parts)
+}
+
+/// 读取一个部分中各对象子记录的偏移，加上 `base` 后存入 `offsets`，返回该部分的长度
+fn read_offsets(path: &Path, base: u64, ids: &IdMap, offsets: &mut HashMap<CompactId, u64>,
+                progress: &dyn Progress) -> Result<u64> {
+    let mut reader = open(path)?;
+    let mut counter = Counter::new(progress, Phase::Index);
+    let count = reader.read_long()?;
+    let mut read = 0;
+    while read < count {
+        let position = reader.position()?;
+        let id = match read_heap_record(&mut reader)? {
+            // HEAP DUMP INFO 不计入子记录数
+            HeapRecord::HeapInfo { .. } => continue,
+            HeapRecord::Root(_) => None,
+            HeapRecord::Class(class) => Some(*class.id()),
+            HeapRecord::Instance(obj) => Some(*obj.id()),
+            HeapRecord::ObjectArray(array) => Some(*array.id()),
+            HeapRecord::PrimitiveArray(array) => Some(*array.id()),
+        };
+        if let Some(id) = id {
+            offsets.insert(ids.intern(id), base + position);
+        }
+        read += 1;
+        counter.add(1);
+    }
+    Ok(reader.size())
+}
+
+/// 加载 `write_dump_index` 或 `link_dump_index` 保存的堆转储，各部分依次合并，对象以 `ids` 中的紧凑下标表示
+pub fn read_dump_index(dir: &Path, ids: &Arc<IdMap>) -> Result<Dump> {
+    let mut dump = Dump::new(ids.clone());
+    for (part, _) in read_parts(dir)? {
+        dump.merge(read_dump(&part, ids)?);
+    }
+    Ok(dump)
+}
+
+/// 读取堆转储索引中偏移为 `offset` 的子记录，偏移按各部分依次相连后计算
+pub fn read_record_at(dir: &Path, offset: u64) -> Result<HeapRecord> {
+    let mut base = 0;
+    for (part, len) in read_parts(dir)? {
+        if offset < base + len {
+            return read_heap_record_at(&mut open(&part)?, offset - base);
+        }
+        base += len;
+    }
+    Err(ParseError::malformed(format!("偏移 {} 超出堆转储索引 {} 的范围", offset, dir.display())))
+}
+
+/// 加载 `write_dump` 保存的堆转储，对象以 `ids` 中的紧凑下标表示
+pub fn read_dump(path: &Path, ids: &Arc<IdMap>) -> Result<Dump> {
+    let mut reader = open(path)?;
+    let count = reader.read_long()?;
+    let mut dump = Dump::new(ids.clone());
+    for _ in 0..count {
+        let record = read_heap_record(&mut reader)?;
+        // HEAP DUMP INFO 不计入子记录数
+        let is_info = matches!(record, HeapRecord::HeapInfo { .. });
+        dump.push(record);
+        if is_info {
+            let record = read_heap_record(&mut reader)?;
+            dump.push(record);
+        }
+    }
+    let lazy_count = reader.read_int()?;
+    let mut lazy = HashMap::with_capacity(lazy_count as usize);
+    for _ in 0..lazy_count {
+        let id = reader.get_id()?;
+        lazy.insert(ids.intern(id), reader.read_long()?);
+    }
+    dump.restore_lazy(&lazy);
+    if reader.read_byte()? != 0 {
+        dump.push(HeapRecord::HeapInfo { heap: Heap::from(reader.read_int()?), name_id: 0 });
+    }
+    Ok(dump)
+}
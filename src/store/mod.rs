@@ -1,3 +1,7 @@
-
-mod base;
-mod class;
\ No newline at end of file
+pub mod base;
+pub mod checkpoint;
+pub mod class;
+pub mod heap;
+pub mod ids;
+pub(crate) mod index;
+pub mod objects;
@@ -0,0 +1,517 @@
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use memmap2::{Mmap, MmapMut};
+use crate::io::channel::{Byte, Int, Long};
+use crate::parser::dump::{Dump, Heap, HeapRecord, OBJECT_TYPE};
+use crate::parser::error::{ParseError, Result};
+use crate::store::ids::{CompactId, IdMap, NO_ID};
+use crate::store::index;
+
+/// 列文件开头的标识
+const COLUMN_MAGIC: &[u8; 8] = b"JSNAPCOL";
+/// 列文件格式版本
+const COLUMN_VERSION: u32 = 1;
+/// 标识、版本、元素宽度、元素个数
+const HEADER_LEN: usize = 24;
+
+/// 按对象 ID 升序排列的对象 ID，即 `IdMap`
+const IDS_FILE: &str = "object.ids";
+/// 堆转储之外、在 `IdMap` 中追加的 ID，按追加顺序排列
+const EXTRA_IDS_FILE: &str = "object.extra";
+/// 对象子记录在堆转储索引文件中的偏移
+const OFFSETS_FILE: &str = "object.offsets";
+/// 对象的类对象的紧凑下标
+const CLASSES_FILE: &str = "object.classes";
+/// 对象的浅大小
+const SIZES_FILE: &str = "object.sizes";
+/// 对象的种类，见 `ObjectKind`
+const KINDS_FILE: &str = "object.kinds";
+/// 对象所在的堆
+const HEAPS_FILE: &str = "object.heaps";
+/// 引用出边
+const OUTBOUND: &str = "refs.out";
+/// 引用入边
+const INBOUND: &str = "refs.in";
+
+/// # A fixed-width column file, memory-mapped
+///
+/// ```text
+/// "JSNAPCOL" | u32 版本 | u32 元素宽度 | u64 元素个数 | 元素 ...
+/// ```
+/// 元素为小端序的 1、4 或 8 字节无符号整数，按最大的值选用最窄的宽度
+#[derive(Debug)]
+pub struct Column {
+    map: Mmap,
+    width: usize,
+    len: usize,
+}
+
+impl Column {
+    pub fn write<T: Copy + Into<u64>>(path: &Path, values: &[T]) -> Result<()> {
+        let io_err = |err| ParseError::store(err, path);
+        let max = values.iter().map(|value| (*value).into()).max().unwrap_or(0);
+        let width = match max {
+            max if max <= u8::MAX as u64 => 1,
+            max if max <= u32::MAX as u64 => 4,
+            _ => 8,
+        };
+        let mut out = BufWriter::new(File::create(path).map_err(io_err)?);
+        out.write_all(COLUMN_MAGIC).map_err(io_err)?;
+        out.write_all(&COLUMN_VERSION.to_le_bytes()).map_err(io_err)?;
+        out.write_all(&(width as u32).to_le_bytes()).map_err(io_err)?;
+        out.write_all(&(values.len() as u64).to_le_bytes()).map_err(io_err)?;
+        for value in values {
+            out.write_all(&(*value).into().to_le_bytes()[..width]).map_err(io_err)?;
+        }
+        out.flush().map_err(io_err)
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|err| ParseError::store(err, path))?;
+        // Safety: 列文件只由本程序写入，映射期间不会被修改
+        let map = unsafe { Mmap::map(&file) }.map_err(|err| ParseError::store(err, path))?;
+        let malformed = || ParseError::malformed(format!("不是可用的列文件: {}", path.display()));
+        if map.len() < HEADER_LEN || &map[..8] != COLUMN_MAGIC {
+            return Err(malformed());
+        }
+        let version = u32::from_le_bytes(map[8..12].try_into().unwrap());
+        let width = u32::from_le_bytes(map[12..16].try_into().unwrap()) as usize;
+        let len = usize::try_from(u64::from_le_bytes(map[16..24].try_into().unwrap())).map_err(|_| malformed())?;
+        if version != COLUMN_VERSION || ![1, 4, 8].contains(&width) {
+            return Err(malformed());
+        }
+        // 元素个数来自文件内容，文件被截断或损坏时不能据此访问映射之外的位置
+        let expected = len.checked_mul(width).and_then(|bytes| bytes.checked_add(HEADER_LEN));
+        if expected != Some(map.len()) {
+            return Err(ParseError::malformed(format!("列文件 {} 的长度 {} 与元素个数 {} 不符",
+                                                     path.display(), map.len(), len)));
+        }
+        Ok(Column { map, width, len })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 第 `index` 个元素
+    pub fn get(&self, index: usize) -> u64 {
+        let start = HEADER_LEN + index * self.width;
+        let bytes = &self.map[start..start + self.width];
+        match self.width {
+            1 => bytes[0] as u64,
+            4 => u32::from_le_bytes(bytes.try_into().unwrap()) as u64,
+            _ => u64::from_le_bytes(bytes.try_into().unwrap()),
+        }
+    }
+
+    /// 在升序排列的列中查找 `value` 的下标
+    pub fn search(&self, value: u64) -> Option<usize> {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match self.get(mid) {
+                found if found == value => return Some(mid),
+                found if found < value => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        None
+    }
+}
+
+/// # A 4-byte column of known length, filled in any order
+///
+/// 文件预先分配并内存映射，元素直接写入映射，不在内存中保留副本；格式与 `Column::write` 写出的 4 字节列相同
+struct ColumnWriter {
+    path: PathBuf,
+    map: MmapMut,
+}
+
+impl ColumnWriter {
+    fn create(path: &Path, len: usize) -> Result<Self> {
+        let store_err = |err| ParseError::store(err, path);
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true)
+            .open(path).map_err(store_err)?;
+        file.set_len((HEADER_LEN + len * 4) as u64).map_err(store_err)?;
+        // Safety: 文件刚由本程序创建，映射期间不会被其他进程修改
+        let mut map = unsafe { MmapMut::map_mut(&file) }.map_err(store_err)?;
+        map[..8].copy_from_slice(COLUMN_MAGIC);
+        map[8..12].copy_from_slice(&COLUMN_VERSION.to_le_bytes());
+        map[12..16].copy_from_slice(&4u32.to_le_bytes());
+        map[16..24].copy_from_slice(&(len as u64).to_le_bytes());
+        Ok(ColumnWriter { path: path.to_path_buf(), map })
+    }
+
+    fn set(&mut self, index: usize, value: u32) {
+        let start = HEADER_LEN + index * 4;
+        self.map[start..start + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn finish(self) -> Result<()> {
+        self.map.flush().map_err(|err| ParseError::store(err, &self.path))
+    }
+}
+
+/// # A reference graph in compressed sparse row form
+///
+/// 对象 `i` 的邻接对象为 `edges[index[i]..index[i + 1]]`，对象均以 `CompactId` 表示
+#[derive(Debug)]
+pub struct Graph {
+    index: Column,
+    edges: Column,
+}
+
+impl Graph {
+    /// 由 (from, to) 下标对生成，`count` 为对象个数
+    ///
+    /// `edges` 每次调用重新产生全部的边，共遍历两遍：先统计各对象的出度，再将边直接写入映射的文件，
+    /// 内存中只保留每个对象一个偏移
+    fn write<I: Iterator<Item = (CompactId, CompactId)>>(dir: &Path, name: &str, count: usize,
+                                                        edges: impl Fn() -> I) -> Result<()> {
+        let mut index = vec![0u64; count + 1];
+        for (from, _) in edges() {
+            index[from as usize + 1] += 1;
+        }
+        for i in 0..count {
+            index[i + 1] += index[i];
+        }
+        // 填写时 index[from] 作为下一条边的位置，填完后即为原来的 index[from + 1]
+        let mut targets = ColumnWriter::create(&dir.join(format!("{}.edges", name)), index[count] as usize)?;
+        for (from, to) in edges() {
+            targets.set(index[from as usize] as usize, to);
+            index[from as usize] += 1;
+        }
+        targets.finish()?;
+        index.copy_within(0..count, 1);
+        index[0] = 0;
+        Column::write(&dir.join(format!("{}.index", name)), &index)
+    }
+
+    fn open(dir: &Path, name: &str) -> Result<Self> {
+        let index = Column::open(&dir.join(format!("{}.index", name)))?;
+        let edges = Column::open(&dir.join(format!("{}.edges", name)))?;
+        Ok(Graph { index, edges })
+    }
+
+    /// 对象 `index` 的邻接对象
+    pub fn neighbors(&self, index: CompactId) -> impl Iterator<Item = CompactId> + '_ {
+        let start = self.index.get(index as usize) as usize;
+        let end = self.index.get(index as usize + 1) as usize;
+        (start..end).map(|edge| self.edges.get(edge) as CompactId)
+    }
+
+    /// 对象 `index` 的邻接对象个数
+    pub fn degree(&self, index: CompactId) -> usize {
+        (self.index.get(index as usize + 1) - self.index.get(index as usize)) as usize
+    }
+}
+
+/// # Kind of an object in the object index
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ObjectKind {
+    Class,
+    Instance,
+    ObjectArray,
+    /// 基本类型数组，带元素类型
+    PrimitiveArray(Byte),
+}
+
+impl ObjectKind {
+    /// 在 `object.kinds` 中的编码，基本类型数组为其元素类型，与其余种类不重叠
+    fn code(self) -> Byte {
+        match self {
+            ObjectKind::Class => 0,
+            ObjectKind::Instance => 1,
+            ObjectKind::ObjectArray => OBJECT_TYPE,
+            ObjectKind::PrimitiveArray(element_type) => element_type,
+        }
+    }
+
+    fn of(code: Byte) -> Self {
+        match code {
+            0 => ObjectKind::Class,
+            1 => ObjectKind::Instance,
+            OBJECT_TYPE => ObjectKind::ObjectArray,
+            element_type => ObjectKind::PrimitiveArray(element_type),
+        }
+    }
+}
+
+/// # Memory-mapped object index of one heap dump
+///
+/// 对象及引用按列保存在 `{work}/dump-{n}/` 中，打开时只做内存映射，适用于 SQLite 难以容纳的大堆。
+/// 对象以 `CompactId` 表示，即其在各列中的位置，与解析得到的 `Dump` 中的紧凑下标一致：
+/// ```text
+/// object.ids         对象 ID，升序，即 `IdMap`
+/// object.extra       `IdMap` 中追加在对象之后的 ID，例如类定义缺失的类
+/// object.offsets     子记录的偏移，按 dump-{n}/part-* 依次相连后计算
+/// object.classes     类对象的 `CompactId`，类对象本身及基本类型数组为 `NO_ID`
+/// object.sizes       浅大小，与类直方图的统计方式一致，类对象为 0
+/// object.kinds       对象的种类，见 `ObjectKind`
+/// object.heaps       对象所在的堆，Android 快照之外都是默认堆
+/// refs.out.*         引用出边
+/// refs.in.*          引用入边
+/// ```
+#[derive(Debug)]
+pub struct ObjectIndex {
+    dir: PathBuf,
+    ids: Arc<IdMap>,
+    offsets: Column,
+    classes: Column,
+    sizes: Column,
+    kinds: Column,
+    heaps: Column,
+    outbound: Graph,
+    inbound: Graph,
+}
+
+impl ObjectIndex {
+    /// 生成索引，`offsets` 为保存堆转储索引时返回的子记录偏移
+    ///
+    /// 堆转储的 `IdMap` 由其全部对象的 ID 生成，对象的紧凑下标即其在各列中的位置；
+    /// 之后追加的下标是堆转储之外的对象，不占位置，只保存其 ID，类定义缺失的类仍可按 ID 命名
+    pub fn write(dir: &Path, dump: &Dump, offsets: &HashMap<CompactId, u64>, id_size: Int) -> Result<()> {
+        let ids = dump.ids();
+        let len = ids.len();
+        let index_of = |index: CompactId, id: Long| -> Result<usize> {
+            match index as usize {
+                index if index < len => Ok(index),
+                _ => Err(ParseError::malformed(format!("对象 0x{:x} 不在堆转储的 IdMap 中", id))),
+            }
+        };
+        let mut classes = vec![NO_ID; len];
+        let mut sizes = vec![0u64; len];
+        let mut kinds = vec![ObjectKind::Class.code(); len];
+        let mut heaps = vec![Int::from(Heap::Default); len];
+        // 类对象本身没有类，浅大小为 0
+        for class in dump.classes() {
+            let index = index_of(ids.compact(*class.id()).unwrap_or(NO_ID), *class.id())?;
+            heaps[index] = Int::from(class.heap());
+        }
+        for obj in dump.objects() {
+            let index = index_of(*obj.id(), dump.id(*obj.id()))?;
+            classes[index] = *obj.class_id();
+            sizes[index] = obj.payload().len() as u64;
+            kinds[index] = ObjectKind::Instance.code();
+            heaps[index] = Int::from(obj.heap());
+        }
+        for array in dump.object_arrays() {
+            let index = index_of(*array.id(), dump.id(*array.id()))?;
+            classes[index] = *array.class_id();
+            sizes[index] = *array.len() as u64 * id_size as u64;
+            kinds[index] = ObjectKind::ObjectArray.code();
+            heaps[index] = Int::from(array.heap());
+        }
+        for array in dump.primitive_arrays() {
+            let index = index_of(*array.id(), dump.id(*array.id()))?;
+            sizes[index] = array.byte_size();
+            kinds[index] = ObjectKind::PrimitiveArray(*array.element_type()).code();
+            heaps[index] = Int::from(array.heap());
+        }
+        let object_offsets = (0..len as CompactId)
+            .map(|index| offsets.get(&index).copied().ok_or_else(|| {
+                ParseError::malformed(format!("对象 0x{:x} 没有子记录偏移", ids.id(index)))
+            }))
+            .collect::<Result<Vec<u64>>>()?;
+        ids.save(&dir.join(IDS_FILE))?;
+        Column::write(&dir.join(OFFSETS_FILE), &object_offsets)?;
+        Column::write(&dir.join(CLASSES_FILE), &classes)?;
+        Column::write(&dir.join(SIZES_FILE), &sizes)?;
+        Column::write(&dir.join(KINDS_FILE), &kinds)?;
+        Column::write(&dir.join(HEAPS_FILE), &heaps)?;
+
+        // 指向堆转储之外的引用不计入；引用不整体收集，每遍从堆转储中重新遍历
+        let edges = || dump.references(id_size)
+            .filter(|(from, to, _)| (*from as usize) < len && (*to as usize) < len)
+            .map(|(from, to, _)| (from, to));
+        Graph::write(dir, OUTBOUND, len, edges)?;
+        Graph::write(dir, INBOUND, len, || edges().map(|(from, to)| (to, from)))?;
+        // 遍历引用时也会追加 ID，最后保存
+        ids.save_extra(&dir.join(EXTRA_IDS_FILE))
+    }
+
+    pub fn open(dir: &Path) -> Result<Self> {
+        let ids = IdMap::open(&dir.join(IDS_FILE))?;
+        ids.load_extra(&dir.join(EXTRA_IDS_FILE))?;
+        Ok(ObjectIndex {
+            dir: dir.to_path_buf(),
+            ids: Arc::new(ids),
+            offsets: Column::open(&dir.join(OFFSETS_FILE))?,
+            classes: Column::open(&dir.join(CLASSES_FILE))?,
+            sizes: Column::open(&dir.join(SIZES_FILE))?,
+            kinds: Column::open(&dir.join(KINDS_FILE))?,
+            heaps: Column::open(&dir.join(HEAPS_FILE))?,
+            outbound: Graph::open(dir, OUTBOUND)?,
+            inbound: Graph::open(dir, INBOUND)?,
+        })
+    }
+
+    /// 对象个数
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// HPROF ID 与 `CompactId` 的映射，由索引中的对象按需读出的 `Dump` 共用
+    pub fn ids(&self) -> &Arc<IdMap> {
+        &self.ids
+    }
+
+    /// 对象 ID 对应的下标，不在堆转储中的对象为 `None`
+    pub fn index_of(&self, id: Long) -> Option<CompactId> {
+        self.ids.compact(id).filter(|index| (*index as usize) < self.len())
+    }
+
+    pub fn id(&self, index: CompactId) -> Long {
+        self.ids.id(index)
+    }
+
+    /// 子记录在堆转储索引各部分依次相连后的偏移
+    pub fn offset(&self, index: CompactId) -> u64 {
+        self.offsets.get(index as usize)
+    }
+
+    /// 从堆转储索引中读取对象的子记录
+    ///
+    /// 未读入内存的大数组读出时没有元素，对象所在的堆见 `heap`
+    pub fn record(&self, index: CompactId) -> Result<HeapRecord> {
+        index::read_record_at(&self.dir, self.offset(index))
+    }
+
+    /// 对象的类，类对象本身及基本类型数组为 `None`
+    ///
+    /// 类定义缺失时为追加在对象之后的下标，只能用于取其 ID
+    pub fn class_index(&self, index: CompactId) -> Option<CompactId> {
+        match self.classes.get(index as usize) as CompactId {
+            NO_ID => None,
+            class => Some(class),
+        }
+    }
+
+    /// 对象的类对象 ID，类对象本身及基本类型数组为 0
+    pub fn class_id(&self, index: CompactId) -> Long {
+        self.class_index(index).map_or(0, |class| self.ids.id(class))
+    }
+
+    pub fn shallow_size(&self, index: CompactId) -> u64 {
+        self.sizes.get(index as usize)
+    }
+
+    pub fn kind(&self, index: CompactId) -> ObjectKind {
+        ObjectKind::of(self.kinds.get(index as usize) as Byte)
+    }
+
+    pub fn heap(&self, index: CompactId) -> Heap {
+        Heap::from(self.heaps.get(index as usize) as Int)
+    }
+
+    /// 该对象引用的对象
+    pub fn outbound(&self) -> &Graph {
+        &self.outbound
+    }
+
+    /// 引用该对象的对象
+    pub fn inbound(&self) -> &Graph {
+        &self.inbound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::parser::dump::{ArrayData, INT_TYPE};
+    use crate::parser::hprof::read;
+    use crate::parser::progress::NoProgress;
+    use crate::parser::writer::HprofBuilder;
+    use super::*;
+
+    #[test]
+    fn test_object_index() {
+        let data = HprofBuilder::new(4).unwrap()
+            .class_dump(100, 0, &[(8, OBJECT_TYPE)])
+            .instance(201, 100, 202u32.to_be_bytes().to_vec())
+            .instance(202, 100, 201u32.to_be_bytes().to_vec())
+            .object_array(300, 100, vec![201, 0, 999])
+            .primitive_array(400, ArrayData::Int(vec![1, 2]))
+            // 类定义缺失
+            .instance(500, 150, Vec::new())
+            .end_dump()
+            .build().unwrap();
+
+        let dir = std::env::temp_dir().join(format!("jsnap-objects-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("heap.hprof");
+        fs::write(&path, &data).unwrap();
+        let hprof = read(&path, false).unwrap();
+        let dump = hprof.dump().unwrap();
+        let offsets = index::write_dump_index(&dir, dump, 4, &NoProgress).unwrap();
+        ObjectIndex::write(&dir, dump, &offsets, 4).unwrap();
+        let objects = ObjectIndex::open(&dir).unwrap();
+
+        assert_eq!(objects.len(), 6);
+        assert_eq!(objects.index_of(999), None);
+        assert_eq!(objects.index_of(150), None);
+        let orphan = objects.index_of(500).unwrap();
+        assert_eq!(objects.class_id(orphan), 150);
+        assert_eq!(objects.kind(objects.index_of(100).unwrap()), ObjectKind::Class);
+        assert_eq!(objects.kind(orphan), ObjectKind::Instance);
+        assert_eq!(objects.kind(objects.index_of(400).unwrap()), ObjectKind::PrimitiveArray(INT_TYPE));
+        assert_eq!(objects.heap(orphan), Heap::Default);
+        let node = objects.index_of(201).unwrap();
+        assert_eq!(objects.class_id(node), 100);
+        assert_eq!(objects.class_index(node), objects.index_of(100));
+        assert_eq!(objects.class_index(objects.index_of(100).unwrap()), None);
+        assert_eq!(objects.shallow_size(node), 4);
+        let array = objects.index_of(300).unwrap();
+        assert_eq!(objects.kind(array), ObjectKind::ObjectArray);
+        assert_eq!(objects.shallow_size(array), 12);
+        // 数组中指向堆转储之外的元素不计入
+        let ids = |graph: &Graph, index| graph.neighbors(index).map(|i| objects.id(i)).collect::<Vec<_>>();
+        assert_eq!(ids(objects.outbound(), node), [202]);
+        assert_eq!(ids(objects.outbound(), array), [201]);
+        let mut referrers = ids(objects.inbound(), node);
+        referrers.sort();
+        assert_eq!(referrers, [202, 300]);
+        assert_eq!(objects.inbound().degree(array), 0);
+
+        let record = objects.record(array);
+        let _ = fs::remove_dir_all(&dir);
+        assert!(matches!(record.unwrap(), HeapRecord::ObjectArray(array) if *array.id() == 300));
+    }
+
+    #[test]
+    fn test_write_error_reports_path() {
+        let path = std::env::temp_dir().join(format!("jsnap-missing-{}", std::process::id())).join("object.ids");
+        let err = Column::write(&path, &[1u32, 2, 3]).unwrap_err();
+        assert!(matches!(&err, ParseError::Store { path: failed, .. } if *failed == path));
+        assert!(err.to_string().contains(&path.display().to_string()));
+    }
+
+    #[test]
+    fn test_truncated_column() {
+        let path = std::env::temp_dir().join(format!("jsnap-column-{}", std::process::id()));
+        Column::write(&path, &[1u32, 2, 3]).unwrap();
+        let data = fs::read(&path).unwrap();
+        fs::write(&path, &data[..data.len() - 2]).unwrap();
+        let truncated = Column::open(&path).err();
+        // 元素个数被改为溢出的值
+        let mut data = data;
+        data[16..24].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&path, &data).unwrap();
+        let overflow = Column::open(&path).err();
+        let _ = fs::remove_file(&path);
+
+        assert!(matches!(truncated, Some(ParseError::Malformed { .. })));
+        assert!(matches!(overflow, Some(ParseError::Malformed { .. })));
+    }
+}